@@ -0,0 +1,213 @@
+//! Instructions per second of the type-erased `DynCpu` (boxed bus, IO behind
+//! `Rc<RefCell>`) against the monomorphized `Cpu<B, I>` the machines use. Criterion
+//! reports the throughput in elements per second, one element per instruction, so
+//! `Melem/s` reads as MIPS.
+//!
+//! The CRC loop always runs. Space Invaders attract mode needs the ROMs in `./res`, the
+//! exerciser a CP/M program given by `EXERCISER=path/8080exm.com`, with
+//! `EXERCISER_CPU=z80` for ZEXDOC/ZEXALL.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::rc::Rc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput};
+
+use space_invaders_8080::cpu::{IoBus, Variant};
+use space_invaders_8080::device::{Latch, Watchdog};
+use space_invaders_8080::game::invaders::driver::INVADERS;
+use space_invaders_8080::game::invaders::{io_bus, load_roms};
+use space_invaders_8080::machine::cpm::{CpmBios, BIOS_BASE_64K, TPA};
+use space_invaders_8080::machine::{BufferTerminal, Cpm};
+use space_invaders_8080::memory::{AddressBus, FlatRam, SharedRam};
+use space_invaders_8080::{Cpu, DynCpu};
+
+/// Instructions run per iteration
+const INSTRUCTIONS: u64 = 1_000_000;
+/// Cycles between the two interrupts of a Space Invaders frame
+const HALF_FRAME: u32 = 17476;
+
+/// CRC-16/CCITT of 4K of memory, forever, with an OUT per byte
+#[rustfmt::skip]
+const CRC_LOOP: [u8; 49] = [
+    0x21, 0x00, 0x10,       // 0000 LXI H,1000h
+    0x01, 0x00, 0x10,       // 0003 LXI B,1000h
+    0x11, 0xff, 0xff,       // 0006 LXI D,0ffffh
+    0x7e,                   // 0009 MOV A,M
+    0xaa,                   //      XRA D
+    0x57,                   //      MOV D,A
+    0xc5,                   //      PUSH B
+    0x06, 0x08,             //      MVI B,8
+    0x7b,                   // 000f MOV A,E
+    0x87,                   //      ADD A
+    0x5f,                   //      MOV E,A
+    0x7a,                   //      MOV A,D
+    0x8f,                   //      ADC A
+    0x57,                   //      MOV D,A
+    0xd2, 0x20, 0x00,       //      JNC 0020h
+    0xee, 0x10,             //      XRI 10h
+    0x57,                   //      MOV D,A
+    0x7b,                   //      MOV A,E
+    0xee, 0x21,             //      XRI 21h
+    0x5f,                   //      MOV E,A
+    0x00,                   //      NOP
+    0x05,                   // 0020 DCR B
+    0xc2, 0x0f, 0x00,       //      JNZ 000fh
+    0xc1,                   //      POP B
+    0x23,                   //      INX H
+    0x0b,                   //      DCX B
+    0xd3, 0x00,             //      OUT 0
+    0x78,                   //      MOV A,B
+    0xb1,                   //      ORA C
+    0xc2, 0x09, 0x00,       //      JNZ 0009h
+    0xc3, 0x00, 0x00,       //      JMP 0
+];
+
+/// What the benchmark loop needs from either CPU flavour
+trait Machine {
+    fn step(&mut self) -> u8;
+
+    fn interrupt(&mut self, code: u8) -> bool;
+}
+
+impl Machine for DynCpu {
+    fn step(&mut self) -> u8 {
+        self.next()
+    }
+
+    fn interrupt(&mut self, code: u8) -> bool {
+        (**self).interrupt(code)
+    }
+}
+
+impl<B: AddressBus> Machine for (Cpu<B, IoBus>, IoBus) {
+    fn step(&mut self) -> u8 {
+        self.0.step(&mut self.1)
+    }
+
+    fn interrupt(&mut self, code: u8) -> bool {
+        self.0.interrupt(code)
+    }
+}
+
+impl Machine for Cpm<BufferTerminal> {
+    fn step(&mut self) -> u8 {
+        Cpm::step(self)
+    }
+
+    fn interrupt(&mut self, _code: u8) -> bool {
+        false
+    }
+}
+
+/// Run [INSTRUCTIONS], raising RST 1 and RST 2 in turn every half frame like the
+/// Space Invaders board. Programs that keep interrupts off never see them.
+fn run(machine: &mut impl Machine) {
+    let mut cycles: u32 = 0;
+    let mut vblank = false;
+    for _ in 0..INSTRUCTIONS {
+        cycles += u32::from(machine.step());
+        if cycles > HALF_FRAME {
+            cycles = 0;
+            if machine.interrupt(if vblank { 0xd7 } else { 0xcf }) {
+                vblank = !vblank;
+            }
+        }
+    }
+}
+
+/// The same workload on both flavours of the current CPU. Neither is the CPU from before
+/// `Cpu` became generic, so the pair measures the cost of type erasure, not the refactor.
+fn bench_pair(
+    group: &mut BenchmarkGroup<'_, criterion::measurement::WallTime>,
+    mut erased: impl Machine,
+    mut generic: impl Machine,
+) {
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function("DynCpu", |b| b.iter(|| run(&mut erased)));
+    group.bench_function("Cpu<B, I>", |b| b.iter(|| run(&mut generic)));
+}
+
+fn crc_io() -> IoBus {
+    let mut io = IoBus::new();
+    io.attach(Latch::default()).output([0]);
+    io
+}
+
+fn crc_memory() -> FlatRam {
+    let mut memory = FlatRam::new();
+    for (i, byte) in CRC_LOOP.iter().enumerate() {
+        memory.set_mem(i as u16, *byte);
+    }
+    memory
+}
+
+fn bench_crc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crc16");
+    let mut erased = DynCpu::new(Box::new(crc_memory()), 0, Rc::new(RefCell::new(crc_io())));
+    erased.register.sp = 0xff00;
+    let mut generic = (Cpu::new(crc_memory(), 0), crc_io());
+    generic.0.register.sp = 0xff00;
+    bench_pair(&mut group, erased, generic);
+    group.finish();
+}
+
+/// The ports of the board, with the watchdog off as nothing ends the frames
+fn invaders_io() -> IoBus {
+    let mut io = io_bus(&INVADERS.ports);
+    if let Some(watchdog) = io.device_mut::<Watchdog>() {
+        watchdog.set_enabled(false);
+    }
+    io
+}
+
+/// Both sides run the same bus, ports and interrupt schedule, only the CPU flavour differs
+fn bench_invaders(c: &mut Criterion) {
+    // Each side draws into its own video RAM, the game reads it back
+    let video = || Rc::new(RefCell::new(vec![0u8; 7168]));
+    let (Ok((erased, _)), Ok((generic, _))) =
+        (load_roms(&INVADERS, video()), load_roms(&INVADERS, video()))
+    else {
+        eprintln!("Space Invaders ROMs not found in ./res, attract mode skipped");
+        return;
+    };
+    let mut group = c.benchmark_group("invaders_attract");
+    let erased = DynCpu::new(Box::new(erased), 0, Rc::new(RefCell::new(invaders_io())));
+    let generic = (Cpu::new(generic, 0), invaders_io());
+    bench_pair(&mut group, erased, generic);
+    group.finish();
+}
+
+fn bench_exerciser(c: &mut Criterion) {
+    let Ok(path) = env::var("EXERCISER") else {
+        eprintln!("EXERCISER is not set, exerciser skipped");
+        return;
+    };
+    let program = fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let variant = match env::var("EXERCISER_CPU").as_deref() {
+        Ok("z80") => Variant::Z80,
+        _ => Variant::I8080,
+    };
+
+    let mut generic = Cpm::with_variant(BufferTerminal::new(b""), BIOS_BASE_64K, variant);
+    generic.load_com(&program);
+
+    // The same machine by hand, with the BIOS shared through Rc<RefCell>
+    let memory = Rc::new(RefCell::new(vec![0u8; 65536]));
+    let mut bios = CpmBios::new(memory.clone(), BufferTerminal::new(b""), BIOS_BASE_64K);
+    bios.install_shim();
+    memory.borrow_mut()[TPA as usize..TPA as usize + program.len()].copy_from_slice(&program);
+    let sp = bios.ccp_base() - 2;
+    let bios = Rc::new(RefCell::new(bios));
+    let mut erased = DynCpu::with_variant(Box::new(SharedRam::new(memory)), TPA, bios, variant);
+    erased.register.sp = sp;
+
+    let mut group = c.benchmark_group("exerciser");
+    group.sample_size(10);
+    bench_pair(&mut group, erased, generic);
+    group.finish();
+}
+
+criterion_group!(benches, bench_crc, bench_invaders, bench_exerciser);
+criterion_main!(benches);
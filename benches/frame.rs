@@ -0,0 +1,76 @@
+//! Cost of one Space Invaders frame: the emulation alone, on the interpreter and on the
+//! block engine, with the conversion of video RAM to pixels, and the conversion by
+//! itself. The frame benches need the ROMs in `./res`. A real frame lasts 16.7 ms.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use space_invaders_8080::cpu::BlockCpu;
+use space_invaders_8080::device::Watchdog;
+use space_invaders_8080::game::invaders::color::ColorSource;
+use space_invaders_8080::game::invaders::driver::INVADERS;
+use space_invaders_8080::game::invaders::{io_bus, load_roms, Board, Screen, HALF_FRAME_CYCLES};
+use space_invaders_8080::Cpu;
+
+/// Frames run before measuring, past the boot RAM test
+const WARM_UP_FRAMES: u32 = 300;
+
+fn bench_frame(c: &mut Criterion) {
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let Ok((bus, _)) = load_roms(&INVADERS, video.clone()) else {
+        eprintln!("Space Invaders ROMs not found in ./res, frame benches skipped");
+        return;
+    };
+    let (block_bus, _) = load_roms(&INVADERS, Rc::new(RefCell::new(vec![0u8; 7168]))).unwrap();
+    let mut board = Board::new(&INVADERS, bus);
+    board.set_watchdog(false);
+    for _ in 0..WARM_UP_FRAMES {
+        board.run_frame();
+    }
+    let mut screen = Screen::new(INVADERS.overlay, None);
+    let mut blocks = BlockCpu::new(Cpu::new(block_bus, 0));
+    let mut io = io_bus(&INVADERS.ports);
+    io.device_mut::<Watchdog>().unwrap().set_enabled(false);
+    let mut block_frame = || {
+        for rst in [0xcf, 0xd7] {
+            blocks.run(&mut io, HALF_FRAME_CYCLES + 1);
+            blocks.cpu.interrupt(rst);
+        }
+    };
+    for _ in 0..WARM_UP_FRAMES {
+        block_frame();
+    }
+
+    let mut group = c.benchmark_group("frame");
+    group.bench_function("emulation", |b| b.iter(|| board.run_frame()));
+    group.bench_function("emulation_blocks", |b| b.iter(&mut block_frame));
+    group.bench_function("with_rendering", |b| {
+        b.iter(|| {
+            board.run_frame();
+            screen.set_buffer(&video.borrow());
+        })
+    });
+    group.finish();
+}
+
+fn bench_set_buffer(c: &mut Criterion) {
+    // Every other pixel lit, so both branches of the conversion run
+    let video = vec![0x55u8; 7168];
+    let mut mono = Screen::new(INVADERS.overlay, None);
+    let prom = ColorSource::Prom {
+        data: (0..1024).map(|i| i as u8 & 0x07).collect(),
+        background: 0,
+        bank: 0,
+    };
+    let mut color = Screen::new(&[], Some(prom));
+
+    let mut group = c.benchmark_group("set_buffer");
+    group.bench_function("overlay", |b| b.iter(|| mono.set_buffer(&video)));
+    group.bench_function("color_prom", |b| b.iter(|| color.set_buffer(&video)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_frame, bench_set_buffer);
+criterion_main!(benches);
@@ -0,0 +1,108 @@
+//! Instruction throughput per opcode class. Each program repeats a few instructions of
+//! one class over 4K, then jumps back to 0. Criterion's `Melem/s` reads as MIPS.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use space_invaders_8080::cpu::IoBus;
+use space_invaders_8080::device::Latch;
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::Cpu;
+
+/// Instructions run per iteration
+const INSTRUCTIONS: u64 = 100_000;
+/// Size of the unrolled loop
+const BODY_SIZE: u16 = 0x1000;
+/// HL points here for the memory operands
+const DATA: u16 = 0x8000;
+/// Target of the CALL class, a RET
+const SUBROUTINE: u16 = 0x7000;
+
+/// Class name and the instructions repeated
+#[rustfmt::skip]
+const CLASSES: &[(&str, &[u8])] = &[
+    // MOV B,C; MOV C,D; MOV D,E; MOV E,B
+    ("mov", &[0x41, 0x4a, 0x53, 0x58]),
+    // MVI B,12h; MVI A,34h
+    ("mvi", &[0x06, 0x12, 0x3e, 0x34]),
+    // MOV A,M; MOV M,A; INR M; LDA; STA
+    ("memory", &[0x7e, 0x77, 0x34, 0x3a, 0x00, 0x80, 0x32, 0x01, 0x80]),
+    // ADD B; SUB C; ANA D; ORA E; XRA B; CMP C; ADC D; SBB E
+    ("alu", &[0x80, 0x91, 0xa2, 0xb3, 0xa8, 0xb9, 0x8a, 0x9b]),
+    // ADI 1; SBI 2; ANI 0ffh; CPI 10h
+    ("alu_imm", &[0xc6, 0x01, 0xde, 0x02, 0xe6, 0xff, 0xfe, 0x10]),
+    // INR B; DCR C; INX D; DCX B
+    ("inc_dec", &[0x04, 0x0d, 0x13, 0x0b]),
+    // DAD B; DAD D; DAA
+    ("dad_daa", &[0x09, 0x19, 0x27]),
+    // RLC; RRC; RAL; RAR
+    ("rotate", &[0x07, 0x0f, 0x17, 0x1f]),
+    // PUSH B; PUSH PSW; POP PSW; POP B; XTHL
+    ("stack", &[0xc5, 0xf5, 0xf1, 0xc1, 0xe3]),
+    // IN 0; OUT 0
+    ("io", &[0xdb, 0x00, 0xd3, 0x00]),
+];
+
+/// `body` over [BODY_SIZE] bytes then JMP 0, with a RET at [SUBROUTINE]
+fn program(body: impl Fn(u16) -> Vec<u8>) -> FlatRam {
+    let mut memory = FlatRam::new();
+    let mut addr = 0;
+    loop {
+        let bytes = body(addr);
+        if addr + bytes.len() as u16 > BODY_SIZE {
+            break;
+        }
+        for byte in bytes {
+            memory.set_mem(addr, byte);
+            addr += 1;
+        }
+    }
+    memory.set_mem(addr, 0xc3);
+    memory.set_word(addr + 1, 0);
+    memory.set_mem(SUBROUTINE, 0xc9);
+    memory
+}
+
+fn bench_class(c: &mut Criterion, name: &str, memory: FlatRam) {
+    let mut io = IoBus::new();
+    io.attach(Latch::default()).input([0]).output([0]);
+    let mut cpu = Cpu::new(memory, 0);
+    cpu.register.sp = 0xff00;
+    cpu.register.set_hl(DATA);
+
+    let mut group = c.benchmark_group("opcodes");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for _ in 0..INSTRUCTIONS {
+                cpu.step(&mut io);
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_opcodes(c: &mut Criterion) {
+    for (name, body) in CLASSES {
+        bench_class(c, name, program(|_| body.to_vec()));
+    }
+    // JMP to the next instruction; JNZ and JZ with Z clear, so one of them is taken
+    bench_class(
+        c,
+        "jump",
+        program(|a| vec![0xc3, (a + 3) as u8, ((a + 3) >> 8) as u8]),
+    );
+    bench_class(
+        c,
+        "branch",
+        program(|a| vec![0xc2, (a + 3) as u8, ((a + 3) >> 8) as u8, 0xca, 0, 0]),
+    );
+    // CALL a RET
+    bench_class(
+        c,
+        "call_ret",
+        program(|_| vec![0xcd, SUBROUTINE as u8, (SUBROUTINE >> 8) as u8]),
+    );
+}
+
+criterion_group!(benches, bench_opcodes);
+criterion_main!(benches);
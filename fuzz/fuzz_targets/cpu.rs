@@ -0,0 +1,12 @@
+//! [fuzz_one] over the inputs libFuzzer comes up with, see its doc for the input layout.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use space_invaders_8080::game::invaders::fuzz::fuzz_one;
+
+/// About four frames
+const STEPS: u32 = 20_000;
+
+fuzz_target!(|data: &[u8]| fuzz_one(data, STEPS));
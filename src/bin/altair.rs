@@ -0,0 +1,60 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use space_invaders_8080::machine::{Altair, HostTerminal};
+
+fn usage() -> ! {
+    eprintln!("Usage: altair <image.bin|image.hex> [--addr HEX] [--pc HEX] [--switches HEX]");
+    process::exit(1);
+}
+
+fn parse_hex(value: Option<&String>) -> u16 {
+    value
+        .and_then(|v| u16::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+        .unwrap_or_else(|| usage())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut image = None;
+    let mut addr = 0;
+    let mut pc = None;
+    let mut switches = 0;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--addr" => addr = parse_hex(iter.next()),
+            "--pc" => pc = Some(parse_hex(iter.next())),
+            "--switches" => switches = parse_hex(iter.next()) as u8,
+            _ if image.is_none() && !arg.starts_with('-') => image = Some(arg),
+            _ => usage(),
+        }
+    }
+    let image = image.unwrap_or_else(|| usage());
+
+    let mut altair = Altair::new(HostTerminal::new());
+    let data = fs::read(image).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", image, e);
+        process::exit(1);
+    });
+    if image.to_ascii_lowercase().ends_with(".hex") {
+        let text = String::from_utf8_lossy(&data);
+        if let Err(e) = altair.load_hex(&text) {
+            eprintln!("Failed to load {}: {}", image, e);
+            process::exit(1);
+        }
+    } else {
+        altair.load_binary(&data, addr);
+    }
+    altair.set_sense_switches(switches);
+    if let Some(pc) = pc {
+        altair.cpu.register.pc = pc;
+    }
+
+    // Stop once stdin is closed and the program has consumed all of it
+    while !altair.sio().terminal().is_eof() {
+        altair.run(100_000);
+    }
+    altair.run(2_000_000);
+}
@@ -0,0 +1,70 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use space_invaders_8080::cpu::Variant;
+use space_invaders_8080::machine::cpm::{DiskImage, Geometry, BIOS_BASE_64K, DRIVES};
+use space_invaders_8080::machine::{Cpm, HostTerminal};
+
+fn usage() -> ! {
+    eprintln!("Usage: cpm [--cpu 8080|8085|z80] [--bios HEX] <a.dsk> [b.dsk ...]");
+    eprintln!("       cpm [--cpu 8080|8085|z80] --com <program.com>");
+    process::exit(1);
+}
+
+fn fail(what: &str, e: std::io::Error) -> ! {
+    eprintln!("{}: {}", what, e);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut bios_base = BIOS_BASE_64K;
+    let mut com = None;
+    let mut variant = Variant::I8080;
+    let mut disks = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bios" => {
+                bios_base = iter
+                    .next()
+                    .and_then(|v| u16::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--cpu" => {
+                variant = match iter.next().map(|v| v.to_lowercase()).as_deref() {
+                    Some("8080") => Variant::I8080,
+                    Some("8085") => Variant::I8085,
+                    Some("z80") => Variant::Z80,
+                    _ => usage(),
+                }
+            }
+            "--com" => com = Some(iter.next().unwrap_or_else(|| usage())),
+            _ if !arg.starts_with('-') && disks.len() < DRIVES => disks.push(arg),
+            _ => usage(),
+        }
+    }
+
+    let mut cpm = Cpm::with_variant(HostTerminal::new(), bios_base, variant);
+    for (drive, path) in disks.iter().enumerate() {
+        let disk =
+            DiskImage::open(Path::new(path), Geometry::IBM_3740).unwrap_or_else(|e| fail(path, e));
+        cpm.mount(drive, disk);
+    }
+    match com {
+        Some(path) => {
+            let data = fs::read(path).unwrap_or_else(|e| fail(path, e));
+            cpm.load_com(&data);
+        }
+        None if disks.is_empty() => usage(),
+        None => cpm.boot().unwrap_or_else(|e| fail(disks[0], e)),
+    }
+
+    // Stop when the program exits, or once stdin is closed and has been consumed
+    while !cpm.is_exited() && !cpm.bios().terminal().is_eof() {
+        cpm.run(100_000);
+    }
+    cpm.run(2_000_000);
+}
@@ -0,0 +1,417 @@
+use crate::cpu::cpu::OP_CYCLES;
+use crate::cpu::disasm::OP_LENGTHS;
+use crate::cpu::{Cpu, Variant, IO};
+use crate::memory::AddressBus;
+
+/// Size of the pages writes are tracked in
+const PAGE_SIZE: usize = 256;
+const PAGES: usize = 0x10000 / PAGE_SIZE;
+/// Longest block, in instructions
+const MAX_BLOCK: usize = 64;
+
+/// Bus wrapper that notes writes to pages holding cached code. Pages are compared by
+/// [AddressBus::canonical] address, so writes through a mirror count too.
+pub struct CodeWatch<B: AddressBus> {
+    inner: B,
+    code: [bool; PAGES],
+    dirty: [bool; PAGES],
+    any_dirty: bool,
+}
+
+impl<B: AddressBus> CodeWatch<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            code: [false; PAGES],
+            dirty: [false; PAGES],
+            any_dirty: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// The wrapped bus. Writes through it are not seen, so every code page is marked
+    /// dirty.
+    pub fn get_mut(&mut self) -> &mut B {
+        self.dirty = self.code;
+        self.any_dirty = self.code.contains(&true);
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn page(&self, addr: u16) -> usize {
+        self.inner.canonical(addr) as usize / PAGE_SIZE
+    }
+}
+
+impl<B: AddressBus> AddressBus for CodeWatch<B> {
+    fn get_mem(&self, addr: u16) -> u8 {
+        self.inner.get_mem(addr)
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        let page = self.page(addr);
+        if self.code[page] {
+            self.dirty[page] = true;
+            self.any_dirty = true;
+        }
+        self.inner.set_mem(addr, val)
+    }
+
+    fn canonical(&self, addr: u16) -> u16 {
+        self.inner.canonical(addr)
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        self.get_mut().fill_ram(pattern)
+    }
+}
+
+/// One pre-decoded instruction. Registers are numbered like in the opcodes: B C D E H L
+/// - A, and pairs BC DE HL SP.
+#[derive(Clone, Copy)]
+enum Op {
+    /// MOV r,r
+    Mov(u8, u8),
+    /// MOV r,M
+    Load(u8),
+    /// MOV M,r
+    Store(u8),
+    /// MVI r,D8
+    Mvi(u8, u8),
+    /// ADD..CMP on a register, the ALU operation numbered like in the opcodes
+    Alu(u8, u8),
+    /// ADD..CMP M
+    AluM(u8),
+    /// ADI..CPI D8
+    AluImm(u8, u8),
+    Inr(u8),
+    Dcr(u8),
+    Lxi(u8, u16),
+    Inx(u8),
+    Dcx(u8),
+    Dad(u8),
+    Jmp(u16),
+    /// Jcc, with the condition numbered like in the opcodes: NZ Z NC C PO PE P M
+    Jcc(u8, u16),
+    /// Everything else runs through the interpreter
+    Exec,
+}
+
+struct Entry {
+    op: Op,
+    len: u8,
+    cycles: u8,
+}
+
+struct Block {
+    entries: Vec<Entry>,
+}
+
+/// 8080 execution engine that decodes code once into basic blocks of micro-ops and
+/// replays them. Writes to pages holding code, seen by [CodeWatch], drop the blocks
+/// decoded from them. Registers, memory and cycles stay identical to [Cpu::step], which
+/// it falls back to for interrupts, HLT and the other variants.
+///
+/// Code is assumed to be plain memory: reads of it are not repeated.
+pub struct BlockCpu<B: AddressBus, I: IO + ?Sized> {
+    pub cpu: Cpu<CodeWatch<B>, I>,
+    /// By start address
+    blocks: Vec<Option<Block>>,
+    /// Start addresses of the blocks decoded from each canonical page
+    page_blocks: Vec<Vec<u16>>,
+    steps: u64,
+}
+
+impl<B: AddressBus, I: IO + ?Sized> BlockCpu<B, I> {
+    pub fn new(cpu: Cpu<B, I>) -> Self {
+        Self {
+            cpu: cpu.map_bus(CodeWatch::new),
+            blocks: (0..0x10000).map(|_| None).collect(),
+            page_blocks: vec![Vec::new(); PAGES],
+            steps: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> Cpu<B, I> {
+        self.cpu.map_bus(CodeWatch::into_inner)
+    }
+
+    /// [Cpu::step] calls the engine has done so far, one per instruction or interrupt
+    /// acknowledge
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Drop every cached block
+    pub fn invalidate(&mut self) {
+        self.blocks.iter_mut().for_each(|b| *b = None);
+        self.page_blocks.iter_mut().for_each(|p| p.clear());
+        self.cpu.addring.code = [false; PAGES];
+        self.cpu.addring.dirty = [false; PAGES];
+        self.cpu.addring.any_dirty = false;
+    }
+
+    /// Run the block at PC, or a single [Cpu::step] when the interpreter has to handle
+    /// it. Returns the cycles run.
+    pub fn step(&mut self, io: &mut I) -> u32 {
+        self.run_block(io, u32::MAX)
+    }
+
+    /// Run at least `cycles` cycles, stopping after the instruction that reaches them,
+    /// exactly where repeated [Cpu::step] would. Returns the cycles run.
+    pub fn run(&mut self, io: &mut I, cycles: u32) -> u32 {
+        let mut done = 0;
+        while done < cycles {
+            done += self.run_block(io, cycles - done);
+        }
+        done
+    }
+
+    fn run_block(&mut self, io: &mut I, budget: u32) -> u32 {
+        self.flush_dirty();
+        let cpu = &mut self.cpu;
+        if cpu.variant() != Variant::I8080
+            || cpu.ei_delay
+            || cpu.halted
+            || (cpu.inte && cpu.int_request.is_some())
+        {
+            self.steps += 1;
+            return u32::from(cpu.step(io));
+        }
+        let pc = cpu.register.pc;
+        if self.blocks[pc as usize].is_none() {
+            self.compile(pc);
+        }
+        let Some(block) = &self.blocks[pc as usize] else {
+            unreachable!()
+        };
+        let cpu = &mut self.cpu;
+        let mut cycles = 0;
+        for entry in &block.entries {
+            cycles += u32::from(cpu.run_entry(entry, io));
+            self.steps += 1;
+            if cycles >= budget || cpu.addring.any_dirty {
+                break;
+            }
+        }
+        cycles
+    }
+
+    /// Decode the block starting at `pc`
+    fn compile(&mut self, pc: u16) {
+        let bus = &mut self.cpu.addring;
+        let mut entries = Vec::new();
+        let mut addr = pc;
+        loop {
+            let op_code = bus.get_mem(addr);
+            let len = OP_LENGTHS[op_code as usize];
+            // Only the instruction's own bytes are read
+            let operand = |i: u8| {
+                if i < len {
+                    bus.get_mem(addr.wrapping_add(u16::from(i)))
+                } else {
+                    0
+                }
+            };
+            let byte = operand(1);
+            let word = u16::from_le_bytes([byte, operand(2)]);
+            for i in 0..u16::from(len) {
+                let page = bus.page(addr.wrapping_add(i));
+                bus.code[page] = true;
+                let starts = &mut self.page_blocks[page];
+                if starts.last() != Some(&pc) {
+                    starts.push(pc);
+                }
+            }
+            entries.push(Entry {
+                op: decode(op_code, byte, word),
+                len,
+                cycles: OP_CYCLES[op_code as usize],
+            });
+            let next = addr.wrapping_add(u16::from(len));
+            if ends_block(op_code) || entries.len() == MAX_BLOCK || next < addr {
+                break;
+            }
+            addr = next;
+        }
+        self.blocks[pc as usize] = Some(Block { entries });
+    }
+
+    /// Drop the blocks of the pages written since the last call
+    fn flush_dirty(&mut self) {
+        let bus = &mut self.cpu.addring;
+        if !bus.any_dirty {
+            return;
+        }
+        bus.any_dirty = false;
+        for page in 0..PAGES {
+            if !bus.dirty[page] {
+                continue;
+            }
+            bus.dirty[page] = false;
+            bus.code[page] = false;
+            for start in self.page_blocks[page].drain(..) {
+                self.blocks[start as usize] = None;
+            }
+        }
+    }
+}
+
+/// Instructions after which PC is not simply the next address, or interrupts change
+fn ends_block(op_code: u8) -> bool {
+    match op_code {
+        // HLT, EI
+        0x76 | 0xfb => true,
+        // Rcc, Jcc, Ccc, RST
+        0xc0..=0xff if matches!(op_code & 0x07, 0 | 2 | 4 | 7) => true,
+        // JMP, RET, PCHL, CALL and their undocumented aliases
+        0xc3 | 0xcb | 0xc9 | 0xd9 | 0xe9 | 0xcd | 0xdd | 0xed | 0xfd => true,
+        _ => false,
+    }
+}
+
+fn decode(op_code: u8, byte: u8, word: u16) -> Op {
+    let y = (op_code >> 3) & 0x07;
+    let z = op_code & 0x07;
+    let pair = y >> 1;
+    match op_code {
+        0x76 => Op::Exec,
+        0x40..=0x7f if y == 6 => Op::Store(z),
+        0x40..=0x7f if z == 6 => Op::Load(y),
+        0x40..=0x7f => Op::Mov(y, z),
+        0x80..=0xbf if z == 6 => Op::AluM(y),
+        0x80..=0xbf => Op::Alu(y, z),
+        0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee | 0xf6 | 0xfe => Op::AluImm(y, byte),
+        0x04 | 0x0c | 0x14 | 0x1c | 0x24 | 0x2c | 0x3c => Op::Inr(y),
+        0x05 | 0x0d | 0x15 | 0x1d | 0x25 | 0x2d | 0x3d => Op::Dcr(y),
+        0x06 | 0x0e | 0x16 | 0x1e | 0x26 | 0x2e | 0x3e => Op::Mvi(y, byte),
+        0x01 | 0x11 | 0x21 | 0x31 => Op::Lxi(pair, word),
+        0x03 | 0x13 | 0x23 | 0x33 => Op::Inx(pair),
+        0x0b | 0x1b | 0x2b | 0x3b => Op::Dcx(pair),
+        0x09 | 0x19 | 0x29 | 0x39 => Op::Dad(pair),
+        0xc3 => Op::Jmp(word),
+        0xc2 | 0xca | 0xd2 | 0xda | 0xe2 | 0xea | 0xf2 | 0xfa => Op::Jcc(y, word),
+        _ => Op::Exec,
+    }
+}
+
+impl<B: AddressBus, I: IO + ?Sized> Cpu<B, I> {
+    fn run_entry(&mut self, entry: &Entry, io: &mut I) -> u8 {
+        if let Op::Exec = entry.op {
+            return self.execute(io);
+        }
+        self.register.pc = self.register.pc.wrapping_add(u16::from(entry.len));
+        match entry.op {
+            Op::Mov(dst, src) => self.set_reg(dst, self.reg(src)),
+            Op::Load(dst) => {
+                let value = self.addring.get_mem(self.register.get_hl());
+                self.set_reg(dst, value);
+            }
+            Op::Store(src) => self.addring.set_mem(self.register.get_hl(), self.reg(src)),
+            Op::Mvi(dst, value) => self.set_reg(dst, value),
+            Op::Alu(alu, src) => self.alu(alu, self.reg(src)),
+            Op::AluM(alu) => self.alu(alu, self.addring.get_mem(self.register.get_hl())),
+            Op::AluImm(alu, value) => self.alu(alu, value),
+            Op::Inr(r) => {
+                let value = self.inr_add(self.reg(r));
+                self.set_reg(r, value);
+            }
+            Op::Dcr(r) => {
+                let value = self.dcr_sub(self.reg(r));
+                self.set_reg(r, value);
+            }
+            Op::Lxi(pair, value) => self.set_pair(pair, value),
+            Op::Inx(pair) => {
+                let value = self.inx(self.pair(pair));
+                self.set_pair(pair, value);
+            }
+            Op::Dcx(pair) => {
+                let value = self.dcx(self.pair(pair));
+                self.set_pair(pair, value);
+            }
+            Op::Dad(pair) => self.dad_add(self.pair(pair)),
+            Op::Jmp(addr) => self.register.pc = addr,
+            Op::Jcc(condition, addr) => {
+                if self.condition(condition) {
+                    self.register.pc = addr;
+                }
+            }
+            Op::Exec => unreachable!(),
+        }
+        entry.cycles
+    }
+
+    fn reg(&self, r: u8) -> u8 {
+        match r {
+            0 => self.register.b,
+            1 => self.register.c,
+            2 => self.register.d,
+            3 => self.register.e,
+            4 => self.register.h,
+            5 => self.register.l,
+            _ => self.register.a,
+        }
+    }
+
+    fn set_reg(&mut self, r: u8, value: u8) {
+        match r {
+            0 => self.register.b = value,
+            1 => self.register.c = value,
+            2 => self.register.d = value,
+            3 => self.register.e = value,
+            4 => self.register.h = value,
+            5 => self.register.l = value,
+            _ => self.register.a = value,
+        }
+    }
+
+    fn pair(&self, pair: u8) -> u16 {
+        match pair {
+            0 => self.register.get_bc(),
+            1 => self.register.get_de(),
+            2 => self.register.get_hl(),
+            _ => self.register.sp,
+        }
+    }
+
+    fn set_pair(&mut self, pair: u8, value: u16) {
+        match pair {
+            0 => self.register.set_bc(value),
+            1 => self.register.set_de(value),
+            2 => self.register.set_hl(value),
+            _ => self.register.sp = value,
+        }
+    }
+
+    fn alu(&mut self, alu: u8, value: u8) {
+        match alu {
+            0 => self.add(value),
+            1 => self.adc(value),
+            2 => self.sub(value),
+            3 => self.sbb(value),
+            4 => self.ana(value),
+            5 => self.xra(value),
+            6 => self.ora(value),
+            _ => self.cmp(value),
+        }
+    }
+
+    fn condition(&self, condition: u8) -> bool {
+        match condition {
+            0 => !self.register.flag_z,
+            1 => self.register.flag_z,
+            2 => !self.register.flag_cy,
+            3 => self.register.flag_cy,
+            4 => !self.register.flag_p,
+            5 => self.register.flag_p,
+            6 => !self.register.flag_s,
+            _ => self.register.flag_s,
+        }
+    }
+}
@@ -1,80 +1,201 @@
 use std::cell::RefCell;
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 
-use crate::cpu::IO;
+use crate::cpu::i8085::{Pins8085, OP_CYCLES_8085};
 use crate::cpu::register::Register;
+use crate::cpu::z80::Z80State;
+use crate::cpu::IO;
 use crate::memory::address::AddressBus;
 use crate::util::U16Util;
 
-/// Abstraction of Intel 8080
-pub struct Cpu {
+/// Which processor the core behaves as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    I8080,
+    /// RIM/SIM, the RST 5.5/6.5/7.5 and TRAP pins, serial I/O, 8085 timings and the
+    /// undocumented DSUB, ARHL, RDEL, LDHI, LDSI, SHLX, LHLX, JNK/JK and RSTV
+    I8085,
+    /// Zilog Z80: CB/DD/ED/FD prefixes, IX/IY, the shadow registers, N/H and the
+    /// undocumented X/Y flags, NMI and interrupt modes 0/1/2
+    Z80,
+}
+
+/// Abstraction of Intel 8080, generic over its bus so that memory accesses are static
+/// calls. The IO is handed to each [Cpu::step] instead of being owned.
+pub struct Cpu<B: AddressBus = Box<dyn AddressBus>, I: IO + ?Sized = dyn IO> {
     pub register: Register,
-    pub addring: Box<dyn AddressBus>,
-    interrupt: bool,
+    pub addring: B,
+    /// Interrupt enable flip-flop
+    pub(crate) inte: bool,
+    /// EI only enables interrupts after the instruction that follows it
+    pub(crate) ei_delay: bool,
+    pub(crate) halted: bool,
+    /// INT line: the instruction a device will put on the data bus when acknowledged
+    pub(crate) int_request: Option<[u8; 3]>,
+    /// Instruction bytes fetched from the data bus instead of memory, during an
+    /// interrupt acknowledge
+    bus_data: Option<([u8; 3], usize)>,
+    variant: Variant,
+    pub(crate) pins: Pins8085,
+    pub(crate) z80: Z80State,
+    io: PhantomData<fn(&mut I)>,
+}
+
+/// Type-erased [Cpu]: any bus behind a `Box` and an IO shared through `Rc<RefCell>`.
+/// Handy for tests and tools; the machines use a concrete `Cpu<B, I>`.
+pub struct DynCpu {
+    cpu: Cpu,
     io: Rc<RefCell<dyn IO>>,
 }
 
-impl Cpu {
-    ///
-    /// # Arguments
-    /// * `name` - A string slice that holds the name of the person
+impl DynCpu {
     pub fn new(addring: Box<dyn AddressBus>, pc: u16, io: Rc<RefCell<dyn IO>>) -> Self {
+        Self::with_variant(addring, pc, io, Variant::I8080)
+    }
+
+    pub fn with_variant(
+        addring: Box<dyn AddressBus>,
+        pc: u16,
+        io: Rc<RefCell<dyn IO>>,
+        variant: Variant,
+    ) -> Self {
+        let cpu = Cpu::with_variant(addring, pc, variant);
+        Self { cpu, io }
+    }
+
+    /// 下一步指令, see [Cpu::step]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> u8 {
+        self.cpu.step(&mut *self.io.borrow_mut())
+    }
+}
+
+impl Deref for DynCpu {
+    type Target = Cpu;
+
+    fn deref(&self) -> &Cpu {
+        &self.cpu
+    }
+}
+
+impl DerefMut for DynCpu {
+    fn deref_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+}
+
+impl<B: AddressBus, I: IO + ?Sized> Cpu<B, I> {
+    pub fn new(addring: B, pc: u16) -> Self {
+        Self::with_variant(addring, pc, Variant::I8080)
+    }
+
+    pub fn with_variant(addring: B, pc: u16, variant: Variant) -> Self {
         let register = Register {
-            a: 0,
-            b: 0,
-            c: 0,
-            d: 0,
-            e: 0,
-            h: 0,
-            l: 0,
             pc,
-            sp: 0,
-            flag_z: false,
-            flag_s: false,
-            flag_p: false,
-            flag_cy: false,
-            flag_ac: false,
+            ..Default::default()
         };
         Self {
             register,
             addring,
-            interrupt: false,
-            io,
+            inte: false,
+            ei_delay: false,
+            halted: false,
+            int_request: None,
+            bus_data: None,
+            variant,
+            pins: Pins8085::default(),
+            z80: Z80State::default(),
+            io: PhantomData,
+        }
+    }
+
+    /// The same CPU on another bus, e.g. a wrapper around the current one
+    pub(crate) fn map_bus<C: AddressBus>(self, f: impl FnOnce(B) -> C) -> Cpu<C, I> {
+        self.map(f)
+    }
+
+    /// The same CPU on another bus and stepped with another IO
+    pub(crate) fn map<C: AddressBus, J: IO + ?Sized>(self, f: impl FnOnce(B) -> C) -> Cpu<C, J> {
+        Cpu {
+            register: self.register,
+            addring: f(self.addring),
+            inte: self.inte,
+            ei_delay: self.ei_delay,
+            halted: self.halted,
+            int_request: self.int_request,
+            bus_data: self.bus_data,
+            variant: self.variant,
+            pins: self.pins,
+            z80: self.z80,
+            io: PhantomData,
         }
     }
 
-    fn get_next_byte(&mut self) -> u8 {
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub(crate) fn get_next_byte(&mut self) -> u8 {
+        if let Some((bytes, index)) = self.bus_data.as_mut() {
+            // The PC does not move while the interrupting device supplies the instruction
+            let byte = bytes.get(*index).copied().unwrap_or(0);
+            *index += 1;
+            return byte;
+        }
         let byte = self.addring.get_mem(self.register.pc);
-        self.register.pc += 1;
+        self.register.pc = self.register.pc.wrapping_add(1);
         byte
     }
 
-    fn get_next_word(&mut self) -> u16 {
-        let addr = self.register.pc;
-        let word =
-            U16Util::from_le_bytes(self.addring.get_mem(addr), self.addring.get_mem(addr + 1));
-        self.register.pc += 2;
-        word
+    pub(crate) fn get_next_word(&mut self) -> u16 {
+        let low = self.get_next_byte();
+        let high = self.get_next_byte();
+        U16Util::from_le_bytes(low, high)
+    }
+
+    /// V and K of the 8085 for `result = op1 + op2`, subtraction passes `!op2`
+    fn set_vk(&mut self, op1: u8, op2: u8, result: u8) {
+        self.register.flag_v = (op1 ^ result) & (op2 ^ result) & 0x80 != 0;
+        self.register.flag_k = (op1 >> 7) + (op2 >> 7) + (result >> 7) >= 2;
+    }
+
+    /// INX, K is set when the register pair wraps to 0
+    pub(crate) fn inx(&mut self, value: u16) -> u16 {
+        let result = value.wrapping_add(1);
+        self.register.flag_k = result == 0;
+        result
+    }
+
+    /// DCX, K is set when the register pair wraps to 0xffff
+    pub(crate) fn dcx(&mut self, value: u16) -> u16 {
+        let result = value.wrapping_sub(1);
+        self.register.flag_k = result == 0xffff;
+        result
     }
 
     /// OPCODE: INR
-    fn inr_add(&mut self, r: u8) -> u8 {
+    pub(crate) fn inr_add(&mut self, r: u8) -> u8 {
         let new_r = r.wrapping_add(1);
+        self.set_vk(r, 1, new_r);
         self.register.flag_z = new_r == 0;
         self.register.flag_s = (new_r & 0b10000000) != 0;
-        self.register.flag_p = new_r.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_r.count_ones().is_multiple_of(2);
         self.register.flag_ac = (r & 0x0f) + 0x01 > 0x0f;
         new_r
     }
 
     /// OPCODE: DCR
-    fn dcr_sub(&mut self, r: u8) -> u8 {
+    pub(crate) fn dcr_sub(&mut self, r: u8) -> u8 {
         let new_r = r.wrapping_sub(1);
+        self.set_vk(r, 0xff, new_r);
         self.register.flag_z = new_r == 0;
         self.register.flag_s = (new_r & 0b10000000) != 0;
-        self.register.flag_p = new_r.count_ones() % 2 == 0x00;
-        self.register.flag_ac = new_r > 0xf;
+        self.register.flag_p = new_r.count_ones().is_multiple_of(2);
+        // DCR adds 0xff, AC is the carry out of bit 3 of that addition
+        self.register.flag_ac = (r & 0x0f) != 0;
         new_r
     }
 
@@ -99,45 +220,48 @@ impl Cpu {
     }
 
     /// r: maybe BC/
-    fn dad_add(&mut self, r: u16) {
+    pub(crate) fn dad_add(&mut self, r: u16) {
         let old_hl = self.register.get_hl();
         let new_hl = old_hl.wrapping_add(r);
         self.register.set_hl(new_hl);
         self.register.flag_cy = new_hl < old_hl;
     }
 
-    fn add(&mut self, r: u8) {
+    pub(crate) fn add(&mut self, r: u8) {
         let r_a = self.register.a;
         let new_a = r_a.wrapping_add(r);
+        self.set_vk(r_a, r, new_a);
         self.register.a = new_a;
         self.register.flag_z = new_a == 0;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = new_a < r_a;
         self.register.flag_ac = (r_a & 0x0f) + (r & 0x0f) > 0x0f;
     }
 
     /// example :  ADC B        1    Z, S, P, CY, AC    A <- A + B + CY
-    fn adc(&mut self, r: u8) {
+    pub(crate) fn adc(&mut self, r: u8) {
         let c = u8::from(self.register.flag_cy);
         let old_a = self.register.a;
         let old_cy: u8 = u8::from(self.register.flag_cy);
         let new_a = old_a.wrapping_add(r).wrapping_add(old_cy);
+        self.set_vk(old_a, r, new_a);
         self.register.flag_z = new_a == 0;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = u16::from(old_a) + u16::from(r) + u16::from(old_cy) > 0xff;
         self.register.flag_ac = (old_a & 0x0f) + (r & 0x0f) + c > 0x0f;
         self.register.a = new_a;
     }
 
     /// example : SUB B        1    Z, S, P, CY, AC    A <- A - B
-    fn sub(&mut self, r: u8) {
+    pub(crate) fn sub(&mut self, r: u8) {
         let old_a = self.register.a;
         let new_a = old_a.wrapping_sub(r);
+        self.set_vk(old_a, !r, new_a);
         self.register.flag_z = new_a == 0;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = old_a < new_a;
         self.register.flag_ac = (old_a as i8 & 0x0f) - (r as i8 & 0x0f) >= 0x00;
         self.register.a = new_a
@@ -145,14 +269,15 @@ impl Cpu {
 
     ///
     /// example : SBB B        1    Z, S, P, CY, AC    A <- A - B - CY
-    fn sbb(&mut self, r: u8) {
+    pub(crate) fn sbb(&mut self, r: u8) {
         let c = u8::from(self.register.flag_cy);
         let old_a = self.register.a;
         let old_cy = u8::from(self.register.flag_cy);
         let new_a = old_a.wrapping_sub(r).wrapping_sub(old_cy);
+        self.set_vk(old_a, !r, new_a);
         self.register.flag_z = new_a == 0;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = u16::from(old_a) < (u16::from(r) + u16::from(old_cy));
         self.register.flag_ac = (old_a as i8 & 0x0f) - (r as i8 & 0x0f) - (c as i8) >= 0x00;
         self.register.a = new_a;
@@ -160,23 +285,25 @@ impl Cpu {
 
     /// ANA opcode
     /// example : ANA B        1    Z, S, P, CY, AC    A <- A & B
-    fn ana(&mut self, r: u8) {
+    pub(crate) fn ana(&mut self, r: u8) {
         let new_a = self.register.a & r;
         self.register.flag_z = new_a == 0;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = false;
-        self.register.flag_ac = ((self.register.a & r) & 0x08) != 0;
+        // The 8080 sets AC to the OR of bit 3 of the operands, the 8085 always sets it
+        self.register.flag_ac =
+            self.variant == Variant::I8085 || ((self.register.a | r) & 0x08) != 0;
         self.register.a = new_a;
     }
 
     ///
     /// example : XRA B        1    Z, S, P, CY, AC    A <- A ^ B
-    fn xra(&mut self, r: u8) {
+    pub(crate) fn xra(&mut self, r: u8) {
         let new_a = self.register.a ^ r;
         self.register.flag_z = new_a == 0;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = false;
         self.register.flag_ac = false;
         self.register.a = new_a;
@@ -184,11 +311,11 @@ impl Cpu {
 
     ///
     /// example : ORA B        1    Z, S, P, CY, AC    A <- A | B
-    fn ora(&mut self, r: u8) {
+    pub(crate) fn ora(&mut self, r: u8) {
         let new_a = self.register.a | r;
         self.register.flag_z = new_a == 0;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = false;
         self.register.flag_ac = false;
         self.register.a = new_a;
@@ -196,24 +323,32 @@ impl Cpu {
 
     ///
     /// example : CMP B        1    Z, S, P, CY, AC    A - B
-    fn cmp(&mut self, r: u8) {
+    pub(crate) fn cmp(&mut self, r: u8) {
         let old_a = self.register.a;
         let new_a = old_a.wrapping_sub(r);
+        self.set_vk(old_a, !r, new_a);
         self.register.flag_z = new_a == 0x00;
         self.register.flag_s = (new_a & 0b10000000) != 0;
-        self.register.flag_p = new_a.count_ones() % 2 == 0x00;
+        self.register.flag_p = new_a.count_ones().is_multiple_of(2);
         self.register.flag_cy = old_a < new_a;
         self.register.flag_ac = ((old_a as i8 & 0x0f) - (r as i8 & 0x0f)) >= 0x00;
     }
 
     /// Add value to Stack
-    fn stack_add(&mut self, value: u16) {
+    pub(crate) fn stack_add(&mut self, value: u16) {
         self.register.sp = self.register.sp.wrapping_sub(2);
-        self.addring.set_word(self.register.sp, value);
+        self.stack_write(self.register.sp, value);
+    }
+
+    /// Write a word on the stack high byte first, the order of the CPU's write cycles
+    pub(crate) fn stack_write(&mut self, addr: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.addring.set_mem(addr.wrapping_add(1), high);
+        self.addring.set_mem(addr, low);
     }
 
     /// Pop value from Stack
-    fn stack_pop(&mut self) -> u16 {
+    pub(crate) fn stack_pop(&mut self) -> u16 {
         let value = self.addring.get_word(self.register.sp);
         self.register.sp = self.register.sp.wrapping_add(2);
         value
@@ -242,6 +377,14 @@ impl Cpu {
         condition
     }
 
+    /// 根据跳转判断是否做 RET 操作
+    fn condition_ret(&mut self, condition: bool) -> bool {
+        if condition {
+            self.register.pc = self.stack_pop();
+        }
+        condition
+    }
+
     fn call(&mut self) {
         let word = self.get_next_word();
         self.stack_add(self.register.pc);
@@ -253,11 +396,104 @@ impl Cpu {
         self.register.pc = u16::from(opcode & 0x38);
     }
 
-    /// 下一步指令
-    pub fn next(&mut self) -> u8 {
+    /// 下一步指令, with `io` serving IN and OUT. A pending interrupt is acknowledged first
+    /// if INTE is set; while halted without one, each call idles for [HALT_CYCLES].
+    pub fn step(&mut self, io: &mut I) -> u8 {
+        let enabled = self.inte && !self.ei_delay;
+        self.ei_delay = false;
+        let serviced = match self.variant {
+            Variant::I8080 => None,
+            Variant::I8085 => self.service_8085(enabled),
+            Variant::Z80 => self.service_z80(enabled, io),
+        };
+        if let Some(cycles) = serviced {
+            return cycles;
+        }
+        if enabled {
+            if let Some(instruction) = self.int_request.take() {
+                return self.acknowledge(instruction, io);
+            }
+        }
+        if self.halted {
+            return HALT_CYCLES;
+        }
+        self.execute(io)
+    }
+
+    /// Interrupt acknowledge cycle: disable interrupts, leave HLT and run `instruction`
+    pub(crate) fn acknowledge(&mut self, instruction: [u8; 3], io: &mut I) -> u8 {
+        self.inte = false;
+        self.halted = false;
+        self.bus_data = Some((instruction, 0));
+        let cycles = self.execute(io);
+        self.bus_data = None;
+        cycles
+    }
+
+    pub(crate) fn execute(&mut self, io: &mut I) -> u8 {
+        if self.variant == Variant::Z80 {
+            return self.execute_z80(io);
+        }
         let op_code = self.get_next_byte();
-        let mut ex_cycle: bool = true;
+        // A conditional branch was taken
+        let mut ex_cycle = false;
+        let i8085 = self.variant == Variant::I8085;
         match op_code {
+            // DSUB         1    Z, S, P, CY, AC, V HL <- HL - BC            8085
+            0x08 if i8085 => self.dsub(),
+            // ARHL         1    CY                 HL <- HL >> 1, signed    8085
+            0x10 if i8085 => self.arhl(),
+            // RDEL         1    CY, V              DE <- DE << 1 | CY       8085
+            0x18 if i8085 => self.rdel(),
+            // RIM          1                       A <- interrupt mask      8085
+            0x20 if i8085 => self.rim(),
+            // LDHI D8      2                       DE <- HL + byte 2        8085
+            0x28 if i8085 => {
+                let offset = self.get_next_byte();
+                let value = self.register.get_hl().wrapping_add(u16::from(offset));
+                self.register.set_de(value);
+            }
+            // SIM          1                       interrupt mask <- A      8085
+            0x30 if i8085 => self.sim(),
+            // LDSI D8      2                       DE <- SP + byte 2        8085
+            0x38 if i8085 => {
+                let offset = self.get_next_byte();
+                let value = self.register.sp.wrapping_add(u16::from(offset));
+                self.register.set_de(value);
+            }
+            // RSTV         1                       if V, CALL $40           8085
+            0xcb if i8085 => {
+                ex_cycle = self.register.flag_v;
+                if ex_cycle {
+                    self.stack_add(self.register.pc);
+                    self.register.pc = 0x40;
+                }
+            }
+            // SHLX         1                       (DE) <- HL               8085
+            0xd9 if i8085 => {
+                let addr = self.register.get_de();
+                self.addring.set_word(addr, self.register.get_hl());
+            }
+            // JNK adr      3                       if not K, PC <- adr      8085
+            0xdd if i8085 => ex_cycle = self.condition_jmp(!self.register.flag_k),
+            // LHLX         1                       HL <- (DE)               8085
+            0xed if i8085 => {
+                let word = self.addring.get_word(self.register.get_de());
+                self.register.set_hl(word);
+            }
+            // JK adr       3                       if K, PC <- adr          8085
+            0xfd if i8085 => ex_cycle = self.condition_jmp(self.register.flag_k),
+            // POP PSW      1                       also restores V and K    8085
+            0xf1 if i8085 => {
+                let value = self.stack_pop();
+                self.register.a = (value >> 8) as u8;
+                self.set_flags_8085(value as u8);
+            }
+            // PUSH PSW     1                       also saves V and K       8085
+            0xf5 if i8085 => {
+                let psw = (u16::from(self.register.a) << 8) | u16::from(self.flags_8085());
+                self.stack_add(psw);
+            }
             // NOP          1
             0x00 => { /* Nothing */ }
             // LXI B,D16    3                      B <- byte 3, C <- byte 2
@@ -270,7 +506,10 @@ impl Cpu {
                 .addring
                 .set_mem(self.register.get_bc(), self.register.a),
             // INX B        1                      BC <- BC+1
-            0x03 => self.register.set_bc(self.register.get_bc().wrapping_add(1)),
+            0x03 => {
+                let value = self.inx(self.register.get_bc());
+                self.register.set_bc(value);
+            }
             // INR B        1    Z, S, P, AC       B <- B+1
             0x04 => self.register.b = self.inr_add(self.register.b),
             // DCR B        1    Z, S, P, AC       B <- B-1
@@ -292,7 +531,10 @@ impl Cpu {
             // LDAX B       1                      A <- (BC)
             0x0a => self.register.a = self.addring.get_mem(self.register.get_bc()),
             // DCX B        1                      BC = BC-1
-            0x0b => self.register.set_bc(self.register.get_bc().wrapping_sub(1)),
+            0x0b => {
+                let value = self.dcx(self.register.get_bc());
+                self.register.set_bc(value);
+            }
             // INR C        1    Z, S, P, AC       C <- C+1
             0x0c => self.register.c = self.inr_add(self.register.c),
             // DCR C        1    Z, S, P, AC       C <-C-1
@@ -319,7 +561,10 @@ impl Cpu {
                 .addring
                 .set_mem(self.register.get_de(), self.register.a),
             // INX D        1                      DE <- DE + 1
-            0x13 => self.register.set_de(self.register.get_de().wrapping_add(1)),
+            0x13 => {
+                let value = self.inx(self.register.get_de());
+                self.register.set_de(value);
+            }
             // INR D        1    Z, S, P, AC       D <- D+1
             0x14 => self.register.d = self.inr_add(self.register.d),
             // DCR D        1    Z, S, P, AC       D <- D-1
@@ -339,7 +584,10 @@ impl Cpu {
             // LDAX D       1                      A <- (DE)
             0x1a => self.register.a = self.addring.get_mem(self.register.get_de()),
             // DCX D        1                      DE = DE-1
-            0x1b => self.register.set_de(self.register.get_de().wrapping_sub(1)),
+            0x1b => {
+                let value = self.dcx(self.register.get_de());
+                self.register.set_de(value);
+            }
             // INR E        1    Z, S, P, AC       E <-E+1
             0x1c => self.register.e = self.inr_add(self.register.e),
             // DCR E        1    Z, S, P, AC       E <- E-1
@@ -365,7 +613,10 @@ impl Cpu {
                 self.addring.set_word(addr, self.register.get_hl());
             }
             // INX H        1                      HL <- HL + 1
-            0x23 => self.register.set_hl(self.register.get_hl().wrapping_add(1)),
+            0x23 => {
+                let value = self.inx(self.register.get_hl());
+                self.register.set_hl(value);
+            }
             // INR H        1    Z, S, P, AC       H <- H+1
             0x24 => self.register.h = self.inr_add(self.register.h),
             // DCR H        1    Z, S, P, AC       H <- H-1
@@ -387,7 +638,10 @@ impl Cpu {
                 self.register.set_hl(word);
             }
             // DCX H        1                      HL = HL-1
-            0x2b => self.register.set_hl(self.register.get_hl().wrapping_sub(1)),
+            0x2b => {
+                let value = self.dcx(self.register.get_hl());
+                self.register.set_hl(value);
+            }
             // INR L        1    Z, S, P, AC       L <- L+1
             0x2c => self.register.l = self.inr_add(self.register.l),
             // DCR L        1    Z, S, P, AC       L <- L-1
@@ -409,7 +663,7 @@ impl Cpu {
                 self.addring.set_mem(addr, self.register.a)
             }
             // INX SP       1                      SP = SP + 1
-            0x33 => self.register.sp = self.register.sp.wrapping_add(1),
+            0x33 => self.register.sp = self.inx(self.register.sp),
             // INR M        1    Z, S, P, AC       (HL) <- (HL)+1
             0x34 => {
                 let addr = self.register.get_hl();
@@ -439,7 +693,7 @@ impl Cpu {
                 self.register.a = self.addring.get_mem(addr)
             }
             // DCX SP       1                       SP = SP-1
-            0x3b => self.register.sp = self.register.sp.wrapping_sub(1),
+            0x3b => self.register.sp = self.dcx(self.register.sp),
             // INR A        1    Z, S, P, AC        A <- A+1
             0x3c => self.register.a = self.inr_add(self.register.a),
             // DCR A        1    Z, S, P, AC        A <- A-1
@@ -569,7 +823,7 @@ impl Cpu {
                 .addring
                 .set_mem(self.register.get_hl(), self.register.l),
             // HLT          1                       special   HALT INSTRUCTION
-            0x76 => ::std::process::exit(0),
+            0x76 => self.halted = true,
             // MOV M,A      1                       (HL) <- A
             0x77 => self
                 .addring
@@ -719,11 +973,7 @@ impl Cpu {
             // CMP A        1    Z, S, P, CY, AC    A - A
             0xbf => self.cmp(self.register.a),
             // RNZ          1                       if NZ, RET
-            0xc0 => {
-                if !self.register.flag_z {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xc0 => ex_cycle = self.condition_ret(!self.register.flag_z),
             // POP B        1                       C <- (sp); B <- (sp+1); sp <- sp+2
             0xc1 => {
                 let value = self.stack_pop();
@@ -745,20 +995,13 @@ impl Cpu {
             // RST 0        1                       CALL $0
             0xc7 => self.rst(op_code),
             // RZ           1                       if Z, RET
-            0xc8 => {
-                if self.register.flag_z {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xc8 => ex_cycle = self.condition_ret(self.register.flag_z),
             // RET          1                       PC.lo <- (sp); PC.hi<-(sp+1); SP <- SP+2
             0xc9 => self.register.pc = self.stack_pop(),
             // JZ adr       3                       if Z, PC <- adr
             0xca => ex_cycle = self.condition_jmp(self.register.flag_z),
-            // -
-            0xcb => {
-                eprintln!("未实现 {:#04X}", op_code);
-                // TODO
-            }
+            // -            3                       alias of JMP
+            0xcb => self.jmp(),
             // CZ adr       3                       if Z, CALL adr
             0xcc => ex_cycle = self.condition_call(self.register.flag_z),
             // CALL adr     3                       (SP-1)<-PC.hi;(SP-2)<-PC.lo;SP<-SP+2;PC=adr
@@ -772,11 +1015,7 @@ impl Cpu {
             // RST 1        1                       CALL $8
             0xcf => self.rst(op_code),
             // RNC          1                       if NCY, RET
-            0xd0 => {
-                if !self.register.flag_cy {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xd0 => ex_cycle = self.condition_ret(!self.register.flag_cy),
             // POP D        1                       E <- (sp); D <- (sp+1); sp <- sp+2
             0xd1 => {
                 let value = self.stack_pop();
@@ -786,8 +1025,8 @@ impl Cpu {
             0xd2 => ex_cycle = self.condition_jmp(!self.register.flag_cy),
             // OUT D8       2                       special
             0xd3 => {
-                //eprintln!("未完整实现 {:#04X}", op_code);
-                let _byte = self.get_next_byte();
+                let byte = self.get_next_byte();
+                io.output(&mut self.register, byte);
             }
             // CNC adr      3                       if NCY, CALL adr
             0xd4 => ex_cycle = self.condition_call(!self.register.flag_cy),
@@ -801,11 +1040,7 @@ impl Cpu {
             // RST 2        1                       CALL $10
             0xd7 => self.rst(op_code),
             // RC           1                       if CY, RET
-            0xd8 => {
-                if self.register.flag_cy {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xd8 => ex_cycle = self.condition_ret(self.register.flag_cy),
             // - 0xC9
             0xd9 => self.register.pc = self.stack_pop(),
             // JC adr       3                       if CY, PC<-adr
@@ -813,8 +1048,7 @@ impl Cpu {
             // IN D8        2                       special
             0xdb => {
                 let byte = self.get_next_byte();
-                let ref_mut = &mut self.io;
-                ref_mut.borrow_mut().input(&mut self.register, byte);
+                io.input(&mut self.register, byte);
             }
             // CC adr       3                       if CY, CALL adr
             0xdc => ex_cycle = self.condition_call(self.register.flag_cy),
@@ -828,11 +1062,7 @@ impl Cpu {
             // RST 3        1                       CALL $18
             0xdf => self.rst(op_code),
             // RPO          1                       if PO, RET
-            0xe0 => {
-                if !self.register.flag_p {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xe0 => ex_cycle = self.condition_ret(!self.register.flag_p),
             // POP H        1                       L <- (sp); H <- (sp+1); sp <- sp+2
             0xe1 => {
                 let value = self.stack_pop();
@@ -845,7 +1075,7 @@ impl Cpu {
                 let addr = self.addring.get_word(self.register.sp);
                 let hl = self.register.get_hl();
                 self.register.set_hl(addr);
-                self.addring.set_word(self.register.sp, hl);
+                self.stack_write(self.register.sp, hl);
             }
             // CPO adr      3                       if PO, CALL adr
             0xe4 => ex_cycle = self.condition_call(!self.register.flag_p),
@@ -859,11 +1089,7 @@ impl Cpu {
             // RST 4        1                       CALL $20
             0xe7 => self.rst(op_code),
             // RPE          1                       if PE, RET
-            0xe8 => {
-                if self.register.flag_p {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xe8 => ex_cycle = self.condition_ret(self.register.flag_p),
             // PCHL         1                       PC.hi <- H; PC.lo <- L
             0xe9 => self.register.pc = self.register.get_hl(),
             // JPE adr      3                       if PE, PC <- adr
@@ -885,11 +1111,7 @@ impl Cpu {
             // RST 5        1                       CALL $28
             0xef => self.rst(op_code),
             // RP           1                       if P, RET
-            0xf0 => {
-                if !self.register.flag_s {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xf0 => ex_cycle = self.condition_ret(!self.register.flag_s),
             // POP PSW      1                       flags <- (sp); A <- (sp+1); sp <- sp+2
             0xf1 => {
                 let value = self.stack_pop();
@@ -897,9 +1119,12 @@ impl Cpu {
                 self.register.set_flags((value & 0x00d5 | 0x0002) as u8);
             }
             // JP adr       3                       if P=1 PC <- adr
-            0xf2 => ex_cycle = self.condition_jmp(self.register.flag_s),
+            0xf2 => ex_cycle = self.condition_jmp(!self.register.flag_s),
             // DI           1                       special
-            0xf3 => self.interrupt = false,
+            0xf3 => {
+                self.inte = false;
+                self.ei_delay = false;
+            }
             // CP adr       3                       if P, PC <- adr    Call if  Plus
             0xf4 => ex_cycle = self.condition_call(!self.register.flag_s),
             // PUSH PSW     1                       (sp-2)<-flags; (sp-1)<-A; sp <- sp - 2
@@ -916,17 +1141,16 @@ impl Cpu {
             // RST 6        1                       CALL $30
             0xf7 => self.rst(op_code),
             // RM           1                       if M, RET
-            0xf8 => {
-                if self.register.flag_s {
-                    self.register.pc = self.stack_pop();
-                }
-            }
+            0xf8 => ex_cycle = self.condition_ret(self.register.flag_s),
             // SPHL         1                       SP=HL
             0xf9 => self.register.sp = self.register.get_hl(),
             // JM adr       3                       if M, PC <- adr
             0xfa => ex_cycle = self.condition_jmp(self.register.flag_s),
             // EI           1                       special
-            0xfb => self.interrupt = true,
+            0xfb => {
+                self.inte = true;
+                self.ei_delay = true;
+            }
             // CM adr       3                       if M, CALL adr   Call If Minus
             0xfc => ex_cycle = self.condition_call(self.register.flag_s),
             // -
@@ -938,42 +1162,123 @@ impl Cpu {
             }
             // RST 7        1                       CALL $38
             0xff => self.rst(op_code),
-            n => println!("unknow opcode 0x{:X}", n),
         };
-        return if ex_cycle {
-            OP_CYCLES[op_code as usize] + 6
-        } else {
-            OP_CYCLES[op_code as usize]
+        let cycles = match self.variant {
+            Variant::I8085 => OP_CYCLES_8085[op_code as usize],
+            _ => OP_CYCLES[op_code as usize],
         };
+        if ex_cycle {
+            cycles + self.branch_cycles(op_code)
+        } else {
+            cycles
+        }
+    }
+
+    /// Extra cycles of a taken conditional CALL, RET or jump
+    fn branch_cycles(&self, op_code: u8) -> u8 {
+        match (self.variant, op_code & 0xc7) {
+            // Ccc
+            (Variant::I8080, 0xc4) => 6,
+            (Variant::I8085, 0xc4) => 9,
+            // Rcc, and RSTV on the 8085
+            (_, 0xc0) | (Variant::I8085, 0xc3) => 6,
+            // Jcc, JNK and JK on the 8085
+            (Variant::I8085, 0xc2) | (Variant::I8085, 0xc5) => 3,
+            _ => 0,
+        }
+    }
+
+    /// RESET line. Per the 8080 datasheet only the program counter and the interrupt
+    /// enable flip-flop are cleared; the other registers and the flags keep their contents.
+    pub fn reset(&mut self) {
+        self.register.pc = 0;
+        self.inte = false;
+        self.ei_delay = false;
+        self.halted = false;
+        self.pins.reset();
+        self.z80.reset();
+        self.register.i = 0;
+        self.register.r = 0;
+    }
+
+    /// Power on: the datasheet leaves the registers undefined, we clear them
+    pub fn power_on(&mut self) {
+        self.register = Register::default();
+        self.reset();
     }
 
+    /// Whether the interrupt enable flip-flop (INTE) is set
+    pub fn interrupts_enabled(&self) -> bool {
+        self.inte
+    }
+
+    /// Set INTE directly, as after an EI whose delay has passed or a DI. For loading a
+    /// saved or test state.
+    pub fn set_interrupts_enabled(&mut self, enabled: bool) {
+        self.inte = enabled;
+        self.ei_delay = false;
+    }
+
+    /// Stopped by HLT, waiting for an interrupt or a reset
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Raise the INT line. `instruction` is what the device puts on the data bus in the
+    /// acknowledge cycle: usually RST n, or a 3-byte CALL. The request stays pending
+    /// until interrupts are enabled, a newer request replaces it.
+    pub fn request_interrupt(&mut self, instruction: &[u8]) {
+        assert!(
+            (1..=3).contains(&instruction.len()),
+            "an 8080 instruction is 1 to 3 bytes"
+        );
+        let mut bytes = [0u8; 3];
+        bytes[..instruction.len()].copy_from_slice(instruction);
+        self.int_request = Some(bytes);
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.int_request.is_some()
+    }
+
+    /// Drop the INT line without an acknowledge
+    pub fn clear_interrupt(&mut self) {
+        self.int_request = None;
+    }
+
+    /// Run RST `code` right away if interrupts are enabled, returns whether it was taken.
+    /// `code` is the RST opcode or just its vector, 0x08 and 0xcf both mean RST 1.
     pub fn interrupt(&mut self, code: u8) -> bool {
-        return if self.interrupt {
-            self.rst(code);
-            self.interrupt = false;
-            true
-        } else {
-            false
-        };
+        if !self.inte || self.ei_delay {
+            return false;
+        }
+        self.inte = false;
+        self.halted = false;
+        self.rst(code);
+        true
     }
 }
 
+/// Cycles counted for each step spent halted
+pub const HALT_CYCLES: u8 = 4;
+
 //  0   1   2   3   4   5   6   7   8   9   a   b   c   d   e   f
-const OP_CYCLES: [u8; 256] = [
-    04, 10, 07, 05, 05, 05, 07, 04, 04, 10, 07, 05, 05, 05, 07, 04, // 0
-    04, 10, 07, 05, 05, 05, 07, 04, 04, 10, 07, 05, 05, 05, 07, 04, // 1
-    04, 10, 16, 05, 05, 05, 07, 04, 04, 10, 16, 05, 05, 05, 07, 04, // 2
-    04, 10, 13, 05, 10, 10, 10, 04, 04, 10, 13, 05, 05, 05, 07, 04, // 3
-    05, 05, 05, 05, 05, 05, 07, 05, 05, 05, 05, 05, 05, 05, 07, 05, // 4
-    05, 05, 05, 05, 05, 05, 07, 05, 05, 05, 05, 05, 05, 05, 07, 05, // 5
-    05, 05, 05, 05, 05, 05, 07, 05, 05, 05, 05, 05, 05, 05, 07, 05, // 6
-    07, 07, 07, 07, 07, 07, 07, 07, 05, 05, 05, 05, 05, 05, 07, 05, // 7
-    04, 04, 04, 04, 04, 04, 07, 04, 04, 04, 04, 04, 04, 04, 07, 04, // 8
-    04, 04, 04, 04, 04, 04, 07, 04, 04, 04, 04, 04, 04, 04, 07, 04, // 9
-    04, 04, 04, 04, 04, 04, 07, 04, 04, 04, 04, 04, 04, 04, 07, 04, // a
-    04, 04, 04, 04, 04, 04, 07, 04, 04, 04, 04, 04, 04, 04, 07, 04, // b
-    05, 10, 10, 10, 11, 11, 07, 11, 05, 10, 10, 10, 11, 17, 07, 11, // c
-    05, 10, 10, 10, 11, 11, 07, 11, 05, 10, 10, 10, 11, 17, 07, 11, // d
-    05, 10, 10, 18, 11, 11, 07, 11, 05, 05, 10, 05, 11, 17, 07, 11, // e
-    05, 10, 10, 04, 11, 11, 07, 11, 05, 05, 10, 04, 11, 17, 07, 11, // f
+#[rustfmt::skip]
+pub(crate) const OP_CYCLES: [u8; 256] = [
+     4, 10,  7,  5,  5,  5,  7,  4,  4, 10,  7,  5,  5,  5,  7,  4, // 0
+     4, 10,  7,  5,  5,  5,  7,  4,  4, 10,  7,  5,  5,  5,  7,  4, // 1
+     4, 10, 16,  5,  5,  5,  7,  4,  4, 10, 16,  5,  5,  5,  7,  4, // 2
+     4, 10, 13,  5, 10, 10, 10,  4,  4, 10, 13,  5,  5,  5,  7,  4, // 3
+     5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 4
+     5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 5
+     5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5, // 6
+     7,  7,  7,  7,  7,  7,  7,  7,  5,  5,  5,  5,  5,  5,  7,  5, // 7
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 8
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 9
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // a
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // b
+     5, 10, 10, 10, 11, 11,  7, 11,  5, 10, 10, 10, 11, 17,  7, 11, // c
+     5, 10, 10, 10, 11, 11,  7, 11,  5, 10, 10, 10, 11, 17,  7, 11, // d
+     5, 10, 10, 18, 11, 11,  7, 11,  5,  5, 10,  4, 11, 17,  7, 11, // e
+     5, 10, 10,  4, 11, 11,  7, 11,  5,  5, 10,  4, 11, 17,  7, 11, // f
 ];
@@ -0,0 +1,242 @@
+//! 8080 disassembler, Intel mnemonics. The undocumented aliases are marked with `*`.
+
+use std::fmt;
+
+use crate::cpu::symbols::{Symbol, SymbolKind, SymbolTable};
+use crate::memory::AddressBus;
+
+/// Bytes taken by each 8080 opcode, operands included
+//  0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
+#[rustfmt::skip]
+pub(crate) const OP_LENGTHS: [u8; 256] = [
+    1, 3, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 0
+    1, 3, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, // 1
+    1, 3, 3, 1, 1, 1, 2, 1, 1, 1, 3, 1, 1, 1, 2, 1, // 2
+    1, 3, 3, 1, 1, 1, 2, 1, 1, 1, 3, 1, 1, 1, 2, 1, // 3
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 4
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 5
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 6
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 7
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 8
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // 9
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // a
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, // b
+    1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 3, 3, 3, 2, 1, // c
+    1, 1, 3, 2, 3, 1, 2, 1, 1, 1, 3, 2, 3, 3, 2, 1, // d
+    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, // e
+    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, // f
+];
+
+const REGS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+const PAIRS: [&str; 4] = ["B", "D", "H", "SP"];
+const CONDITIONS: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU: [&str; 8] = ["ADD", "ADC", "SUB", "SBB", "ANA", "XRA", "ORA", "CMP"];
+const ALU_IMM: [&str; 8] = ["ADI", "ACI", "SUI", "SBI", "ANI", "XRI", "ORI", "CPI"];
+const ROTATES: [&str; 8] = ["RLC", "RRC", "RAL", "RAR", "DAA", "CMA", "STC", "CMC"];
+
+/// One decoded instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    /// Address operand of a jump, call or memory access, for labels
+    pub target: Option<u16>,
+}
+
+impl Instruction {
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    /// The text with the address operand replaced by its symbol: `LDA isrDelay`
+    pub fn labelled(&self, symbols: &SymbolTable) -> String {
+        match self.target.and_then(|t| Some((t, symbols.name(t)?))) {
+            Some((target, name)) => self.text.replace(&format!("${:04x}", target), name),
+            None => self.text.clone(),
+        }
+    }
+}
+
+/// `1a5c  3a 67 20  LDA $2067`
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(f, "{:04x}  {:<9} {}", self.addr, bytes.join(" "), self.text)
+    }
+}
+
+/// One line of a listing
+pub(crate) enum Entry<'a> {
+    Label(&'a Symbol),
+    /// With its operand labelled
+    Code(Instruction),
+    Data {
+        addr: u16,
+        bytes: Vec<u8>,
+    },
+}
+
+impl fmt::Display for Entry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Label(symbol) => match &symbol.comment {
+                Some(comment) => write!(f, "{}:  ; {}", symbol.name, comment),
+                None => write!(f, "{}:", symbol.name),
+            },
+            Entry::Code(instruction) => write!(f, "{}", instruction),
+            Entry::Data { addr, bytes } => {
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                let db: Vec<String> = bytes.iter().map(|b| format!("${:02x}", b)).collect();
+                write!(f, "{:04x}  {:<9} DB {}", addr, hex.join(" "), db.join(","))
+            }
+        }
+    }
+}
+
+/// Split `start..end` into labels, instructions and data. Bytes from a data symbol on are data,
+/// up to the next symbol or `executed` address. Decoding restarts at every symbol and executed
+/// address, and the bytes skipped to get there are data too.
+pub(crate) fn entries<'a>(
+    bus: &(impl AddressBus + ?Sized),
+    start: u16,
+    end: u16,
+    symbols: &'a SymbolTable,
+    executed: impl Fn(u16) -> bool,
+) -> Vec<Entry<'a>> {
+    // The first address of `from + 1..to` something starts at, or `to`
+    let boundary = |from: u16, to: u16| {
+        (from + 1..to)
+            .find(|&a| symbols.get(a).is_some() || executed(a))
+            .unwrap_or(to)
+    };
+    let data = |addr: u16, stop: u16| Entry::Data {
+        addr,
+        bytes: (addr..stop).map(|a| bus.get_mem(a)).collect(),
+    };
+    let mut out = Vec::new();
+    let mut in_data = false;
+    let mut addr = start;
+    while addr < end {
+        if let Some(symbol) = symbols.get(addr) {
+            out.push(Entry::Label(symbol));
+            in_data = symbol.kind == SymbolKind::Data;
+        }
+        if executed(addr) {
+            in_data = false;
+        }
+        if in_data {
+            let stop = boundary(addr, end.min(addr.saturating_add(3)));
+            out.push(data(addr, stop));
+            addr = stop;
+            continue;
+        }
+        let mut instruction = disassemble(bus, addr);
+        let len = instruction.size().min(end - addr);
+        let stop = boundary(addr, addr + len);
+        if stop < addr + len && !executed(addr) {
+            out.push(data(addr, stop));
+            addr = stop;
+            continue;
+        }
+        instruction.text = instruction.labelled(symbols);
+        out.push(Entry::Code(instruction));
+        addr += len;
+    }
+    out
+}
+
+/// Listing of `start..end` with the labels and data areas of `symbols`
+pub fn listing(
+    bus: &(impl AddressBus + ?Sized),
+    start: u16,
+    end: u16,
+    symbols: &SymbolTable,
+) -> String {
+    entries(bus, start, end, symbols, |_| false)
+        .iter()
+        .map(|entry| format!("{}\n", entry))
+        .collect()
+}
+
+/// Decode the instruction at `addr`
+pub fn disassemble(bus: &(impl AddressBus + ?Sized), addr: u16) -> Instruction {
+    let op = bus.get_mem(addr);
+    let len = OP_LENGTHS[op as usize];
+    let bytes: Vec<u8> = (0..u16::from(len))
+        .map(|i| bus.get_mem(addr.wrapping_add(i)))
+        .collect();
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+    let (text, target) = mnemonic(op, byte, word);
+    Instruction {
+        addr,
+        bytes,
+        text,
+        target,
+    }
+}
+
+/// Text of `op` with its operands, and the address operand if any
+fn mnemonic(op: u8, byte: u8, word: u16) -> (String, Option<u16>) {
+    let (y, z) = (usize::from(op >> 3 & 7), usize::from(op & 7));
+    let (p, q) = (y >> 1, y & 1);
+    let plain = |text: &str| (text.to_string(), None);
+    let with_addr = |text: &str| (format!("{} ${:04x}", text, word), Some(word));
+    match op {
+        0x76 => plain("HLT"),
+        0x40..=0x7f => plain(&format!("MOV {},{}", REGS[y], REGS[z])),
+        0x80..=0xbf => plain(&format!("{} {}", ALU[y], REGS[z])),
+        0x00..=0x3f => match (z, q) {
+            (0, _) if op == 0x00 => plain("NOP"),
+            (0, _) => plain("*NOP"),
+            (1, 0) => (format!("LXI {},${:04x}", PAIRS[p], word), Some(word)),
+            (1, _) => plain(&format!("DAD {}", PAIRS[p])),
+            (2, _) => match op {
+                0x02 | 0x12 => plain(&format!("STAX {}", PAIRS[p])),
+                0x0a | 0x1a => plain(&format!("LDAX {}", PAIRS[p])),
+                0x22 => with_addr("SHLD"),
+                0x2a => with_addr("LHLD"),
+                0x32 => with_addr("STA"),
+                _ => with_addr("LDA"),
+            },
+            (3, 0) => plain(&format!("INX {}", PAIRS[p])),
+            (3, _) => plain(&format!("DCX {}", PAIRS[p])),
+            (4, _) => plain(&format!("INR {}", REGS[y])),
+            (5, _) => plain(&format!("DCR {}", REGS[y])),
+            (6, _) => plain(&format!("MVI {},${:02x}", REGS[y], byte)),
+            _ => plain(ROTATES[y]),
+        },
+        _ => match z {
+            0 => plain(&format!("R{}", CONDITIONS[y])),
+            1 => match op {
+                0xc9 => plain("RET"),
+                0xd9 => plain("*RET"),
+                0xe9 => plain("PCHL"),
+                0xf9 => plain("SPHL"),
+                0xf1 => plain("POP PSW"),
+                _ => plain(&format!("POP {}", PAIRS[p])),
+            },
+            2 => with_addr(&format!("J{}", CONDITIONS[y])),
+            3 => match op {
+                0xc3 => with_addr("JMP"),
+                0xcb => with_addr("*JMP"),
+                0xd3 => plain(&format!("OUT ${:02x}", byte)),
+                0xdb => plain(&format!("IN ${:02x}", byte)),
+                0xe3 => plain("XTHL"),
+                0xeb => plain("XCHG"),
+                0xf3 => plain("DI"),
+                _ => plain("EI"),
+            },
+            4 => with_addr(&format!("C{}", CONDITIONS[y])),
+            5 => match op {
+                0xcd => with_addr("CALL"),
+                0xdd | 0xed | 0xfd => with_addr("*CALL"),
+                0xf5 => plain("PUSH PSW"),
+                _ => plain(&format!("PUSH {}", PAIRS[p])),
+            },
+            6 => plain(&format!("{} ${:02x}", ALU_IMM[y], byte)),
+            _ => (format!("RST {}", y), Some(op as u16 & 0x38)),
+        },
+    }
+}
@@ -0,0 +1,621 @@
+//! GDB remote serial protocol stub: gdb, or any front-end speaking the protocol, debugs the
+//! emulated 8080 over TCP.
+//!
+//! Registers follow [TARGET_XML]: A, F, B, C, D, E, H, L as bytes, then SP and PC little
+//! endian. Breakpoints are kept by the stub and checked before each instruction, so they work
+//! in ROM too. Watchpoints look at the reads and writes an [AccessLog] bus recorded while the
+//! target stepped, interrupt pushes included, and stop after the instruction.
+//!
+//! With symbols set, `monitor where` names the routine at PC and `monitor sym NAME` gives
+//! the address of a symbol.
+
+use std::cell::RefCell;
+
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::cpu::observer::Access;
+use crate::cpu::symbols::SymbolTable;
+use crate::cpu::{Cpu, Register, IO};
+use crate::memory::AddressBus;
+
+/// Target description sent to gdb through `qXfer:features:read`
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.i8080.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="f" bitsize="8" type="uint8"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="e" bitsize="8" type="uint8"/>
+    <reg name="h" bitsize="8" type="uint8"/>
+    <reg name="l" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// Size of the `g` packet
+const REGISTER_BYTES: usize = 12;
+
+/// Instructions run between two checks for a break from gdb
+const POLL_STEPS: u32 = 0x4000;
+
+/// A machine the stub can drive
+pub trait Target {
+    fn registers(&self) -> &Register;
+    fn registers_mut(&mut self) -> &mut Register;
+    fn bus(&self) -> &dyn AddressBus;
+    fn bus_mut(&mut self) -> &mut dyn AddressBus;
+    /// Run one instruction, with whatever the machine does around it such as interrupts.
+    /// Returns its cycles.
+    fn step(&mut self) -> u8;
+    /// The memory accesses recorded since it was last cleared, oldest first
+    fn access_log(&mut self) -> &mut Vec<(Access, u16)>;
+}
+
+/// Bus wrapper that records the address of every read and write, for watchpoints
+pub struct AccessLog<B: AddressBus> {
+    inner: B,
+    accesses: RefCell<Vec<(Access, u16)>>,
+}
+
+impl<B: AddressBus> AccessLog<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            accesses: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// The wrapped bus, accesses through it are not recorded
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// The accesses so far, [Access::Read] or [Access::Write] with their address
+    pub fn accesses(&mut self) -> &mut Vec<(Access, u16)> {
+        self.accesses.get_mut()
+    }
+}
+
+impl<B: AddressBus> AddressBus for AccessLog<B> {
+    fn get_mem(&self, addr: u16) -> u8 {
+        self.accesses.borrow_mut().push((Access::Read, addr));
+        self.inner.get_mem(addr)
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        self.accesses.get_mut().push((Access::Write, addr));
+        self.inner.set_mem(addr, val)
+    }
+
+    fn canonical(&self, addr: u16) -> u16 {
+        self.inner.canonical(addr)
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        self.inner.fill_ram(pattern)
+    }
+}
+
+/// A bare CPU and its IO as a [Target]
+pub struct CpuTarget<B: AddressBus, I: IO> {
+    pub cpu: Cpu<AccessLog<B>, I>,
+    pub io: I,
+}
+
+impl<B: AddressBus, I: IO> CpuTarget<B, I> {
+    pub fn new(cpu: Cpu<B, I>, io: I) -> Self {
+        Self {
+            cpu: cpu.map_bus(AccessLog::new),
+            io,
+        }
+    }
+}
+
+impl<B: AddressBus, I: IO> Target for CpuTarget<B, I> {
+    fn registers(&self) -> &Register {
+        &self.cpu.register
+    }
+
+    fn registers_mut(&mut self) -> &mut Register {
+        &mut self.cpu.register
+    }
+
+    fn bus(&self) -> &dyn AddressBus {
+        &self.cpu.addring
+    }
+
+    fn bus_mut(&mut self) -> &mut dyn AddressBus {
+        &mut self.cpu.addring
+    }
+
+    fn step(&mut self) -> u8 {
+        self.cpu.step(&mut self.io)
+    }
+
+    fn access_log(&mut self) -> &mut Vec<(Access, u16)> {
+        self.cpu.addring.accesses()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+struct Watchpoint {
+    kind: WatchKind,
+    addr: u16,
+    len: u16,
+}
+
+/// Why the target stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Step,
+    Breakpoint,
+    Watch(WatchKind, u16),
+    /// gdb sent a break (Ctrl-C)
+    Interrupted,
+}
+
+pub struct GdbStub<T: Target> {
+    pub target: T,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    last_stop: Stop,
+    no_ack: bool,
+    /// gdb understands `swbreak` in stop replies
+    swbreak: bool,
+    /// For the monitor commands
+    symbols: SymbolTable,
+}
+
+impl<T: Target> GdbStub<T> {
+    pub fn new(target: T) -> Self {
+        Self {
+            target,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            last_stop: Stop::Interrupted,
+            no_ack: false,
+            swbreak: false,
+            symbols: SymbolTable::new(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Wait for one gdb connection on `addr` and serve it until it detaches
+    pub fn listen(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    /// Serve a connected gdb until it detaches, kills the target or hangs up. Breakpoints
+    /// and watchpoints are cleared for the next session.
+    pub fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        self.no_ack = false;
+        self.swbreak = false;
+        let result = self.session(&mut stream);
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        match result {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(()),
+            result => result,
+        }
+    }
+
+    fn session(&mut self, stream: &mut TcpStream) -> io::Result<()> {
+        loop {
+            let packet = self.receive(stream)?;
+            match packet.as_str() {
+                "D" => return self.send(stream, "OK"),
+                "k" => return Ok(()),
+                _ => {}
+            }
+            let reply = match packet.as_bytes().first() {
+                Some(b'c') | Some(b's') => {
+                    let single = packet.starts_with('s');
+                    match resume_addr(&packet[1..]) {
+                        Some(addr) => {
+                            if let Some(addr) = addr {
+                                self.target.registers_mut().pc = addr;
+                            }
+                            self.last_stop = self.resume(stream, single)?;
+                            self.stop_reply()
+                        }
+                        None => "E01".to_string(),
+                    }
+                }
+                _ => self.handle(&packet),
+            };
+            self.send(stream, &reply)?;
+            if packet == "QStartNoAckMode" {
+                self.no_ack = true;
+            }
+        }
+    }
+
+    /// Reply to a packet that does not run the target
+    fn handle(&mut self, packet: &str) -> String {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => Some(self.stop_reply()),
+            Some(b'g') => Some(hex(&self.read_registers())),
+            Some(b'G') => unhex(&packet[1..]).and_then(|bytes| self.write_registers(&bytes)),
+            Some(b'p') => {
+                let n = usize::from_str_radix(&packet[1..], 16).ok();
+                n.and_then(register_range)
+                    .map(|range| hex(&self.read_registers()[range]))
+            }
+            Some(b'P') => packet[1..].split_once('=').and_then(|(n, value)| {
+                let range = register_range(usize::from_str_radix(n, 16).ok()?)?;
+                let value = unhex(value).filter(|v| v.len() == range.len())?;
+                let mut registers = self.read_registers();
+                registers[range].copy_from_slice(&value);
+                self.write_registers(&registers)
+            }),
+            Some(b'm') => parse_range(&packet[1..]).map(|(addr, len)| {
+                let bus = self.target.bus();
+                let bytes: Vec<u8> = (0..len)
+                    .map(|i| bus.get_mem(addr.wrapping_add(i)))
+                    .collect();
+                hex(&bytes)
+            }),
+            Some(b'M') => packet[1..].split_once(':').and_then(|(range, data)| {
+                let (addr, len) = parse_range(range)?;
+                let bytes = unhex(data).filter(|b| b.len() == usize::from(len))?;
+                let bus = self.target.bus_mut();
+                for (i, byte) in bytes.iter().enumerate() {
+                    bus.set_mem(addr.wrapping_add(i as u16), *byte);
+                }
+                Some("OK".to_string())
+            }),
+            Some(b'Z') | Some(b'z') => self.set_point(packet),
+            Some(b'H') | Some(b'T') => Some("OK".to_string()),
+            _ => return self.query(packet),
+        };
+        reply.unwrap_or_else(|| "E01".to_string())
+    }
+
+    /// `q` and `Q` packets. Unknown packets get an empty reply, which tells gdb the stub
+    /// does not support them.
+    fn query(&mut self, packet: &str) -> String {
+        if let Some(features) = packet.strip_prefix("qSupported") {
+            self.swbreak = features.split([':', ';']).any(|f| f == "swbreak+");
+            return "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+"
+                .to_string();
+        }
+        if let Some(command) = packet.strip_prefix("qRcmd,") {
+            return match unhex(command).and_then(|c| String::from_utf8(c).ok()) {
+                Some(command) => hex(self.monitor(&command).as_bytes()),
+                None => "E01".to_string(),
+            };
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match range.split_once(',').and_then(|(offset, len)| {
+                let offset = usize::from_str_radix(offset, 16).ok()?;
+                let len = usize::from_str_radix(len, 16).ok()?;
+                Some((offset, len))
+            }) {
+                Some((offset, len)) => {
+                    let rest = TARGET_XML.get(offset..).unwrap_or("");
+                    if rest.len() > len {
+                        format!("m{}", &rest[..len])
+                    } else {
+                        format!("l{}", rest)
+                    }
+                }
+                None => "E01".to_string(),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => "OK",
+            "qAttached" => "1",
+            "qC" => "QC1",
+            "qfThreadInfo" => "m1",
+            "qsThreadInfo" => "l",
+            _ => "",
+        }
+        .to_string()
+    }
+
+    /// Output of a `monitor` command
+    fn monitor(&self, command: &str) -> String {
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("where"), None, _) => {
+                let pc = self.target.registers().pc;
+                match self.symbols.locate(pc) {
+                    Some(location) => format!("pc {:04x} {}\n", pc, location),
+                    None => format!("pc {:04x}\n", pc),
+                }
+            }
+            (Some("sym"), Some(name), None) => match self.symbols.find(name) {
+                Some(symbol) => format!("{} = {:04x}\n", symbol.name, symbol.addr),
+                None => format!("no symbol {}\n", name),
+            },
+            _ => "commands: where, sym NAME\n".to_string(),
+        }
+    }
+
+    /// `Z`/`z` type,addr,kind: 0 and 1 are breakpoints, 2 to 4 write, read and access
+    /// watchpoints of `kind` bytes
+    fn set_point(&mut self, packet: &str) -> Option<String> {
+        let insert = packet.starts_with('Z');
+        let mut fields = packet[1..].split(',');
+        let kind = fields.next()?;
+        let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let len = u16::from_str_radix(fields.next()?.split(';').next()?, 16).ok()?;
+        let watch = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return Some(String::new()),
+        };
+        if insert {
+            self.watchpoints.push(Watchpoint {
+                kind: watch,
+                addr,
+                len: len.max(1),
+            });
+        } else {
+            self.watchpoints
+                .retain(|w| !(w.kind == watch && w.addr == addr && w.len == len.max(1)));
+        }
+        Some("OK".to_string())
+    }
+
+    fn stop_reply(&self) -> String {
+        match self.last_stop {
+            Stop::Step => "S05".to_string(),
+            Stop::Breakpoint if self.swbreak => "T05swbreak:;".to_string(),
+            Stop::Breakpoint => "S05".to_string(),
+            Stop::Watch(kind, addr) => {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T05{}:{:x};", name, addr)
+            }
+            Stop::Interrupted => "S02".to_string(),
+        }
+    }
+
+    /// Run one instruction, or until a breakpoint, a watchpoint or a break from gdb. The
+    /// first instruction always runs, so continuing from a breakpoint moves on.
+    fn resume(&mut self, stream: &mut TcpStream, single: bool) -> io::Result<Stop> {
+        let mut steps: u32 = 0;
+        loop {
+            if let Some(hit) = self.step_watched() {
+                return Ok(hit);
+            }
+            if single {
+                return Ok(Stop::Step);
+            }
+            if self.breakpoints.contains(&self.target.registers().pc) {
+                return Ok(Stop::Breakpoint);
+            }
+            steps = steps.wrapping_add(1);
+            if steps.is_multiple_of(POLL_STEPS) && interrupted(stream)? {
+                return Ok(Stop::Interrupted);
+            }
+        }
+    }
+
+    /// Step the target, returning the watchpoint the instruction hit
+    fn step_watched(&mut self) -> Option<Stop> {
+        let pc = self.target.registers().pc;
+        self.target.access_log().clear();
+        self.target.step();
+        let log = self.target.access_log();
+        data_accesses(log, pc).iter().find_map(|&(access, addr)| {
+            let w = self.watchpoints.iter().find(|w| {
+                let kind = match w.kind {
+                    WatchKind::Write => access == Access::Write,
+                    WatchKind::Read => access == Access::Read,
+                    WatchKind::Access => true,
+                };
+                kind && addr.wrapping_sub(w.addr) < w.len
+            })?;
+            Some(Stop::Watch(w.kind, w.addr))
+        })
+    }
+
+    fn read_registers(&self) -> [u8; REGISTER_BYTES] {
+        let r = self.target.registers();
+        let [sp_low, sp_high] = r.sp.to_le_bytes();
+        let [pc_low, pc_high] = r.pc.to_le_bytes();
+        [
+            r.a,
+            r.get_flags(),
+            r.b,
+            r.c,
+            r.d,
+            r.e,
+            r.h,
+            r.l,
+            sp_low,
+            sp_high,
+            pc_low,
+            pc_high,
+        ]
+    }
+
+    fn write_registers(&mut self, bytes: &[u8]) -> Option<String> {
+        if bytes.len() != REGISTER_BYTES {
+            return None;
+        }
+        let r = self.target.registers_mut();
+        r.a = bytes[0];
+        r.set_flags(bytes[1]);
+        r.b = bytes[2];
+        r.c = bytes[3];
+        r.d = bytes[4];
+        r.e = bytes[5];
+        r.h = bytes[6];
+        r.l = bytes[7];
+        r.sp = u16::from_le_bytes([bytes[8], bytes[9]]);
+        r.pc = u16::from_le_bytes([bytes[10], bytes[11]]);
+        Some("OK".to_string())
+    }
+
+    /// Next packet from gdb, acknowledged unless in no-ack mode. Stray acks and breaks
+    /// are skipped.
+    fn receive(&mut self, stream: &mut TcpStream) -> io::Result<String> {
+        loop {
+            while read_byte(stream)? != b'$' {}
+            let mut data = Vec::new();
+            let mut sum: u8 = 0;
+            loop {
+                let byte = read_byte(stream)?;
+                if byte == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(byte);
+                data.push(byte);
+            }
+            let checksum = [read_byte(stream)?, read_byte(stream)?];
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok());
+            if !self.no_ack {
+                if expected != Some(sum) {
+                    stream.write_all(b"-")?;
+                    continue;
+                }
+                stream.write_all(b"+")?;
+            }
+            return Ok(String::from_utf8_lossy(&unescape(&data)).into_owned());
+        }
+    }
+
+    /// Send a packet, again until gdb acknowledges it unless in no-ack mode
+    fn send(&mut self, stream: &mut TcpStream, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, sum);
+        loop {
+            stream.write_all(packet.as_bytes())?;
+            if self.no_ack {
+                return Ok(());
+            }
+            loop {
+                match read_byte(stream)? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// The accesses in `log` of the instruction that started at `pc`, without its fetch: the
+/// reads of `pc`, `pc+1` and on it starts with
+fn data_accesses(log: &[(Access, u16)], pc: u16) -> &[(Access, u16)] {
+    let fetched = log
+        .iter()
+        .zip(0u16..)
+        .take_while(|&(&(access, addr), i)| access == Access::Read && addr == pc.wrapping_add(i))
+        .count();
+    &log[fetched..]
+}
+
+/// Bytes of register `n` in the `g` packet
+fn register_range(n: usize) -> Option<std::ops::Range<usize>> {
+    match n {
+        0..=7 => Some(n..n + 1),
+        8 | 9 => Some(8 + (n - 8) * 2..10 + (n - 8) * 2),
+        _ => None,
+    }
+}
+
+/// `addr,length` in hex
+fn parse_range(text: &str) -> Option<(u16, u16)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((
+        u16::from_str_radix(addr, 16).ok()?,
+        u16::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// The optional address of `c` and `s`: `Some(None)` when there is none, `None` when it
+/// does not parse
+fn resume_addr(text: &str) -> Option<Option<u16>> {
+    if text.is_empty() {
+        return Some(None);
+    }
+    u16::from_str_radix(text, 16).ok().map(Some)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `}` escapes the next byte, xored with 0x20
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => out.extend(bytes.next().map(|b| b ^ 0x20)),
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+fn read_byte(stream: &mut TcpStream) -> io::Result<u8> {
+    let mut byte = [0u8];
+    stream.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+/// Whether gdb sent a break (0x03) while the target runs. A hang-up counts as one.
+fn interrupted(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0u8];
+    let result = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+    match result {
+        Ok(0) => Ok(true),
+        Ok(_) => Ok(byte[0] == 0x03),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
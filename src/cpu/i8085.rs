@@ -0,0 +1,195 @@
+use crate::cpu::{Cpu, IO};
+use crate::memory::AddressBus;
+
+/// Interrupt inputs the 8085 adds to INTR, highest priority first after TRAP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line8085 {
+    /// Non-maskable, rising edge, vector 0x24
+    Trap,
+    /// Rising edge, latched until serviced or reset by SIM, vector 0x3c
+    Rst75,
+    /// Level, vector 0x34
+    Rst65,
+    /// Level, vector 0x2c
+    Rst55,
+}
+
+/// State of the 8085 interrupt and serial pins
+#[derive(Default)]
+pub(crate) struct Pins8085 {
+    /// RST 7.5/6.5/5.5 masks set by SIM, bits 2..0
+    mask: u8,
+    rst75: bool,
+    rst65: bool,
+    rst55: bool,
+    trap: bool,
+    trap_level: bool,
+    rst75_level: bool,
+    /// Interrupt enable before the last TRAP, reported once by RIM
+    ie_before_trap: Option<bool>,
+    sid: bool,
+    sod: bool,
+}
+
+impl Pins8085 {
+    /// RESET IN masks RST 7.5/6.5/5.5 and clears the 7.5 latch and SOD
+    pub(crate) fn reset(&mut self) {
+        self.mask = 0b111;
+        self.rst75 = false;
+        self.trap = false;
+        self.ie_before_trap = None;
+        self.sod = false;
+    }
+}
+
+/// Cycles of each opcode on the 8085, without the extra cycles of a taken branch
+//  0   1   2   3   4   5   6   7   8   9   a   b   c   d   e   f
+#[rustfmt::skip]
+pub(crate) const OP_CYCLES_8085: [u8; 256] = [
+     4, 10,  7,  6,  4,  4,  7,  4, 10, 10,  7,  6,  4,  4,  7,  4, // 0
+     7, 10,  7,  6,  4,  4,  7,  4, 10, 10,  7,  6,  4,  4,  7,  4, // 1
+     4, 10, 16,  6,  4,  4,  7,  4, 10, 10, 16,  6,  4,  4,  7,  4, // 2
+     4, 10, 13,  6, 10, 10, 10,  4, 10, 10, 13,  6,  4,  4,  7,  4, // 3
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 4
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 5
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 6
+     7,  7,  7,  7,  7,  7,  5,  7,  4,  4,  4,  4,  4,  4,  7,  4, // 7
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 8
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 9
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // a
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // b
+     6, 10,  7, 10,  9, 12,  7, 12,  6, 10,  7,  6,  9, 18,  7, 12, // c
+     6, 10,  7, 10,  9, 12,  7, 12,  6, 10,  7, 10,  9,  7,  7, 12, // d
+     6, 10,  7, 16,  9, 12,  7, 12,  6,  6,  7,  4,  9, 10,  7, 12, // e
+     6, 10,  7,  4,  9, 12,  7, 12,  6,  6,  7,  4,  9,  7,  7, 12, // f
+];
+
+/// Cycles of taking one of the 8085 interrupt vectors
+const VECTOR_CYCLES: u8 = 12;
+
+impl<B: AddressBus, I: IO + ?Sized> Cpu<B, I> {
+    /// Drive one of the 8085 interrupt pins. Ignored by the other variants.
+    pub fn set_line(&mut self, line: Line8085, high: bool) {
+        let pins = &mut self.pins;
+        match line {
+            Line8085::Trap => {
+                pins.trap |= high && !pins.trap_level;
+                pins.trap_level = high;
+            }
+            Line8085::Rst75 => {
+                pins.rst75 |= high && !pins.rst75_level;
+                pins.rst75_level = high;
+            }
+            Line8085::Rst65 => pins.rst65 = high,
+            Line8085::Rst55 => pins.rst55 = high,
+        }
+    }
+
+    /// Serial input pin, read by RIM
+    pub fn set_sid(&mut self, high: bool) {
+        self.pins.sid = high;
+    }
+
+    /// Serial output pin, written by SIM
+    pub fn sod(&self) -> bool {
+        self.pins.sod
+    }
+
+    /// Take TRAP or an unmasked RST 7.5/6.5/5.5, in priority order.
+    /// Returns the cycles spent, or None when nothing is pending.
+    pub(crate) fn service_8085(&mut self, enabled: bool) -> Option<u8> {
+        let pins = &mut self.pins;
+        let vector = if pins.trap {
+            pins.trap = false;
+            pins.ie_before_trap = Some(self.inte);
+            0x24
+        } else if !enabled {
+            return None;
+        } else if pins.rst75 && pins.mask & 0b100 == 0 {
+            pins.rst75 = false;
+            0x3c
+        } else if pins.rst65 && pins.mask & 0b010 == 0 {
+            0x34
+        } else if pins.rst55 && pins.mask & 0b001 == 0 {
+            0x2c
+        } else {
+            return None;
+        };
+        self.inte = false;
+        self.halted = false;
+        self.stack_add(self.register.pc);
+        self.register.pc = vector;
+        Some(VECTOR_CYCLES)
+    }
+
+    /// RIM: A <- SID, pending 7.5/6.5/5.5, IE, masks
+    pub(crate) fn rim(&mut self) {
+        let pins = &mut self.pins;
+        let ie = pins.ie_before_trap.take().unwrap_or(self.inte);
+        self.register.a = (u8::from(pins.sid) << 7)
+            | (u8::from(pins.rst75) << 6)
+            | (u8::from(pins.rst65) << 5)
+            | (u8::from(pins.rst55) << 4)
+            | (u8::from(ie) << 3)
+            | pins.mask;
+    }
+
+    /// SIM: A holds SOD, SOE, R7.5, MSE and the masks
+    pub(crate) fn sim(&mut self) {
+        let a = self.register.a;
+        let pins = &mut self.pins;
+        if a & 0x08 != 0 {
+            pins.mask = a & 0x07;
+        }
+        if a & 0x10 != 0 {
+            pins.rst75 = false;
+        }
+        if a & 0x40 != 0 {
+            pins.sod = a & 0x80 != 0;
+        }
+    }
+
+    /// DSUB: HL <- HL - BC
+    pub(crate) fn dsub(&mut self) {
+        let hl = self.register.get_hl();
+        let bc = self.register.get_bc();
+        // Flags come from the high byte, subtracted with the borrow of the low byte
+        let a = self.register.a;
+        self.register.a = self.register.l;
+        self.sub(self.register.c);
+        self.register.a = self.register.h;
+        self.sbb(self.register.b);
+        self.register.a = a;
+        let result = hl.wrapping_sub(bc);
+        self.register.set_hl(result);
+        self.register.flag_z = result == 0;
+    }
+
+    /// ARHL: arithmetic shift HL right, CY <- bit 0
+    pub(crate) fn arhl(&mut self) {
+        let hl = self.register.get_hl();
+        self.register.flag_cy = hl & 1 != 0;
+        self.register.set_hl((hl >> 1) | (hl & 0x8000));
+    }
+
+    /// RDEL: rotate DE left through carry, V <- sign change
+    pub(crate) fn rdel(&mut self) {
+        let de = self.register.get_de();
+        let result = (de << 1) | u16::from(self.register.flag_cy);
+        self.register.flag_cy = de & 0x8000 != 0;
+        self.register.flag_v = (de ^ result) & 0x8000 != 0;
+        self.register.set_de(result);
+    }
+
+    /// PUSH PSW image: S Z K AC 0 P V CY
+    pub(crate) fn flags_8085(&self) -> u8 {
+        let r = &self.register;
+        (r.get_flags() & 0b1101_0101) | (u8::from(r.flag_k) << 5) | (u8::from(r.flag_v) << 1)
+    }
+
+    pub(crate) fn set_flags_8085(&mut self, flags: u8) {
+        self.register.set_flags(flags);
+        self.register.flag_k = flags & 0b0010_0000 != 0;
+        self.register.flag_v = flags & 0b0000_0010 != 0;
+    }
+}
@@ -1,8 +1,9 @@
-use crate::cpu::Cpu;
 use crate::cpu::Register;
 
 pub trait IO {
+    /// IN: `byte` is the port number, the value goes to `cpu.a`
     fn input(&mut self, cpu: &mut Register, byte: u8);
 
-    fn output(&mut self, cpu: &mut Cpu, byte: u8);
+    /// OUT: `byte` is the port number, the value is `cpu.a`
+    fn output(&mut self, cpu: &mut Register, byte: u8);
 }
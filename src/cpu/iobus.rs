@@ -0,0 +1,142 @@
+use std::any::Any;
+
+use crate::cpu::Register;
+use crate::cpu::IO;
+
+/// Hardware on the I/O ports. `port` is the full port number, so one device can tell
+/// several of its registers apart.
+pub trait PortDevice: Any {
+    /// IN from a port the device is mapped on for input
+    fn input(&mut self, _port: u8) -> u8 {
+        0xff
+    }
+
+    /// OUT to a port the device is mapped on for output
+    fn output(&mut self, _port: u8, _val: u8) {}
+
+    /// Power on state
+    fn reset(&mut self) {}
+}
+
+/// Port decoder: IN and OUT of each port go to the device registered for them.
+/// The first access to an unmapped port is logged, and IN reads the open bus value.
+pub struct IoBus {
+    devices: Vec<Box<dyn PortDevice>>,
+    inputs: [Option<u8>; 256],
+    outputs: [Option<u8>; 256],
+    open_bus: u8,
+    /// Unmapped ports already logged, IN in bit 0 and OUT in bit 1
+    logged: [u8; 256],
+}
+
+/// Returned by [IoBus::attach] to pick the ports of the new device
+pub struct Attach<'a> {
+    bus: &'a mut IoBus,
+    device: u8,
+}
+
+impl Attach<'_> {
+    pub fn input(self, ports: impl IntoIterator<Item = u8>) -> Self {
+        for port in ports {
+            Self::map(&mut self.bus.inputs[port as usize], "IN", port, self.device);
+        }
+        self
+    }
+
+    pub fn output(self, ports: impl IntoIterator<Item = u8>) -> Self {
+        for port in ports {
+            Self::map(
+                &mut self.bus.outputs[port as usize],
+                "OUT",
+                port,
+                self.device,
+            );
+        }
+        self
+    }
+
+    fn map(slot: &mut Option<u8>, dir: &str, port: u8, device: u8) {
+        assert!(slot.is_none(), "{} port {:02x} is mapped twice", dir, port);
+        *slot = Some(device);
+    }
+}
+
+impl IoBus {
+    pub fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            inputs: [None; 256],
+            outputs: [None; 256],
+            open_bus: 0xff,
+            logged: [0; 256],
+        }
+    }
+
+    /// Value read from unmapped ports, 0xff by default
+    pub fn set_open_bus(&mut self, value: u8) {
+        self.open_bus = value;
+    }
+
+    /// Add a device, then map it with [Attach::input] and [Attach::output].
+    /// Mapping a port that already has a device panics.
+    pub fn attach(&mut self, device: impl PortDevice) -> Attach<'_> {
+        assert!(self.devices.len() < u8::MAX as usize, "too many devices");
+        self.devices.push(Box::new(device));
+        let device = (self.devices.len() - 1) as u8;
+        Attach { bus: self, device }
+    }
+
+    /// The first device of type `T`
+    pub fn device<T: PortDevice>(&self) -> Option<&T> {
+        (self.devices.iter()).find_map(|d| (d.as_ref() as &dyn Any).downcast_ref::<T>())
+    }
+
+    pub fn device_mut<T: PortDevice>(&mut self) -> Option<&mut T> {
+        (self.devices.iter_mut()).find_map(|d| (d.as_mut() as &mut dyn Any).downcast_mut::<T>())
+    }
+
+    /// Put every device back in its power on state
+    pub fn reset(&mut self) {
+        self.devices.iter_mut().for_each(|d| d.reset());
+    }
+
+    pub fn read(&mut self, port: u8) -> u8 {
+        match self.inputs[port as usize] {
+            Some(device) => self.devices[device as usize].input(port),
+            None => {
+                self.log_unmapped(port, 0b01, "IN");
+                self.open_bus
+            }
+        }
+    }
+
+    pub fn write(&mut self, port: u8, val: u8) {
+        match self.outputs[port as usize] {
+            Some(device) => self.devices[device as usize].output(port, val),
+            None => self.log_unmapped(port, 0b10, "OUT"),
+        }
+    }
+
+    fn log_unmapped(&mut self, port: u8, bit: u8, dir: &str) {
+        if self.logged[port as usize] & bit == 0 {
+            self.logged[port as usize] |= bit;
+            eprintln!("{} on unmapped port {:02x}", dir, port);
+        }
+    }
+}
+
+impl Default for IoBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IO for IoBus {
+    fn input(&mut self, cpu: &mut Register, byte: u8) {
+        cpu.a = self.read(byte);
+    }
+
+    fn output(&mut self, cpu: &mut Register, byte: u8) {
+        self.write(byte, cpu.a);
+    }
+}
@@ -0,0 +1,361 @@
+//! Differential testing: two cores run the same program side by side and are compared
+//! after every instruction.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::cpu::symbols::SymbolTable;
+use crate::cpu::{BlockCpu, Cpu, Register, IO};
+use crate::memory::AddressBus;
+
+/// Bus wrapper that records every write, for comparing what two cores stored
+pub struct WriteLog<B: AddressBus> {
+    inner: B,
+    writes: Vec<(u16, u8)>,
+}
+
+impl<B: AddressBus> WriteLog<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            writes: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// The wrapped bus, writes through it are not recorded
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Writes since the last call, oldest first
+    pub fn take_writes(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.writes)
+    }
+}
+
+impl<B: AddressBus> AddressBus for WriteLog<B> {
+    fn get_mem(&self, addr: u16) -> u8 {
+        self.inner.get_mem(addr)
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        self.writes.push((addr, val));
+        self.inner.set_mem(addr, val)
+    }
+
+    fn canonical(&self, addr: u16) -> u16 {
+        self.inner.canonical(addr)
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        self.inner.fill_ram(pattern)
+    }
+}
+
+/// A CPU implementation as seen by [Lockstep]
+pub trait Core {
+    /// Run exactly one instruction, or one interrupt acknowledge, returns its cycles
+    fn step(&mut self) -> u32;
+
+    fn register(&self) -> &Register;
+
+    fn interrupts_enabled(&self) -> bool;
+
+    fn is_halted(&self) -> bool;
+
+    /// Memory writes since the last call
+    fn take_writes(&mut self) -> Vec<(u16, u8)>;
+
+    /// See [Cpu::interrupt]
+    fn interrupt(&mut self, code: u8) -> bool;
+}
+
+/// The interpreter with its own IO
+pub struct CpuCore<B: AddressBus, I: IO> {
+    pub cpu: Cpu<WriteLog<B>, I>,
+    pub io: I,
+}
+
+impl<B: AddressBus, I: IO> CpuCore<B, I> {
+    pub fn new(cpu: Cpu<B, I>, io: I) -> Self {
+        let cpu = cpu.map_bus(WriteLog::new);
+        Self { cpu, io }
+    }
+}
+
+impl<B: AddressBus, I: IO> Core for CpuCore<B, I> {
+    fn step(&mut self) -> u32 {
+        u32::from(self.cpu.step(&mut self.io))
+    }
+
+    fn register(&self) -> &Register {
+        &self.cpu.register
+    }
+
+    fn interrupts_enabled(&self) -> bool {
+        self.cpu.interrupts_enabled()
+    }
+
+    fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    fn take_writes(&mut self) -> Vec<(u16, u8)> {
+        self.cpu.addring.take_writes()
+    }
+
+    fn interrupt(&mut self, code: u8) -> bool {
+        self.cpu.interrupt(code)
+    }
+}
+
+/// The [BlockCpu] engine with its own IO, stopped after every instruction
+pub struct BlockCore<B: AddressBus, I: IO> {
+    pub cpu: BlockCpu<WriteLog<B>, I>,
+    pub io: I,
+}
+
+impl<B: AddressBus, I: IO> BlockCore<B, I> {
+    pub fn new(cpu: Cpu<B, I>, io: I) -> Self {
+        let cpu = BlockCpu::new(cpu.map_bus(WriteLog::new));
+        Self { cpu, io }
+    }
+}
+
+impl<B: AddressBus, I: IO> Core for BlockCore<B, I> {
+    fn step(&mut self) -> u32 {
+        // Every instruction takes at least one cycle
+        self.cpu.run(&mut self.io, 1)
+    }
+
+    fn register(&self) -> &Register {
+        &self.cpu.cpu.register
+    }
+
+    fn interrupts_enabled(&self) -> bool {
+        self.cpu.cpu.interrupts_enabled()
+    }
+
+    fn is_halted(&self) -> bool {
+        self.cpu.cpu.is_halted()
+    }
+
+    fn take_writes(&mut self) -> Vec<(u16, u8)> {
+        self.cpu.cpu.addring.get_mut().take_writes()
+    }
+
+    fn interrupt(&mut self, code: u8) -> bool {
+        self.cpu.cpu.interrupt(code)
+    }
+}
+
+/// Where two cores went apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Steps both cores agreed on before this one
+    pub step: u64,
+    /// PC before the step
+    pub pc: u16,
+    /// PC relative to the closest code symbol, see [SymbolTable::locate]
+    pub location: Option<String>,
+    /// One `what: a != b` line per difference
+    pub diffs: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "diverged at step {}, pc {:04x}", self.step, self.pc)?;
+        if let Some(location) = &self.location {
+            write!(f, " ({})", location)?;
+        }
+        for diff in &self.diffs {
+            write!(f, "\n  {}", diff)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for Divergence {}
+
+/// Two cores run over the same program, compared after every step: registers, flags,
+/// interrupt state, memory writes and cycles. Each core has its own memory and IO, set
+/// up the same way by the caller.
+pub struct Lockstep<A: Core, B: Core> {
+    pub a: A,
+    pub b: B,
+    steps: u64,
+    /// Names for the addresses of a [Divergence]
+    symbols: SymbolTable,
+}
+
+impl<A: Core, B: Core> Lockstep<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            steps: 0,
+            symbols: SymbolTable::new(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Steps run so far
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// One instruction on both cores, returns its cycles
+    pub fn step(&mut self) -> Result<u32, Divergence> {
+        let pc = self.a.register().pc;
+        let cycles = (self.a.step(), self.b.step());
+        let mut diffs = diff_cores(&self.a, &self.b);
+        let writes = (self.a.take_writes(), self.b.take_writes());
+        if writes.0 != writes.1 {
+            diffs.push(format!(
+                "writes: {} != {}",
+                format_writes(&writes.0, &self.symbols),
+                format_writes(&writes.1, &self.symbols)
+            ));
+        }
+        if cycles.0 != cycles.1 {
+            diffs.push(format!("cycles: {} != {}", cycles.0, cycles.1));
+        }
+        self.check(pc, diffs)?;
+        Ok(cycles.0)
+    }
+
+    /// `steps` instructions, or until the first divergence
+    pub fn run(&mut self, steps: u64) -> Result<(), Divergence> {
+        for _ in 0..steps {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// At least `cycles` cycles, stopping after the instruction that reaches them.
+    /// Returns the cycles run.
+    pub fn run_cycles(&mut self, cycles: u32) -> Result<u32, Divergence> {
+        let mut done = 0;
+        while done < cycles {
+            done += self.step()?;
+        }
+        Ok(done)
+    }
+
+    /// [Cpu::interrupt] on both cores, which must both take it or both refuse it
+    pub fn interrupt(&mut self, code: u8) -> Result<bool, Divergence> {
+        let pc = self.a.register().pc;
+        let taken = (self.a.interrupt(code), self.b.interrupt(code));
+        let mut diffs = diff_cores(&self.a, &self.b);
+        if taken.0 != taken.1 {
+            diffs.push(format!(
+                "interrupt {:02x} taken: {} != {}",
+                code, taken.0, taken.1
+            ));
+        }
+        let writes = (self.a.take_writes(), self.b.take_writes());
+        if writes.0 != writes.1 {
+            diffs.push(format!(
+                "writes: {} != {}",
+                format_writes(&writes.0, &self.symbols),
+                format_writes(&writes.1, &self.symbols)
+            ));
+        }
+        self.check(pc, diffs)?;
+        Ok(taken.0)
+    }
+
+    fn check(&mut self, pc: u16, diffs: Vec<String>) -> Result<(), Divergence> {
+        if !diffs.is_empty() {
+            return Err(Divergence {
+                step: self.steps,
+                pc,
+                location: self.symbols.locate(pc),
+                diffs,
+            });
+        }
+        self.steps += 1;
+        Ok(())
+    }
+}
+
+fn diff_cores(a: &impl Core, b: &impl Core) -> Vec<String> {
+    let mut diffs = diff_registers(a.register(), b.register());
+    if a.interrupts_enabled() != b.interrupts_enabled() {
+        diffs.push(format!(
+            "inte: {} != {}",
+            a.interrupts_enabled(),
+            b.interrupts_enabled()
+        ));
+    }
+    if a.is_halted() != b.is_halted() {
+        diffs.push(format!("halted: {} != {}", a.is_halted(), b.is_halted()));
+    }
+    diffs
+}
+
+/// One line per register that differs
+pub fn diff_registers(a: &Register, b: &Register) -> Vec<String> {
+    let bytes = [
+        ("a", a.a, b.a),
+        ("b", a.b, b.b),
+        ("c", a.c, b.c),
+        ("d", a.d, b.d),
+        ("e", a.e, b.e),
+        ("h", a.h, b.h),
+        ("l", a.l, b.l),
+    ];
+    let mut diffs: Vec<String> = bytes
+        .iter()
+        .filter(|(_, x, y)| x != y)
+        .map(|(name, x, y)| format!("{}: {:02x} != {:02x}", name, x, y))
+        .collect();
+    for (name, x, y) in [("sp", a.sp, b.sp), ("pc", a.pc, b.pc)] {
+        if x != y {
+            diffs.push(format!("{}: {:04x} != {:04x}", name, x, y));
+        }
+    }
+    let (x, y) = (a.get_flags(), b.get_flags());
+    if x != y {
+        diffs.push(format!("flags: {} != {}", format_flags(x), format_flags(y)));
+    }
+    diffs
+}
+
+/// `SZ-A-P-C` with the clear flags as `.`
+fn format_flags(flags: u8) -> String {
+    let names = [
+        (0x80, 'S'),
+        (0x40, 'Z'),
+        (0x10, 'A'),
+        (0x04, 'P'),
+        (0x01, 'C'),
+    ];
+    names
+        .iter()
+        .map(|&(bit, name)| if flags & bit != 0 { name } else { '.' })
+        .collect()
+}
+
+/// `[2000=05 20c0(isrDelay)=08]`, named when a symbol is at the address
+fn format_writes(writes: &[(u16, u8)], symbols: &SymbolTable) -> String {
+    let writes: Vec<String> = writes
+        .iter()
+        .map(|&(addr, val)| match symbols.name(addr) {
+            Some(name) => format!("{:04x}({})={:02x}", addr, name, val),
+            None => format!("{:04x}={:02x}", addr, val),
+        })
+        .collect();
+    format!("[{}]", writes.join(" "))
+}
@@ -1,7 +1,21 @@
+mod block;
+#[allow(clippy::module_inception)]
 pub mod cpu;
+pub mod disasm;
+pub mod gdb;
+mod i8085;
 mod io;
+mod iobus;
+pub mod lockstep;
+pub mod observer;
+pub mod profiler;
 pub mod register;
+pub mod symbols;
+mod z80;
 
-pub use cpu::Cpu;
+pub use block::{BlockCpu, CodeWatch};
+pub use cpu::{Cpu, DynCpu, Variant, HALT_CYCLES};
+pub use i8085::Line8085;
 pub use io::IO;
+pub use iobus::{Attach, IoBus, PortDevice};
 pub use register::Register;
@@ -0,0 +1,509 @@
+//! Bus cycle logging: every opcode fetch, memory read and write, stack access, IN/OUT and
+//! interrupt acknowledge, with the cycle it happened at, plus the INT, INTE and HLTA
+//! lines. Exported as CSV, or as VCD for a waveform viewer.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::cpu::symbols::SymbolTable;
+use crate::cpu::{Cpu, Register, Variant, IO};
+use crate::memory::AddressBus;
+
+/// Kind of bus cycle, as the 8080 status word would tell it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// M1: opcode fetch, and on the Z80 the opcode after a prefix
+    Fetch,
+    Read,
+    Write,
+    StackRead,
+    StackWrite,
+    In,
+    Out,
+    /// Interrupt acknowledge, `data` is the instruction on the data bus
+    Inta,
+}
+
+impl Access {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Access::Fetch => "fetch",
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::StackRead => "stack_read",
+            Access::StackWrite => "stack_write",
+            Access::In => "in",
+            Access::Out => "out",
+            Access::Inta => "inta",
+        }
+    }
+}
+
+/// One bus cycle. For IN and OUT the port is on both halves of the address, like on
+/// the 8080.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusCycle {
+    /// T1 of the machine cycle, counted from the creation of the observer
+    pub cycle: u64,
+    pub access: Access,
+    pub addr: u16,
+    pub data: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line {
+    /// An interrupt request is pending
+    Int,
+    /// Interrupt enable flip-flop
+    Inte,
+    /// Halted
+    Hlta,
+}
+
+impl Line {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Line::Int => "int",
+            Line::Inte => "inte",
+            Line::Hlta => "hlta",
+        }
+    }
+}
+
+/// A line sampled at an instruction boundary, recorded when it changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineChange {
+    pub cycle: u64,
+    pub line: Line,
+    pub level: bool,
+}
+
+/// What the two wrappers share: the log and the state of the current instruction
+struct Recorder {
+    variant: Variant,
+    /// Start of the current instruction
+    cycle: u64,
+    pc: u16,
+    sp: u16,
+    /// Accesses are only recorded inside [BusObserver::step]
+    active: bool,
+    /// The current instruction pushes or pops
+    stack_op: bool,
+    /// Index of the first bus cycle of the current instruction
+    start: usize,
+    bus: Vec<BusCycle>,
+    lines: Vec<LineChange>,
+    levels: [bool; 3],
+}
+
+impl Recorder {
+    fn begin(&mut self, register: &Register) {
+        self.pc = register.pc;
+        self.sp = register.sp;
+        self.stack_op = false;
+        self.start = self.bus.len();
+        self.active = true;
+    }
+
+    fn record(&mut self, access: Access, addr: u16, data: u8) {
+        if !self.active {
+            return;
+        }
+        let index = self.bus.len() - self.start;
+        let access = match access {
+            Access::Read if self.is_fetch(index, addr) => {
+                self.stack_op |= is_stack_op(data, self.variant);
+                Access::Fetch
+            }
+            Access::Read if self.stack_op && self.in_stack(addr) => Access::StackRead,
+            // An internal interrupt (RST 5.5, TRAP, NMI, IM 1) starts with its pushes
+            Access::Write if index == 0 => {
+                self.stack_op = true;
+                Access::StackWrite
+            }
+            Access::Write if self.stack_op && self.in_stack(addr) => Access::StackWrite,
+            Access::Inta => {
+                self.stack_op = true;
+                Access::Inta
+            }
+            access => access,
+        };
+        self.bus.push(BusCycle {
+            cycle: self.cycle,
+            access,
+            addr,
+            data,
+        });
+    }
+
+    fn is_fetch(&self, index: usize, addr: u16) -> bool {
+        match index {
+            0 => addr == self.pc,
+            // The Z80 fetches the opcode after a prefix in a second M1
+            1 => {
+                let first = self.bus[self.start];
+                self.variant == Variant::Z80
+                    && first.access == Access::Fetch
+                    && matches!(first.data, 0xcb | 0xdd | 0xed | 0xfd)
+                    && addr == self.pc.wrapping_add(1)
+            }
+            _ => false,
+        }
+    }
+
+    /// The two bytes above and below SP, but not the instruction's own operands
+    fn in_stack(&self, addr: u16) -> bool {
+        addr.wrapping_sub(self.sp.wrapping_sub(2)) < 4 && addr.wrapping_sub(self.pc) >= 4
+    }
+
+    /// Spread the bus cycles of the instruction over its `cycles`: M1 takes 4 or 5
+    /// cycles, the other machine cycles 3, except the 5-cycle write of XTHL
+    fn finish(&mut self, cycles: u32) {
+        self.active = false;
+        let bus = &mut self.bus[self.start..];
+        let others = bus.len().saturating_sub(1) as u32;
+        let xthl = matches!(bus.first(), Some(b) if b.access == Access::Fetch && b.data == 0xe3);
+        let mut offset = if xthl {
+            4
+        } else {
+            cycles.saturating_sub(3 * others).clamp(4, 5)
+        };
+        for b in bus.iter_mut().skip(1) {
+            b.cycle += u64::from(offset);
+            offset += 3;
+        }
+        self.cycle += u64::from(cycles);
+    }
+
+    /// Record the lines that changed during the instruction
+    fn sample<B: AddressBus, I: IO + ?Sized>(&mut self, cpu: &Cpu<B, I>) {
+        let levels = levels(cpu);
+        for (i, line) in LINES.into_iter().enumerate() {
+            if levels[i] != self.levels[i] {
+                self.lines.push(LineChange {
+                    cycle: self.cycle,
+                    line,
+                    level: levels[i],
+                });
+            }
+        }
+        self.levels = levels;
+    }
+}
+
+const LINES: [Line; 3] = [Line::Int, Line::Inte, Line::Hlta];
+
+fn levels<B: AddressBus, I: IO + ?Sized>(cpu: &Cpu<B, I>) -> [bool; 3] {
+    [
+        cpu.interrupt_pending(),
+        cpu.interrupts_enabled(),
+        cpu.is_halted(),
+    ]
+}
+
+/// Whether `op` reads or writes the stack: RET, POP, CALL, PUSH, RST and XTHL
+fn is_stack_op(op: u8, variant: Variant) -> bool {
+    if op < 0xc0 {
+        return false;
+    }
+    match (op & 0x07, variant) {
+        // Rcc, Ccc, RST
+        (0 | 4 | 7, _) => true,
+        // POP, RET
+        (1, _) if op & 0x08 == 0 || op == 0xc9 => true,
+        // Undocumented RET
+        (1, Variant::I8080) => op == 0xd9,
+        // XTHL, RSTV
+        (3, Variant::I8085) => op == 0xe3 || op == 0xcb,
+        (3, _) => op == 0xe3,
+        // PUSH, CALL
+        (5, _) if op & 0x08 == 0 || op == 0xcd => true,
+        // Undocumented CALLs
+        (5, Variant::I8080) => true,
+        _ => false,
+    }
+}
+
+/// Bus wrapper that reports every access to the observer
+pub struct ObservedBus<B: AddressBus> {
+    inner: B,
+    log: Rc<RefCell<Recorder>>,
+}
+
+impl<B: AddressBus> ObservedBus<B> {
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// The wrapped bus, accesses through it are not recorded
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+}
+
+impl<B: AddressBus> AddressBus for ObservedBus<B> {
+    fn get_mem(&self, addr: u16) -> u8 {
+        let val = self.inner.get_mem(addr);
+        self.log.borrow_mut().record(Access::Read, addr, val);
+        val
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        self.log.borrow_mut().record(Access::Write, addr, val);
+        self.inner.set_mem(addr, val)
+    }
+
+    fn canonical(&self, addr: u16) -> u16 {
+        self.inner.canonical(addr)
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        self.inner.fill_ram(pattern)
+    }
+}
+
+/// IO wrapper that reports every IN and OUT to the observer
+pub struct ObservedIo<I: IO> {
+    inner: I,
+    log: Rc<RefCell<Recorder>>,
+}
+
+impl<I: IO> ObservedIo<I> {
+    pub fn get_ref(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+}
+
+impl<I: IO> IO for ObservedIo<I> {
+    fn input(&mut self, cpu: &mut Register, byte: u8) {
+        self.inner.input(cpu, byte);
+        let addr = u16::from_le_bytes([byte, byte]);
+        self.log.borrow_mut().record(Access::In, addr, cpu.a);
+    }
+
+    fn output(&mut self, cpu: &mut Register, byte: u8) {
+        let addr = u16::from_le_bytes([byte, byte]);
+        self.log.borrow_mut().record(Access::Out, addr, cpu.a);
+        self.inner.output(cpu, byte)
+    }
+}
+
+/// A CPU whose bus and IO are observed. Memory and IO behave as before, the observer
+/// only records; the cycle of each access comes from the 8080 machine cycle lengths.
+pub struct BusObserver<B: AddressBus, I: IO> {
+    pub cpu: Cpu<ObservedBus<B>, ObservedIo<I>>,
+    pub io: ObservedIo<I>,
+    log: Rc<RefCell<Recorder>>,
+    /// Names for the addresses in the CSV
+    symbols: SymbolTable,
+}
+
+impl<B: AddressBus, I: IO> BusObserver<B, I> {
+    pub fn new(cpu: Cpu<B, I>, io: I) -> Self {
+        let levels = levels(&cpu);
+        let lines = LINES
+            .into_iter()
+            .zip(levels)
+            .map(|(line, level)| LineChange {
+                cycle: 0,
+                line,
+                level,
+            })
+            .collect();
+        let log = Rc::new(RefCell::new(Recorder {
+            variant: cpu.variant(),
+            cycle: 0,
+            pc: 0,
+            sp: 0,
+            active: false,
+            stack_op: false,
+            start: 0,
+            bus: Vec::new(),
+            lines,
+            levels,
+        }));
+        let cpu = cpu.map(|inner| ObservedBus {
+            inner,
+            log: log.clone(),
+        });
+        let io = ObservedIo {
+            inner: io,
+            log: log.clone(),
+        };
+        Self {
+            cpu,
+            io,
+            log,
+            symbols: SymbolTable::new(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// See [Cpu::step]
+    pub fn step(&mut self) -> u8 {
+        let acknowledge = self.cpu.interrupts_enabled() && !self.cpu.ei_delay;
+        let mut log = self.log.borrow_mut();
+        log.begin(&self.cpu.register);
+        if let (true, Some(instruction)) = (acknowledge, self.cpu.int_request) {
+            // The 8085 and the Z80 may serve another source first, close enough
+            log.record(Access::Inta, self.cpu.register.pc, instruction[0]);
+        }
+        drop(log);
+        let cycles = self.cpu.step(&mut self.io);
+        let mut log = self.log.borrow_mut();
+        log.finish(u32::from(cycles));
+        log.sample(&self.cpu);
+        cycles
+    }
+
+    /// See [Cpu::request_interrupt], the INT line shows the request
+    pub fn request_interrupt(&mut self, instruction: &[u8]) {
+        self.cpu.request_interrupt(instruction);
+        self.log.borrow_mut().sample(&self.cpu);
+    }
+
+    /// See [Cpu::interrupt], counted as the 11 cycles of an RST acknowledge
+    pub fn interrupt(&mut self, code: u8) -> bool {
+        if !self.cpu.interrupts_enabled() || self.cpu.ei_delay {
+            return false;
+        }
+        let mut log = self.log.borrow_mut();
+        log.begin(&self.cpu.register);
+        log.record(Access::Inta, self.cpu.register.pc, 0xc7 | (code & 0x38));
+        drop(log);
+        self.cpu.interrupt(code);
+        let mut log = self.log.borrow_mut();
+        log.finish(11);
+        log.sample(&self.cpu);
+        true
+    }
+
+    /// Cycles run so far
+    pub fn cycles(&self) -> u64 {
+        self.log.borrow().cycle
+    }
+
+    pub fn bus_cycles(&self) -> Vec<BusCycle> {
+        self.log.borrow().bus.clone()
+    }
+
+    pub fn line_changes(&self) -> Vec<LineChange> {
+        self.log.borrow().lines.clone()
+    }
+
+    /// Forget what was recorded, the cycle count goes on
+    pub fn clear(&mut self) {
+        let mut log = self.log.borrow_mut();
+        log.bus.clear();
+        log.lines.clear();
+        log.start = 0;
+    }
+
+    /// `cycle,access,addr,data,label`, the line changes as `cycle,int,,1,`. Fetches are
+    /// labelled with the routine they are in, memory accesses with the symbol at their
+    /// address.
+    pub fn write_csv(&self, mut w: impl Write) -> io::Result<()> {
+        let log = self.log.borrow();
+        writeln!(w, "cycle,access,addr,data,label")?;
+        let mut lines = log.lines.iter().peekable();
+        for b in &log.bus {
+            while let Some(l) = lines.next_if(|l| l.cycle <= b.cycle) {
+                writeln!(w, "{},{},,{},", l.cycle, l.line.name(), u8::from(l.level))?;
+            }
+            let label = match b.access {
+                Access::Fetch => self.symbols.locate(b.addr),
+                Access::In | Access::Out | Access::Inta => None,
+                _ => self.symbols.name(b.addr).map(str::to_string),
+            };
+            writeln!(
+                w,
+                "{},{},{:04x},{:02x},{}",
+                b.cycle,
+                b.access.name(),
+                b.addr,
+                b.data,
+                label.unwrap_or_default()
+            )?;
+        }
+        for l in lines {
+            writeln!(w, "{},{},,{},", l.cycle, l.line.name(), u8::from(l.level))?;
+        }
+        Ok(())
+    }
+
+    /// Value Change Dump with the address and data buses, one strobe per kind of cycle
+    /// held for the 3 cycles of a machine cycle, and the lines. `clock_hz` sets the
+    /// time scale, e.g. 2_000_000 for a 2 MHz 8080.
+    pub fn write_vcd(&self, mut w: impl Write, clock_hz: u32) -> io::Result<()> {
+        let log = self.log.borrow();
+        let ns = |cycle: u64| cycle * 1_000_000_000 / u64::from(clock_hz.max(1));
+        let strobes = ["m1", "memr", "memw", "stack", "iord", "iowr", "inta"];
+
+        writeln!(w, "$timescale 1ns $end")?;
+        writeln!(w, "$scope module cpu $end")?;
+        writeln!(w, "$var wire 16 A addr $end")?;
+        writeln!(w, "$var wire 8 D data $end")?;
+        for (i, name) in strobes.iter().enumerate() {
+            writeln!(w, "$var wire 1 s{} {} $end", i, name)?;
+        }
+        for line in LINES {
+            writeln!(w, "$var wire 1 l{} {} $end", line as u8, line.name())?;
+        }
+        writeln!(w, "$upscope $end")?;
+        writeln!(w, "$enddefinitions $end")?;
+
+        // (time, change), sorted by time before writing
+        let mut changes: Vec<(u64, String)> = Vec::new();
+        changes.push((0, "bxxxxxxxxxxxxxxxx A".to_string()));
+        changes.push((0, "bxxxxxxxx D".to_string()));
+        for i in 0..strobes.len() {
+            changes.push((0, format!("0s{}", i)));
+        }
+        for b in &log.bus {
+            let active: &[usize] = match b.access {
+                Access::Fetch => &[0, 1],
+                Access::Read => &[1],
+                Access::Write => &[2],
+                Access::StackRead => &[1, 3],
+                Access::StackWrite => &[2, 3],
+                Access::In => &[4],
+                Access::Out => &[5],
+                Access::Inta => &[0, 6],
+            };
+            changes.push((b.cycle, format!("b{:016b} A", b.addr)));
+            changes.push((b.cycle, format!("b{:08b} D", b.data)));
+            for i in active {
+                changes.push((b.cycle, format!("1s{}", i)));
+                changes.push((b.cycle + 3, format!("0s{}", i)));
+            }
+        }
+        for l in &log.lines {
+            changes.push((l.cycle, format!("{}l{}", u8::from(l.level), l.line as u8)));
+        }
+        // Stable: a strobe released at the same time another cycle raises it stays up
+        changes.sort_by_key(|(cycle, change)| (*cycle, change.starts_with('1')));
+
+        let mut time = None;
+        for (cycle, change) in changes {
+            if time != Some(cycle) {
+                writeln!(w, "#{}", ns(cycle))?;
+                time = Some(cycle);
+            }
+            writeln!(w, "{}", change)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> (Cpu<B, I>, I) {
+        let cpu = self.cpu.map(|bus| bus.inner);
+        (cpu, self.io.inner)
+    }
+}
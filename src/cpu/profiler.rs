@@ -0,0 +1,292 @@
+//! Execution profiler: per-PC counts and cycles, and per-subroutine self and inclusive
+//! cycles from following CALL/RST and RET through the stack pointer.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::cpu::disasm::{entries, Entry, OP_LENGTHS};
+use crate::cpu::symbols::SymbolTable;
+use crate::cpu::{Cpu, DynCpu, IO};
+use crate::memory::AddressBus;
+
+/// A subroutine, keyed by its entry address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Routine {
+    pub calls: u64,
+    /// Cycles of its own instructions
+    pub self_cycles: u64,
+    /// Cycles from entry to return, callees included. Recursive calls are counted once.
+    pub inclusive_cycles: u64,
+}
+
+/// Where a step started
+struct Before {
+    pc: u16,
+    sp: u16,
+    len: u8,
+    idle: bool,
+}
+
+/// A call in progress
+struct Frame {
+    entry: u16,
+    /// SP right after the return address was pushed; the frame ends when SP goes above
+    sp: u16,
+    start: u64,
+}
+
+/// Watches a CPU instruction by instruction: run each step through [Profiler::step]
+/// or [Profiler::next]. Interrupts taken outside of a step are noticed at the next one.
+pub struct Profiler {
+    hits: Vec<u64>,
+    cycles: Vec<u64>,
+    routines: HashMap<u16, Routine>,
+    stack: Vec<Frame>,
+    /// Entry of the code running outside of any call
+    root: Option<u16>,
+    total_cycles: u64,
+    instructions: u64,
+    /// PC and SP after the last step
+    last: Option<(u16, u16)>,
+    /// Names for the report and the listing
+    symbols: SymbolTable,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            hits: vec![0; 0x10000],
+            cycles: vec![0; 0x10000],
+            routines: HashMap::new(),
+            stack: Vec::new(),
+            root: None,
+            total_cycles: 0,
+            instructions: 0,
+            last: None,
+            symbols: SymbolTable::new(),
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// [Cpu::step] with the profiler watching
+    pub fn step<B: AddressBus, I: IO + ?Sized>(&mut self, cpu: &mut Cpu<B, I>, io: &mut I) -> u8 {
+        let before = self.before(cpu);
+        let cycles = cpu.step(io);
+        self.after(cpu, before, cycles);
+        cycles
+    }
+
+    /// [DynCpu::next] with the profiler watching
+    pub fn next(&mut self, cpu: &mut DynCpu) -> u8 {
+        let before = self.before(cpu);
+        let cycles = cpu.next();
+        self.after(cpu, before, cycles);
+        cycles
+    }
+
+    /// Notice an interrupt taken since the last step: PC moved and the old one was
+    /// pushed. Returns the state the step starts from.
+    fn before<B: AddressBus, I: IO + ?Sized>(&mut self, cpu: &Cpu<B, I>) -> Before {
+        let (pc, sp) = (cpu.register.pc, cpu.register.sp);
+        if let Some((last_pc, last_sp)) = self.last {
+            if pc != last_pc && sp == last_sp.wrapping_sub(2) && cpu.addring.get_word(sp) == last_pc
+            {
+                self.enter(pc, sp);
+            }
+        }
+        if self.root.is_none() {
+            self.root = Some(pc);
+        }
+        // Halted, or acknowledging INT: the instruction at PC does not run
+        let idle = cpu.is_halted() || cpu.inte && !cpu.ei_delay && cpu.int_request.is_some();
+        Before {
+            pc,
+            sp,
+            len: OP_LENGTHS[cpu.addring.get_mem(pc) as usize],
+            idle,
+        }
+    }
+
+    fn after<B: AddressBus, I: IO + ?Sized>(
+        &mut self,
+        cpu: &Cpu<B, I>,
+        before: Before,
+        cycles: u8,
+    ) {
+        let Before { pc, sp, len, idle } = before;
+        let cycles = u64::from(cycles);
+        if !idle {
+            self.hits[pc as usize] += 1;
+            self.instructions += 1;
+        }
+        // Cycles spent halted go to the HLT
+        let at = if cpu.is_halted() && idle {
+            pc.wrapping_sub(1)
+        } else {
+            pc
+        };
+        self.cycles[at as usize] += cycles;
+        self.total_cycles += cycles;
+        let current = self.stack.last().map(|f| f.entry).or(self.root);
+        if let Some(entry) = current {
+            self.routines.entry(entry).or_default().self_cycles += cycles;
+        }
+
+        let (new_pc, new_sp) = (cpu.register.pc, cpu.register.sp);
+        // Frames whose return address was popped, by RET or by resetting SP. Compared as a
+        // distance, as the stack may wrap around 0 (CP/M programs start at LXI SP,0)
+        while self
+            .stack
+            .last()
+            .is_some_and(|f| new_sp.wrapping_sub(f.sp) as i16 > 0)
+        {
+            self.leave();
+        }
+        // CALL, RST, or an interrupt acknowledged in the step: PC moved somewhere else
+        // and the address to come back to was pushed
+        let next = pc.wrapping_add(u16::from(len));
+        if new_sp == sp.wrapping_sub(2) && new_pc != next {
+            let pushed = cpu.addring.get_word(new_sp);
+            if pushed == next || pushed == pc {
+                self.enter(new_pc, new_sp);
+            }
+        }
+        self.last = Some((new_pc, new_sp));
+    }
+
+    fn enter(&mut self, entry: u16, sp: u16) {
+        self.routines.entry(entry).or_default().calls += 1;
+        self.stack.push(Frame {
+            entry,
+            sp,
+            start: self.total_cycles,
+        });
+    }
+
+    fn leave(&mut self) {
+        let frame = self.stack.pop().unwrap();
+        if self.stack.iter().all(|f| f.entry != frame.entry) {
+            let routine = self.routines.entry(frame.entry).or_default();
+            routine.inclusive_cycles += self.total_cycles - frame.start;
+        }
+    }
+
+    /// Times `addr` was executed
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.hits[addr as usize]
+    }
+
+    /// Cycles spent in the instruction at `addr`
+    pub fn cycles(&self, addr: u16) -> u64 {
+        self.cycles[addr as usize]
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Statistics of the routine entered at `entry`. Calls still running have no
+    /// inclusive cycles yet.
+    pub fn routine(&self, entry: u16) -> Option<Routine> {
+        self.routines.get(&entry).copied()
+    }
+
+    /// Instruction addresses executed at least once, in order
+    pub fn executed(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..=0xffff).filter(|&addr| self.hits[addr as usize] > 0)
+    }
+
+    /// Bytes of `start..end` covered by executed instructions, read from `bus`
+    pub fn coverage(&self, bus: &(impl AddressBus + ?Sized), start: u16, end: u16) -> usize {
+        let mut covered = vec![false; usize::from(end.wrapping_sub(start))];
+        for addr in start..end {
+            if self.hits[addr as usize] > 0 {
+                let len = OP_LENGTHS[bus.get_mem(addr) as usize];
+                for i in 0..u16::from(len) {
+                    if let Some(c) = covered.get_mut(usize::from(addr - start) + usize::from(i)) {
+                        *c = true;
+                    }
+                }
+            }
+        }
+        covered.iter().filter(|c| **c).count()
+    }
+
+    /// The `top` hottest instructions and routines
+    pub fn report(&self, top: usize) -> String {
+        let total = self.total_cycles.max(1) as f64;
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} instructions, {} cycles",
+            self.instructions, self.total_cycles
+        );
+
+        let mut hot: Vec<u16> = self.executed().collect();
+        hot.sort_by_key(|&addr| std::cmp::Reverse(self.cycles[addr as usize]));
+        let _ = writeln!(out, "\nhotspots:\n addr        hits       cycles      %");
+        for addr in hot.into_iter().take(top) {
+            let (hits, cycles) = (self.hits[addr as usize], self.cycles[addr as usize]);
+            let share = cycles as f64 * 100.0 / total;
+            let name = self.symbols.locate(addr).unwrap_or_default();
+            let line = format!(
+                " {:04x} {:>11} {:>12} {:>6.2}  {}",
+                addr, hits, cycles, share, name
+            );
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+
+        let mut routines: Vec<(&u16, &Routine)> = self.routines.iter().collect();
+        routines.sort_by_key(|(entry, r)| (std::cmp::Reverse(r.inclusive_cycles), **entry));
+        let _ = writeln!(
+            out,
+            "\nroutines:\n entry       calls         self    inclusive      %"
+        );
+        for (entry, r) in routines.into_iter().take(top) {
+            let share = r.inclusive_cycles as f64 * 100.0 / total;
+            let name = self.symbols.name(*entry).unwrap_or_default();
+            let line = format!(
+                " {:04x} {:>11} {:>12} {:>12} {:>6.2}  {}",
+                entry, r.calls, r.self_cycles, r.inclusive_cycles, share, name
+            );
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+        out
+    }
+
+    /// Disassembly of `start..end` with hits and cycles in front of every instruction, labelled
+    /// with the symbols. Decoding restarts at executed addresses, so code after data lines up;
+    /// bytes skipped that way are shown as `DB`.
+    pub fn annotate(&self, bus: &(impl AddressBus + ?Sized), start: u16, end: u16) -> String {
+        let mut out = String::new();
+        let executed = |addr: u16| self.hits[addr as usize] > 0;
+        for entry in entries(bus, start, end, &self.symbols, executed) {
+            let counts = match &entry {
+                Entry::Label(_) => {
+                    let _ = writeln!(out, "{}", entry);
+                    continue;
+                }
+                Entry::Code(instruction) if executed(instruction.addr) => {
+                    let addr = instruction.addr as usize;
+                    format!("{:>11} {:>12}", self.hits[addr], self.cycles[addr])
+                }
+                _ => String::new(),
+            };
+            let _ = writeln!(out, "{:>24}  {}", counts, entry);
+        }
+        out
+    }
+}
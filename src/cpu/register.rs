@@ -1,4 +1,5 @@
-/// The register of Intel 8080
+/// The register of Intel 8080, plus the extra ones of the 8085 and the Z80
+#[derive(Default)]
 pub struct Register {
     pub a: u8,
     pub b: u8,
@@ -23,6 +24,26 @@ pub struct Register {
     /// AC (auxillary carry) is used mostly for BCD (binary coded decimal) math.
     /// Read the data book for more details, Space Invaders doesn't use it.
     pub flag_ac: bool,
+    /// V (overflow), 8085 only: two's complement overflow of the last arithmetic operation
+    pub flag_v: bool,
+    /// K (X5), 8085 only: INX/DCX wrapped around, or the signed comparison result of the
+    /// last arithmetic operation
+    pub flag_k: bool,
+    /// N (add/subtract), Z80 only: the last arithmetic operation was a subtraction, for DAA
+    pub flag_n: bool,
+    /// Undocumented bits 3 (X) and 5 (Y) of the Z80 flags
+    pub flag_x: bool,
+    pub flag_y: bool,
+    // Z80: 索引寄存器 IX/IY, interrupt vector I, refresh counter R
+    pub ix: u16,
+    pub iy: u16,
+    pub i: u8,
+    pub r: u8,
+    /// Z80 shadow registers AF', BC', DE', HL'
+    pub af_alt: u16,
+    pub bc_alt: u16,
+    pub de_alt: u16,
+    pub hl_alt: u16,
 }
 
 impl Register {
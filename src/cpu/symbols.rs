@@ -0,0 +1,148 @@
+//! Symbol files: names for routines, tables and variables, used by the disassembler and the
+//! profiler.
+//!
+//! One symbol per line: `address kind name`, then an optional `; comment`. The address is hex,
+//! the kind is `code` for entry points and `data` for tables and RAM variables. Lines starting
+//! with `;` are comments.
+//!
+//! ```text
+//! ; Space Invaders
+//! 08ff code DrawChar       ; Draw the character in A at HL
+//! 20c0 data isrDelay
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// Where instructions start
+    Code,
+    /// Bytes that are not instructions: tables, text, RAM variables
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub addr: u16,
+    pub kind: SymbolKind,
+    pub name: String,
+    pub comment: Option<String>,
+}
+
+/// Symbols by address, at most one per address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: BTreeMap<u16, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a symbol file's text. A later line for the same address wins.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut table = Self::new();
+        for (no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let symbol = parse_symbol(line).ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("bad symbol at line {}: {}", no + 1, line),
+                )
+            })?;
+            table.insert(symbol);
+        }
+        Ok(table)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Add `symbol`, replacing the one at its address
+    pub fn insert(&mut self, symbol: Symbol) {
+        self.symbols.insert(symbol.addr, symbol);
+    }
+
+    /// Add all symbols of `other`, replacing ours at the same addresses
+    pub fn merge(&mut self, other: SymbolTable) {
+        self.symbols.extend(other.symbols);
+    }
+
+    pub fn get(&self, addr: u16) -> Option<&Symbol> {
+        self.symbols.get(&addr)
+    }
+
+    /// Name of the symbol at `addr`
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.get(addr).map(|s| s.name.as_str())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.values().find(|s| s.name == name)
+    }
+
+    /// `addr` relative to the closest code symbol at or below it: `DrawChar` or `DrawChar+3`
+    pub fn locate(&self, addr: u16) -> Option<String> {
+        let symbol = self
+            .symbols
+            .range(..=addr)
+            .rev()
+            .map(|(_, s)| s)
+            .find(|s| s.kind == SymbolKind::Code)?;
+        Some(match addr - symbol.addr {
+            0 => symbol.name.clone(),
+            offset => format!("{}+{}", symbol.name, offset),
+        })
+    }
+
+    /// Address of the first symbol after `addr`
+    pub fn next_after(&self, addr: u16) -> Option<u16> {
+        let start = addr.checked_add(1)?;
+        self.symbols.range(start..).next().map(|(a, _)| *a)
+    }
+
+    /// All symbols, by address
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+fn parse_symbol(line: &str) -> Option<Symbol> {
+    let (fields, comment) = match line.split_once(';') {
+        Some((fields, comment)) => (fields, Some(comment.trim()).filter(|c| !c.is_empty())),
+        None => (line, None),
+    };
+    let mut fields = fields.split_whitespace();
+    let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let kind = match fields.next()? {
+        "code" => SymbolKind::Code,
+        "data" => SymbolKind::Data,
+        _ => return None,
+    };
+    let name = fields.next()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(Symbol {
+        addr,
+        kind,
+        name: name.to_string(),
+        comment: comment.map(str::to_string),
+    })
+}
@@ -0,0 +1,958 @@
+use crate::cpu::{Cpu, IO};
+use crate::memory::AddressBus;
+
+// Z80 flag bits: S Z Y H X P/V N C
+const FLAG_S: u8 = 0x80;
+const FLAG_Z: u8 = 0x40;
+const FLAG_Y: u8 = 0x20;
+const FLAG_H: u8 = 0x10;
+const FLAG_X: u8 = 0x08;
+const FLAG_P: u8 = 0x04;
+const FLAG_N: u8 = 0x02;
+const FLAG_C: u8 = 0x01;
+
+/// Register that stands in for HL after a DD or FD prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    Hl,
+    Ix,
+    Iy,
+}
+
+/// Interrupt state the Z80 adds to the 8080 one. IFF1 is `Cpu::inte`.
+#[derive(Default)]
+pub(crate) struct Z80State {
+    iff2: bool,
+    /// Interrupt mode set by IM 0/1/2
+    im: u8,
+    nmi: bool,
+}
+
+impl Z80State {
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Cycles of each unprefixed opcode on the Z80, without the extra cycles of a taken branch
+//  0   1   2   3   4   5   6   7   8   9   a   b   c   d   e   f
+#[rustfmt::skip]
+const OP_CYCLES_Z80: [u8; 256] = [
+     4, 10,  7,  6,  4,  4,  7,  4,  4, 11,  7,  6,  4,  4,  7,  4, // 0
+     8, 10,  7,  6,  4,  4,  7,  4, 12, 11,  7,  6,  4,  4,  7,  4, // 1
+     7, 10, 16,  6,  4,  4,  7,  4,  7, 11, 16,  6,  4,  4,  7,  4, // 2
+     7, 10, 13,  6, 11, 11, 10,  4,  7, 11, 13,  6,  4,  4,  7,  4, // 3
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 4
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 5
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 6
+     7,  7,  7,  7,  7,  7,  4,  7,  4,  4,  4,  4,  4,  4,  7,  4, // 7
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 8
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // 9
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // a
+     4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4, // b
+     5, 10, 10, 10, 10, 11,  7, 11,  5, 10, 10,  0, 10, 17,  7, 11, // c
+     5, 10, 10, 11, 10, 11,  7, 11,  5,  4, 10, 11, 10,  0,  7, 11, // d
+     5, 10, 10, 19, 10, 11,  7, 11,  5,  4, 10,  4, 10,  0,  7, 11, // e
+     5, 10, 10,  4, 10, 11,  7, 11,  5,  6, 10,  4, 10,  0,  7, 11, // f
+];
+
+/// S, Z, Y and X of a result
+fn szxy(value: u8) -> u8 {
+    (value & (FLAG_S | FLAG_Y | FLAG_X)) | if value == 0 { FLAG_Z } else { 0 }
+}
+
+fn parity(value: u8) -> u8 {
+    if value.count_ones().is_multiple_of(2) {
+        FLAG_P
+    } else {
+        0
+    }
+}
+
+impl<B: AddressBus, I: IO + ?Sized> Cpu<B, I> {
+    /// Pulse the NMI line: taken before the next instruction even with interrupts off
+    pub fn nmi(&mut self) {
+        self.z80.nmi = true;
+    }
+
+    /// Interrupt mode selected by the last IM instruction
+    pub fn interrupt_mode(&self) -> u8 {
+        self.z80.im
+    }
+
+    /// F register of the Z80
+    pub fn z80_flags(&self) -> u8 {
+        let r = &self.register;
+        (u8::from(r.flag_s) << 7)
+            | (u8::from(r.flag_z) << 6)
+            | (u8::from(r.flag_y) << 5)
+            | (u8::from(r.flag_ac) << 4)
+            | (u8::from(r.flag_x) << 3)
+            | (u8::from(r.flag_p) << 2)
+            | (u8::from(r.flag_n) << 1)
+            | u8::from(r.flag_cy)
+    }
+
+    pub fn set_z80_flags(&mut self, flags: u8) {
+        let r = &mut self.register;
+        r.flag_s = flags & FLAG_S != 0;
+        r.flag_z = flags & FLAG_Z != 0;
+        r.flag_y = flags & FLAG_Y != 0;
+        r.flag_ac = flags & FLAG_H != 0;
+        r.flag_x = flags & FLAG_X != 0;
+        r.flag_p = flags & FLAG_P != 0;
+        r.flag_n = flags & FLAG_N != 0;
+        r.flag_cy = flags & FLAG_C != 0;
+    }
+
+    /// NMI, then INT in the current interrupt mode. IM 0 runs the instruction on the
+    /// data bus like the 8080, IM 1 is RST 38h and IM 2 calls through the table at I.
+    pub(crate) fn service_z80(&mut self, enabled: bool, io: &mut I) -> Option<u8> {
+        if self.z80.nmi {
+            self.z80.nmi = false;
+            self.inte = false;
+            self.halted = false;
+            self.stack_add(self.register.pc);
+            self.register.pc = 0x66;
+            return Some(11);
+        }
+        if !enabled {
+            return None;
+        }
+        let instruction = self.int_request.take()?;
+        self.z80.iff2 = false;
+        if self.z80.im == 0 {
+            return Some(self.acknowledge(instruction, io) + 2);
+        }
+        self.inte = false;
+        self.halted = false;
+        self.stack_add(self.register.pc);
+        if self.z80.im == 1 {
+            self.register.pc = 0x38;
+            Some(13)
+        } else {
+            let vector = (u16::from(self.register.i) << 8) | u16::from(instruction[0]);
+            self.register.pc = self.addring.get_word(vector);
+            Some(19)
+        }
+    }
+
+    /// Opcode fetch (M1), counts up the low 7 bits of R
+    fn fetch_opcode(&mut self) -> u8 {
+        let r = self.register.r;
+        self.register.r = (r & 0x80) | (r.wrapping_add(1) & 0x7f);
+        self.get_next_byte()
+    }
+
+    pub(crate) fn execute_z80(&mut self, io: &mut I) -> u8 {
+        let mut op = self.fetch_opcode();
+        let mut index = Index::Hl;
+        let mut cycles = 0;
+        // Only the last of several DD/FD prefixes counts, the others act as NOPs
+        while op == 0xdd || op == 0xfd {
+            index = if op == 0xdd { Index::Ix } else { Index::Iy };
+            cycles += 4;
+            op = self.fetch_opcode();
+        }
+        cycles
+            + match op {
+                0xcb => self.z80_cb(index),
+                0xed => self.z80_ed(io),
+                _ => self.z80_main(op, index, io),
+            }
+    }
+
+    fn index_reg(&self, index: Index) -> u16 {
+        match index {
+            Index::Hl => self.register.get_hl(),
+            Index::Ix => self.register.ix,
+            Index::Iy => self.register.iy,
+        }
+    }
+
+    fn set_index_reg(&mut self, index: Index, value: u16) {
+        match index {
+            Index::Hl => self.register.set_hl(value),
+            Index::Ix => self.register.ix = value,
+            Index::Iy => self.register.iy = value,
+        }
+    }
+
+    /// Address of (HL), or of (IX+d)/(IY+d) reading the displacement
+    fn index_addr(&mut self, index: Index) -> u16 {
+        match index {
+            Index::Hl => self.register.get_hl(),
+            _ => {
+                let d = self.get_next_byte() as i8;
+                self.index_reg(index).wrapping_add(d as u16)
+            }
+        }
+    }
+
+    /// r[n]: B C D E H L - A, H and L become IXH/IXL or IYH/IYL after a prefix
+    fn reg8(&self, n: u8, index: Index) -> u8 {
+        match n {
+            0 => self.register.b,
+            1 => self.register.c,
+            2 => self.register.d,
+            3 => self.register.e,
+            4 => (self.index_reg(index) >> 8) as u8,
+            5 => self.index_reg(index) as u8,
+            _ => self.register.a,
+        }
+    }
+
+    fn set_reg8(&mut self, n: u8, index: Index, value: u8) {
+        match n {
+            0 => self.register.b = value,
+            1 => self.register.c = value,
+            2 => self.register.d = value,
+            3 => self.register.e = value,
+            4 => {
+                let low = self.index_reg(index) & 0x00ff;
+                self.set_index_reg(index, (u16::from(value) << 8) | low);
+            }
+            5 => {
+                let high = self.index_reg(index) & 0xff00;
+                self.set_index_reg(index, high | u16::from(value));
+            }
+            _ => self.register.a = value,
+        }
+    }
+
+    /// rp[p]: BC DE HL SP
+    fn rp(&self, p: u8, index: Index) -> u16 {
+        match p {
+            0 => self.register.get_bc(),
+            1 => self.register.get_de(),
+            2 => self.index_reg(index),
+            _ => self.register.sp,
+        }
+    }
+
+    fn set_rp(&mut self, p: u8, index: Index, value: u16) {
+        match p {
+            0 => self.register.set_bc(value),
+            1 => self.register.set_de(value),
+            2 => self.set_index_reg(index, value),
+            _ => self.register.sp = value,
+        }
+    }
+
+    /// cc[y]: NZ Z NC C PO PE P M
+    fn z80_condition(&self, y: u8) -> bool {
+        let r = &self.register;
+        match y {
+            0 => !r.flag_z,
+            1 => r.flag_z,
+            2 => !r.flag_cy,
+            3 => r.flag_cy,
+            4 => !r.flag_p,
+            5 => r.flag_p,
+            6 => !r.flag_s,
+            _ => r.flag_s,
+        }
+    }
+
+    fn port_in(&mut self, io: &mut I, port: u8) -> u8 {
+        let a = self.register.a;
+        io.input(&mut self.register, port);
+        let value = self.register.a;
+        self.register.a = a;
+        value
+    }
+
+    fn port_out(&mut self, io: &mut I, port: u8, value: u8) {
+        let a = self.register.a;
+        self.register.a = value;
+        io.output(&mut self.register, port);
+        self.register.a = a;
+    }
+
+    fn z80_main(&mut self, op: u8, index: Index, io: &mut I) -> u8 {
+        let x = op >> 6;
+        let y = (op >> 3) & 7;
+        let z = op & 7;
+        let p = y >> 1;
+        let q = y & 1;
+        let mut cycles = OP_CYCLES_Z80[op as usize];
+        // (HL) operands become (IX+d)/(IY+d)
+        let memory = match x {
+            0 => (4..=6).contains(&z) && y == 6,
+            1 => (y == 6 || z == 6) && op != 0x76,
+            2 => z == 6,
+            _ => false,
+        };
+        if memory && index != Index::Hl {
+            cycles += if op == 0x36 { 5 } else { 8 };
+        }
+        match x {
+            0 => match z {
+                0 => match y {
+                    0 => {}
+                    // EX AF,AF'
+                    1 => {
+                        let af = (u16::from(self.register.a) << 8) | u16::from(self.z80_flags());
+                        let alt = std::mem::replace(&mut self.register.af_alt, af);
+                        self.register.a = (alt >> 8) as u8;
+                        self.set_z80_flags(alt as u8);
+                    }
+                    // DJNZ d
+                    2 => {
+                        let d = self.get_next_byte() as i8;
+                        self.register.b = self.register.b.wrapping_sub(1);
+                        if self.register.b != 0 {
+                            self.register.pc = self.register.pc.wrapping_add(d as u16);
+                            cycles += 5;
+                        }
+                    }
+                    // JR d
+                    3 => {
+                        let d = self.get_next_byte() as i8;
+                        self.register.pc = self.register.pc.wrapping_add(d as u16);
+                    }
+                    // JR cc,d
+                    _ => {
+                        let d = self.get_next_byte() as i8;
+                        if self.z80_condition(y - 4) {
+                            self.register.pc = self.register.pc.wrapping_add(d as u16);
+                            cycles += 5;
+                        }
+                    }
+                },
+                1 => {
+                    if q == 0 {
+                        let word = self.get_next_word();
+                        self.set_rp(p, index, word);
+                    } else {
+                        let result = self.z80_add16(self.index_reg(index), self.rp(p, index));
+                        self.set_index_reg(index, result);
+                    }
+                }
+                2 => match (q, p) {
+                    (0, 0) => self
+                        .addring
+                        .set_mem(self.register.get_bc(), self.register.a),
+                    (0, 1) => self
+                        .addring
+                        .set_mem(self.register.get_de(), self.register.a),
+                    (0, 2) => {
+                        let addr = self.get_next_word();
+                        self.addring.set_word(addr, self.index_reg(index));
+                    }
+                    (0, _) => {
+                        let addr = self.get_next_word();
+                        self.addring.set_mem(addr, self.register.a);
+                    }
+                    (_, 0) => self.register.a = self.addring.get_mem(self.register.get_bc()),
+                    (_, 1) => self.register.a = self.addring.get_mem(self.register.get_de()),
+                    (_, 2) => {
+                        let addr = self.get_next_word();
+                        let word = self.addring.get_word(addr);
+                        self.set_index_reg(index, word);
+                    }
+                    (_, _) => {
+                        let addr = self.get_next_word();
+                        self.register.a = self.addring.get_mem(addr);
+                    }
+                },
+                // INC/DEC rp, no flags
+                3 => {
+                    let value = self.rp(p, index);
+                    let value = if q == 0 {
+                        value.wrapping_add(1)
+                    } else {
+                        value.wrapping_sub(1)
+                    };
+                    self.set_rp(p, index, value);
+                }
+                4 | 5 => {
+                    let inc = |cpu: &mut Self, v| {
+                        if z == 4 {
+                            cpu.z80_inc(v)
+                        } else {
+                            cpu.z80_dec(v)
+                        }
+                    };
+                    if y == 6 {
+                        let addr = self.index_addr(index);
+                        let value = self.addring.get_mem(addr);
+                        let value = inc(self, value);
+                        self.addring.set_mem(addr, value);
+                    } else {
+                        let value = self.reg8(y, index);
+                        let value = inc(self, value);
+                        self.set_reg8(y, index, value);
+                    }
+                }
+                // LD r,n
+                6 => {
+                    if y == 6 {
+                        let addr = self.index_addr(index);
+                        let value = self.get_next_byte();
+                        self.addring.set_mem(addr, value);
+                    } else {
+                        let value = self.get_next_byte();
+                        self.set_reg8(y, index, value);
+                    }
+                }
+                _ => self.z80_misc(y),
+            },
+            1 => {
+                if op == 0x76 {
+                    self.halted = true;
+                } else if y == 6 {
+                    let addr = self.index_addr(index);
+                    self.addring.set_mem(addr, self.reg8(z, Index::Hl));
+                } else if z == 6 {
+                    let addr = self.index_addr(index);
+                    let value = self.addring.get_mem(addr);
+                    self.set_reg8(y, Index::Hl, value);
+                } else {
+                    let value = self.reg8(z, index);
+                    self.set_reg8(y, index, value);
+                }
+            }
+            2 => {
+                let value = if z == 6 {
+                    let addr = self.index_addr(index);
+                    self.addring.get_mem(addr)
+                } else {
+                    self.reg8(z, index)
+                };
+                self.z80_alu(y, value);
+            }
+            _ => match z {
+                // RET cc
+                0 => {
+                    if self.z80_condition(y) {
+                        self.register.pc = self.stack_pop();
+                        cycles += 6;
+                    }
+                }
+                1 => match (q, p) {
+                    (0, 3) => {
+                        let af = self.stack_pop();
+                        self.register.a = (af >> 8) as u8;
+                        self.set_z80_flags(af as u8);
+                    }
+                    (0, _) => {
+                        let value = self.stack_pop();
+                        self.set_rp(p, index, value);
+                    }
+                    (_, 0) => self.register.pc = self.stack_pop(),
+                    // EXX
+                    (_, 1) => {
+                        let r = &mut self.register;
+                        let (bc, de, hl) = (r.bc_alt, r.de_alt, r.hl_alt);
+                        (r.bc_alt, r.de_alt, r.hl_alt) = (r.get_bc(), r.get_de(), r.get_hl());
+                        r.set_bc(bc);
+                        r.set_de(de);
+                        r.set_hl(hl);
+                    }
+                    // JP (HL)
+                    (_, 2) => self.register.pc = self.index_reg(index),
+                    // LD SP,HL
+                    (_, _) => self.register.sp = self.index_reg(index),
+                },
+                // JP cc,nn
+                2 => {
+                    let addr = self.get_next_word();
+                    if self.z80_condition(y) {
+                        self.register.pc = addr;
+                    }
+                }
+                3 => match y {
+                    0 => self.register.pc = self.get_next_word(),
+                    // OUT (n),A
+                    2 => {
+                        let port = self.get_next_byte();
+                        io.output(&mut self.register, port);
+                    }
+                    // IN A,(n)
+                    3 => {
+                        let port = self.get_next_byte();
+                        io.input(&mut self.register, port);
+                    }
+                    // EX (SP),HL
+                    4 => {
+                        let sp = self.register.sp;
+                        let value = self.addring.get_word(sp);
+                        self.stack_write(sp, self.index_reg(index));
+                        self.set_index_reg(index, value);
+                    }
+                    // EX DE,HL, never IX/IY
+                    5 => {
+                        let de = self.register.get_de();
+                        self.register.set_de(self.register.get_hl());
+                        self.register.set_hl(de);
+                    }
+                    6 => {
+                        self.inte = false;
+                        self.z80.iff2 = false;
+                        self.ei_delay = false;
+                    }
+                    _ => {
+                        self.inte = true;
+                        self.z80.iff2 = true;
+                        self.ei_delay = true;
+                    }
+                },
+                // CALL cc,nn
+                4 => {
+                    let addr = self.get_next_word();
+                    if self.z80_condition(y) {
+                        self.stack_add(self.register.pc);
+                        self.register.pc = addr;
+                        cycles += 7;
+                    }
+                }
+                5 => {
+                    if q == 0 {
+                        let value = if p == 3 {
+                            (u16::from(self.register.a) << 8) | u16::from(self.z80_flags())
+                        } else {
+                            self.rp(p, index)
+                        };
+                        self.stack_add(value);
+                    } else {
+                        let addr = self.get_next_word();
+                        self.stack_add(self.register.pc);
+                        self.register.pc = addr;
+                    }
+                }
+                6 => {
+                    let value = self.get_next_byte();
+                    self.z80_alu(y, value);
+                }
+                _ => {
+                    self.stack_add(self.register.pc);
+                    self.register.pc = u16::from(op & 0x38);
+                }
+            },
+        }
+        cycles
+    }
+
+    /// RLCA RRCA RLA RRA DAA CPL SCF CCF
+    fn z80_misc(&mut self, y: u8) {
+        let a = self.register.a;
+        let f = self.z80_flags();
+        let (a, f) = match y {
+            0..=3 => {
+                let c = f & FLAG_C;
+                let (r, carry) = match y {
+                    0 => (a.rotate_left(1), a >> 7),
+                    1 => (a.rotate_right(1), a & 1),
+                    2 => ((a << 1) | c, a >> 7),
+                    _ => ((a >> 1) | (c << 7), a & 1),
+                };
+                let f = (f & (FLAG_S | FLAG_Z | FLAG_P)) | (r & (FLAG_X | FLAG_Y)) | carry;
+                (r, f)
+            }
+            4 => {
+                let mut diff = 0;
+                let mut carry = f & FLAG_C;
+                if f & FLAG_H != 0 || a & 0x0f > 9 {
+                    diff |= 0x06;
+                }
+                if carry != 0 || a > 0x99 {
+                    diff |= 0x60;
+                    carry = FLAG_C;
+                }
+                let (r, half) = if f & FLAG_N != 0 {
+                    (a.wrapping_sub(diff), f & FLAG_H != 0 && a & 0x0f < 6)
+                } else {
+                    (a.wrapping_add(diff), a & 0x0f > 9)
+                };
+                let half = if half { FLAG_H } else { 0 };
+                (r, szxy(r) | parity(r) | half | (f & FLAG_N) | carry)
+            }
+            5 => {
+                let r = !a;
+                let f = (f & (FLAG_S | FLAG_Z | FLAG_P | FLAG_C))
+                    | (r & (FLAG_X | FLAG_Y))
+                    | FLAG_H
+                    | FLAG_N;
+                (r, f)
+            }
+            6 => {
+                let f = (f & (FLAG_S | FLAG_Z | FLAG_P)) | (a & (FLAG_X | FLAG_Y)) | FLAG_C;
+                (a, f)
+            }
+            _ => {
+                let half = (f & FLAG_C) << 4;
+                let f = (f & (FLAG_S | FLAG_Z | FLAG_P))
+                    | (a & (FLAG_X | FLAG_Y))
+                    | half
+                    | ((f & FLAG_C) ^ FLAG_C);
+                (a, f)
+            }
+        };
+        self.register.a = a;
+        self.set_z80_flags(f);
+    }
+
+    /// alu[y]: ADD ADC SUB SBC AND XOR OR CP
+    fn z80_alu(&mut self, y: u8, value: u8) {
+        let a = self.register.a;
+        let (r, f) = match y {
+            0 => self.z80_add(a, value, false),
+            1 => self.z80_add(a, value, true),
+            2 => self.z80_sub(a, value, false),
+            3 => self.z80_sub(a, value, true),
+            4 => {
+                let r = a & value;
+                (r, szxy(r) | parity(r) | FLAG_H)
+            }
+            5 => {
+                let r = a ^ value;
+                (r, szxy(r) | parity(r))
+            }
+            6 => {
+                let r = a | value;
+                (r, szxy(r) | parity(r))
+            }
+            _ => {
+                // CP takes X and Y from the operand
+                let (_, f) = self.z80_sub(a, value, false);
+                (a, (f & !(FLAG_X | FLAG_Y)) | (value & (FLAG_X | FLAG_Y)))
+            }
+        };
+        self.register.a = r;
+        self.set_z80_flags(f);
+    }
+
+    fn z80_add(&self, a: u8, value: u8, carry: bool) -> (u8, u8) {
+        let c = u8::from(carry && self.register.flag_cy);
+        let sum = u16::from(a) + u16::from(value) + u16::from(c);
+        let r = sum as u8;
+        let overflow = ((a ^ !value) & (a ^ r) & 0x80) >> 5;
+        let f = szxy(r) | ((a ^ value ^ r) & FLAG_H) | overflow | u8::from(sum > 0xff);
+        (r, f)
+    }
+
+    fn z80_sub(&self, a: u8, value: u8, carry: bool) -> (u8, u8) {
+        let c = u8::from(carry && self.register.flag_cy);
+        let diff = u16::from(a)
+            .wrapping_sub(u16::from(value))
+            .wrapping_sub(u16::from(c));
+        let r = diff as u8;
+        let overflow = ((a ^ value) & (a ^ r) & 0x80) >> 5;
+        let f = szxy(r) | ((a ^ value ^ r) & FLAG_H) | overflow | FLAG_N | u8::from(diff > 0xff);
+        (r, f)
+    }
+
+    fn z80_inc(&mut self, value: u8) -> u8 {
+        let r = value.wrapping_add(1);
+        let mut f = (self.z80_flags() & FLAG_C) | szxy(r);
+        if r & 0x0f == 0 {
+            f |= FLAG_H;
+        }
+        if r == 0x80 {
+            f |= FLAG_P;
+        }
+        self.set_z80_flags(f);
+        r
+    }
+
+    fn z80_dec(&mut self, value: u8) -> u8 {
+        let r = value.wrapping_sub(1);
+        let mut f = (self.z80_flags() & FLAG_C) | szxy(r) | FLAG_N;
+        if r & 0x0f == 0x0f {
+            f |= FLAG_H;
+        }
+        if r == 0x7f {
+            f |= FLAG_P;
+        }
+        self.set_z80_flags(f);
+        r
+    }
+
+    /// ADD HL,rp: S, Z and P/V are kept
+    fn z80_add16(&mut self, a: u16, value: u16) -> u16 {
+        let sum = u32::from(a) + u32::from(value);
+        let r = sum as u16;
+        let f = (self.z80_flags() & (FLAG_S | FLAG_Z | FLAG_P))
+            | (((a ^ value ^ r) >> 8) as u8 & FLAG_H)
+            | ((r >> 8) as u8 & (FLAG_X | FLAG_Y))
+            | u8::from(sum > 0xffff);
+        self.set_z80_flags(f);
+        r
+    }
+
+    /// ADC HL,rp and SBC HL,rp
+    fn z80_adc16(&mut self, value: u16, subtract: bool) {
+        let a = self.register.get_hl();
+        let c = u32::from(self.register.flag_cy);
+        let (result, overflow) = if subtract {
+            let result = u32::from(a).wrapping_sub(u32::from(value)).wrapping_sub(c);
+            (result, (a ^ value) & (a ^ result as u16) & 0x8000)
+        } else {
+            let result = u32::from(a) + u32::from(value) + c;
+            (result, (a ^ !value) & (a ^ result as u16) & 0x8000)
+        };
+        let r = result as u16;
+        let mut f = ((r >> 8) as u8 & (FLAG_S | FLAG_X | FLAG_Y))
+            | (((a ^ value ^ r) >> 8) as u8 & FLAG_H)
+            | (overflow >> 13) as u8
+            | u8::from(result > 0xffff);
+        if r == 0 {
+            f |= FLAG_Z;
+        }
+        if subtract {
+            f |= FLAG_N;
+        }
+        self.register.set_hl(r);
+        self.set_z80_flags(f);
+    }
+
+    /// rot[y]: RLC RRC RL RR SLA SRA SLL SRL
+    fn z80_rot(&mut self, y: u8, value: u8) -> u8 {
+        let c = u8::from(self.register.flag_cy);
+        let (r, carry) = match y {
+            0 => (value.rotate_left(1), value >> 7),
+            1 => (value.rotate_right(1), value & 1),
+            2 => ((value << 1) | c, value >> 7),
+            3 => ((value >> 1) | (c << 7), value & 1),
+            4 => (value << 1, value >> 7),
+            5 => ((value >> 1) | (value & 0x80), value & 1),
+            // SLL, undocumented: shifts a 1 in
+            6 => ((value << 1) | 1, value >> 7),
+            _ => (value >> 1, value & 1),
+        };
+        self.set_z80_flags(szxy(r) | parity(r) | carry);
+        r
+    }
+
+    /// CB prefix: rotates, BIT, RES and SET. After DD/FD it is DD CB d op on (IX+d),
+    /// where the result is also copied to r[z] unless z is 6.
+    fn z80_cb(&mut self, index: Index) -> u8 {
+        let (addr, op) = if index == Index::Hl {
+            (None, self.fetch_opcode())
+        } else {
+            let addr = self.index_addr(index);
+            (Some(addr), self.get_next_byte())
+        };
+        let x = op >> 6;
+        let y = (op >> 3) & 7;
+        let z = op & 7;
+        let addr = addr.or(if z == 6 {
+            Some(self.register.get_hl())
+        } else {
+            None
+        });
+        let value = match addr {
+            Some(addr) => self.addring.get_mem(addr),
+            None => self.reg8(z, Index::Hl),
+        };
+        let result = match x {
+            0 => self.z80_rot(y, value),
+            1 => {
+                // X and Y come from the value, or from the high byte of IX+d
+                let xy = match (index, addr) {
+                    (Index::Hl, _) | (_, None) => value,
+                    (_, Some(addr)) => (addr >> 8) as u8,
+                };
+                let bit = value & (1 << y);
+                let mut f = (self.z80_flags() & FLAG_C) | FLAG_H | (xy & (FLAG_X | FLAG_Y));
+                if bit == 0 {
+                    f |= FLAG_Z | FLAG_P;
+                }
+                f |= bit & FLAG_S;
+                self.set_z80_flags(f);
+                let cycles = if addr.is_none() { 8 } else { 12 };
+                return if index == Index::Hl { cycles } else { 16 };
+            }
+            2 => value & !(1 << y),
+            _ => value | (1 << y),
+        };
+        match addr {
+            Some(addr) => {
+                self.addring.set_mem(addr, result);
+                if index != Index::Hl && z != 6 {
+                    self.set_reg8(z, Index::Hl, result);
+                }
+            }
+            None => self.set_reg8(z, Index::Hl, result),
+        }
+        match (index, addr) {
+            (Index::Hl, None) => 8,
+            (Index::Hl, Some(_)) => 15,
+            _ => 19,
+        }
+    }
+
+    /// ED prefix. Undefined opcodes are 8 cycle NOPs.
+    fn z80_ed(&mut self, io: &mut I) -> u8 {
+        let op = self.fetch_opcode();
+        let x = op >> 6;
+        let y = (op >> 3) & 7;
+        let z = op & 7;
+        let p = y >> 1;
+        let q = y & 1;
+        match x {
+            1 => match z {
+                // IN r,(C), IN (C) only sets the flags
+                0 => {
+                    let value = self.port_in(io, self.register.c);
+                    if y != 6 {
+                        self.set_reg8(y, Index::Hl, value);
+                    }
+                    let f = (self.z80_flags() & FLAG_C) | szxy(value) | parity(value);
+                    self.set_z80_flags(f);
+                    12
+                }
+                // OUT (C),r, OUT (C),0
+                1 => {
+                    let value = if y == 6 { 0 } else { self.reg8(y, Index::Hl) };
+                    self.port_out(io, self.register.c, value);
+                    12
+                }
+                2 => {
+                    self.z80_adc16(self.rp(p, Index::Hl), q == 0);
+                    15
+                }
+                3 => {
+                    let addr = self.get_next_word();
+                    if q == 0 {
+                        self.addring.set_word(addr, self.rp(p, Index::Hl));
+                    } else {
+                        let value = self.addring.get_word(addr);
+                        self.set_rp(p, Index::Hl, value);
+                    }
+                    20
+                }
+                // NEG
+                4 => {
+                    let (r, f) = self.z80_sub(0, self.register.a, false);
+                    self.register.a = r;
+                    self.set_z80_flags(f);
+                    8
+                }
+                // RETN, RETI
+                5 => {
+                    self.register.pc = self.stack_pop();
+                    self.inte = self.z80.iff2;
+                    14
+                }
+                6 => {
+                    self.z80.im = [0, 0, 1, 2][(y & 3) as usize];
+                    8
+                }
+                _ => self.z80_ed_misc(y),
+            },
+            2 if z <= 3 && y >= 4 => self.z80_block(y, z, io),
+            _ => 8,
+        }
+    }
+
+    /// LD I,A  LD R,A  LD A,I  LD A,R  RRD  RLD
+    fn z80_ed_misc(&mut self, y: u8) -> u8 {
+        let f = self.z80_flags() & FLAG_C;
+        match y {
+            0 => self.register.i = self.register.a,
+            1 => self.register.r = self.register.a,
+            2 | 3 => {
+                let value = if y == 2 {
+                    self.register.i
+                } else {
+                    self.register.r
+                };
+                self.register.a = value;
+                let iff2 = if self.z80.iff2 { FLAG_P } else { 0 };
+                self.set_z80_flags(f | szxy(value) | iff2);
+            }
+            4 | 5 => {
+                let hl = self.register.get_hl();
+                let value = self.addring.get_mem(hl);
+                let a = self.register.a;
+                let (mem, low) = if y == 4 {
+                    ((a << 4) | (value >> 4), value & 0x0f)
+                } else {
+                    ((value << 4) | (a & 0x0f), value >> 4)
+                };
+                self.addring.set_mem(hl, mem);
+                let a = (a & 0xf0) | low;
+                self.register.a = a;
+                self.set_z80_flags(f | szxy(a) | parity(a));
+                return 18;
+            }
+            _ => {}
+        }
+        9
+    }
+
+    /// LDI CPI INI OUTI, the D versions when y is odd and the repeating ones from y 6
+    fn z80_block(&mut self, y: u8, z: u8, io: &mut I) -> u8 {
+        let step: u16 = if y & 1 == 0 { 1 } else { 0xffff };
+        let repeat = y >= 6;
+        let hl = self.register.get_hl();
+        self.register.set_hl(hl.wrapping_add(step));
+        let f = self.z80_flags();
+        let again = match z {
+            // LDI
+            0 => {
+                let value = self.addring.get_mem(hl);
+                let de = self.register.get_de();
+                self.addring.set_mem(de, value);
+                self.register.set_de(de.wrapping_add(step));
+                let bc = self.register.get_bc().wrapping_sub(1);
+                self.register.set_bc(bc);
+                let n = value.wrapping_add(self.register.a);
+                let mut f = (f & (FLAG_S | FLAG_Z | FLAG_C)) | (n & FLAG_X) | ((n << 4) & FLAG_Y);
+                if bc != 0 {
+                    f |= FLAG_P;
+                }
+                self.set_z80_flags(f);
+                bc != 0
+            }
+            // CPI
+            1 => {
+                let value = self.addring.get_mem(hl);
+                let a = self.register.a;
+                let r = a.wrapping_sub(value);
+                let half = (a ^ value ^ r) & FLAG_H;
+                let n = r.wrapping_sub(half >> 4);
+                let bc = self.register.get_bc().wrapping_sub(1);
+                self.register.set_bc(bc);
+                let mut f = (f & FLAG_C)
+                    | (r & FLAG_S)
+                    | half
+                    | (n & FLAG_X)
+                    | ((n << 4) & FLAG_Y)
+                    | FLAG_N;
+                if r == 0 {
+                    f |= FLAG_Z;
+                }
+                if bc != 0 {
+                    f |= FLAG_P;
+                }
+                self.set_z80_flags(f);
+                bc != 0 && r != 0
+            }
+            // INI
+            2 => {
+                let value = self.port_in(io, self.register.c);
+                self.addring.set_mem(hl, value);
+                self.register.b = self.register.b.wrapping_sub(1);
+                self.set_z80_flags((f & FLAG_C) | szxy(self.register.b) | FLAG_N);
+                self.register.b != 0
+            }
+            // OUTI
+            _ => {
+                self.register.b = self.register.b.wrapping_sub(1);
+                let value = self.addring.get_mem(hl);
+                self.port_out(io, self.register.c, value);
+                self.set_z80_flags((f & FLAG_C) | szxy(self.register.b) | FLAG_N);
+                self.register.b != 0
+            }
+        };
+        if repeat && again {
+            self.register.pc = self.register.pc.wrapping_sub(2);
+            21
+        } else {
+            16
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::cpu::PortDevice;
+
+/// Output latches that hold the last byte written to each of their ports, such as the
+/// sound board triggers. Reading one back returns the latched value.
+pub struct Latch {
+    values: [u8; 256],
+}
+
+impl Latch {
+    pub fn new() -> Self {
+        Self { values: [0; 256] }
+    }
+
+    pub fn get(&self, port: u8) -> u8 {
+        self.values[port as usize]
+    }
+}
+
+impl Default for Latch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PortDevice for Latch {
+    fn input(&mut self, port: u8) -> u8 {
+        self.get(port)
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        self.values[port as usize] = val;
+    }
+
+    fn reset(&mut self) {
+        self.values = [0; 256];
+    }
+}
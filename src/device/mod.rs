@@ -0,0 +1,7 @@
+mod latch;
+mod shifter;
+mod watchdog;
+
+pub use latch::Latch;
+pub use shifter::ShiftRegister;
+pub use watchdog::{Watchdog, WATCHDOG_FRAMES};
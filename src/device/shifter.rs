@@ -0,0 +1,40 @@
+use crate::cpu::PortDevice;
+
+/// MB14241 barrel shifter of the Midway 8080 boards. Bytes written to the data port enter
+/// a 16 bit register from the top; IN returns 8 bits of it, `count` bits from the top.
+pub struct ShiftRegister {
+    count_port: u8,
+    data_port: u8,
+    shift: u16,
+    count: u8,
+}
+
+impl ShiftRegister {
+    pub fn new(count_port: u8, data_port: u8) -> Self {
+        Self {
+            count_port,
+            data_port,
+            shift: 0,
+            count: 0,
+        }
+    }
+}
+
+impl PortDevice for ShiftRegister {
+    fn input(&mut self, _port: u8) -> u8 {
+        (self.shift >> (8 - self.count)) as u8
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        if port == self.count_port {
+            self.count = val & 0x07;
+        } else if port == self.data_port {
+            self.shift = (self.shift >> 8) | (u16::from(val) << 8);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.shift = 0;
+        self.count = 0;
+    }
+}
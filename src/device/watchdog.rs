@@ -0,0 +1,60 @@
+use crate::cpu::PortDevice;
+
+/// Frames without a kick before the Midway boards reset, 255 vblanks
+pub const WATCHDOG_FRAMES: u32 = 255;
+
+/// Watchdog counter: any OUT to its port kicks it, and the machine calls [Watchdog::tick]
+/// once per frame. When the count reaches the timeout the machine must be reset.
+pub struct Watchdog {
+    timeout: u32,
+    count: u32,
+    enabled: bool,
+}
+
+impl Watchdog {
+    pub fn new(timeout: u32) -> Self {
+        Self {
+            timeout,
+            count: 0,
+            enabled: true,
+        }
+    }
+
+    /// A disabled watchdog never fires, handy when stepping through code in a debugger
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.count = 0;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn kick(&mut self) {
+        self.count = 0;
+    }
+
+    /// Count a frame, returns true when the timeout elapsed. The counter starts over.
+    pub fn tick(&mut self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        self.count += 1;
+        if self.count < self.timeout {
+            return false;
+        }
+        eprintln!("Watchdog: no kick for {} frames, resetting", self.count);
+        self.count = 0;
+        true
+    }
+}
+
+impl PortDevice for Watchdog {
+    fn output(&mut self, _port: u8, _val: u8) {
+        self.kick();
+    }
+
+    fn reset(&mut self) {
+        self.kick();
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::memory::AddressBus;
+
+/// How a snapshot value must relate to the current one to stay a candidate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchCondition {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    Equal(u8),
+}
+
+/// Narrows a RAM range down to the addresses that hold a value of interest,
+/// by comparing successive snapshots.
+pub struct RamSearch {
+    start: u16,
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    /// Take the first snapshot of `start..=end`, every address is a candidate
+    pub fn new(bus: &dyn AddressBus, start: u16, end: u16) -> Self {
+        let snapshot = (start..=end).map(|addr| bus.get_mem(addr)).collect();
+        Self {
+            start,
+            snapshot,
+            candidates: (start..=end).collect(),
+        }
+    }
+
+    /// Keep the candidates matching `condition`, then take a new snapshot
+    pub fn filter(&mut self, bus: &dyn AddressBus, condition: SearchCondition) {
+        let start = self.start;
+        let snapshot = &mut self.snapshot;
+        self.candidates.retain(|&addr| {
+            let old = snapshot[(addr - start) as usize];
+            let new = bus.get_mem(addr);
+            match condition {
+                SearchCondition::Changed => new != old,
+                SearchCondition::Unchanged => new == old,
+                SearchCondition::Increased => new > old,
+                SearchCondition::Decreased => new < old,
+                SearchCondition::Equal(value) => new == value,
+            }
+        });
+        for (i, value) in snapshot.iter_mut().enumerate() {
+            *value = bus.get_mem(start + i as u16);
+        }
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+/// What a cheat code does with its address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatKind {
+    /// Write the value every frame
+    Freeze,
+    /// Write the value once, when the cheat is enabled
+    Patch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheatCode {
+    pub kind: CheatKind,
+    pub addr: u16,
+    pub value: u8,
+}
+
+/// A named group of codes, such as "Infinite lives"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<CheatCode>,
+    pub enabled: bool,
+    /// Whether the `Patch` codes were written since the cheat was enabled
+    patched: bool,
+}
+
+impl Cheat {
+    pub fn new(name: &str, codes: Vec<CheatCode>) -> Self {
+        Self {
+            name: name.to_string(),
+            codes,
+            enabled: true,
+            patched: false,
+        }
+    }
+}
+
+/// Applies cheats to the address bus once per frame.
+///
+/// Cheat file format:
+/// ```text
+/// ; comment
+/// [Infinite lives]
+/// freeze 21ff 03
+///
+/// [99 credits] off
+/// patch 20eb 99
+/// ```
+/// Addresses and values are hex, `off` after the name saves a disabled cheat.
+#[derive(Default)]
+pub struct CheatEngine {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut cheats: Vec<Cheat> = Vec::new();
+        for (no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let bad_line = || {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("bad cheat at line {}: {}", no + 1, line),
+                )
+            };
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, rest) = header.split_once(']').ok_or_else(bad_line)?;
+                let mut cheat = Cheat::new(name.trim(), Vec::new());
+                cheat.enabled = match rest.trim() {
+                    "" | "on" => true,
+                    "off" => false,
+                    _ => return Err(bad_line()),
+                };
+                cheats.push(cheat);
+                continue;
+            }
+            let code = parse_code(line).ok_or_else(bad_line)?;
+            cheats.last_mut().ok_or_else(bad_line)?.codes.push(code);
+        }
+        Ok(Self { cheats })
+    }
+
+    /// Load a cheat file, a missing file gives an empty engine
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for cheat in &self.cheats {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("[{}]", cheat.name));
+            if !cheat.enabled {
+                text.push_str(" off");
+            }
+            text.push('\n');
+            for code in &cheat.codes {
+                let kind = match code.kind {
+                    CheatKind::Freeze => "freeze",
+                    CheatKind::Patch => "patch",
+                };
+                text.push_str(&format!("{} {:04x} {:02x}\n", kind, code.addr, code.value));
+            }
+        }
+        text
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Add a cheat, replacing any cheat with the same name
+    pub fn add(&mut self, cheat: Cheat) {
+        match self.cheats.iter_mut().find(|c| c.name == cheat.name) {
+            Some(old) => *old = cheat,
+            None => self.cheats.push(cheat),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.cheats.retain(|c| c.name != name);
+    }
+
+    /// Returns false when there is no cheat called `name`
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.cheats.iter_mut().find(|c| c.name == name) {
+            Some(cheat) => {
+                if !cheat.enabled && enabled {
+                    cheat.patched = false;
+                }
+                cheat.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether `apply` would touch memory at all
+    pub fn is_active(&self) -> bool {
+        self.cheats.iter().any(|c| c.enabled && !c.codes.is_empty())
+    }
+
+    /// Call once per frame. Disabled cheats never touch the bus.
+    pub fn apply(&mut self, bus: &mut dyn AddressBus) {
+        for cheat in self.cheats.iter_mut().filter(|c| c.enabled) {
+            for code in &cheat.codes {
+                if code.kind == CheatKind::Freeze || !cheat.patched {
+                    bus.set_mem(code.addr, code.value);
+                }
+            }
+            cheat.patched = true;
+        }
+    }
+}
+
+fn parse_code(line: &str) -> Option<CheatCode> {
+    let mut fields = line.split_whitespace();
+    let kind = match fields.next()? {
+        "freeze" => CheatKind::Freeze,
+        "patch" => CheatKind::Patch,
+        _ => return None,
+    };
+    let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let value = u8::from_str_radix(fields.next()?, 16).ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(CheatCode { kind, addr, value })
+}
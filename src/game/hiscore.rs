@@ -0,0 +1,195 @@
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::memory::AddressBus;
+
+/// Built-in descriptors for the Midway 8080 games, in the spirit of MAME's `hiscore.dat`.
+///
+/// One or more `set:` lines name the ROM sets that share the entries below them, and each entry
+/// is `address,length,start,end` in hex. `start`/`end` are the values the first and last byte of
+/// the range must hold before it is safe to inject a saved score.
+pub const BUILTIN_HISCORE_DAT: &str = "\
+; Space Invaders keeps its high score as two BCD bytes in work RAM.
+invaders:
+20f4,2,00,00
+";
+
+/// A RAM range that holds part of the high score table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HiscoreEntry {
+    pub addr: u16,
+    pub len: u16,
+    /// Expected value of the first byte before loading
+    pub start: u8,
+    /// Expected value of the last byte before loading
+    pub end: u8,
+}
+
+/// Describes where a game keeps its high scores
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HiscoreDescriptor {
+    pub entries: Vec<HiscoreEntry>,
+}
+
+impl HiscoreDescriptor {
+    /// Find the descriptor of `set` in a `hiscore.dat` style text.
+    /// Returns `Ok(None)` when the set is not listed.
+    pub fn parse(text: &str, set: &str) -> io::Result<Option<Self>> {
+        let mut entries = Vec::new();
+        // Whether the set names seen since the last entry include `set`
+        let mut selected = false;
+        let mut in_names = false;
+        for (no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_suffix(':') {
+                if !in_names {
+                    selected = false;
+                    in_names = true;
+                }
+                selected |= name.trim() == set;
+                continue;
+            }
+            in_names = false;
+            if !selected {
+                continue;
+            }
+            entries.push(parse_entry(line).ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("bad hiscore entry at line {}: {}", no + 1, line),
+                )
+            })?);
+        }
+        Ok(if entries.is_empty() {
+            None
+        } else {
+            Some(Self { entries })
+        })
+    }
+
+    /// Descriptor of `set` from [BUILTIN_HISCORE_DAT]
+    pub fn builtin(set: &str) -> Option<Self> {
+        Self::parse(BUILTIN_HISCORE_DAT, set).unwrap()
+    }
+
+    /// Total number of bytes covered by all entries
+    pub fn size(&self) -> usize {
+        self.entries.iter().map(|e| e.len as usize).sum()
+    }
+
+    /// Whether the game has initialised the ranges, so a saved score will not be overwritten
+    pub fn is_safe(&self, bus: &dyn AddressBus) -> bool {
+        self.entries.iter().all(|e| {
+            let last = e.addr.wrapping_add(e.len.saturating_sub(1));
+            bus.get_mem(e.addr) == e.start && bus.get_mem(last) == e.end
+        })
+    }
+
+    /// Read all ranges into one buffer, in entry order
+    pub fn read(&self, bus: &dyn AddressBus) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.size());
+        for e in &self.entries {
+            for i in 0..e.len {
+                data.push(bus.get_mem(e.addr.wrapping_add(i)));
+            }
+        }
+        data
+    }
+
+    /// Write a buffer produced by [HiscoreDescriptor::read] back into RAM
+    pub fn write(&self, bus: &mut dyn AddressBus, data: &[u8]) {
+        let mut bytes = data.iter();
+        for e in &self.entries {
+            for i in 0..e.len {
+                match bytes.next() {
+                    Some(b) => bus.set_mem(e.addr.wrapping_add(i), *b),
+                    None => return,
+                }
+            }
+        }
+    }
+}
+
+fn parse_entry(line: &str) -> Option<HiscoreEntry> {
+    let mut fields = line.split(',').map(str::trim);
+    let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let len = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let start = u8::from_str_radix(fields.next()?, 16).ok()?;
+    let end = u8::from_str_radix(fields.next()?, 16).ok()?;
+    if fields.next().is_some() || len == 0 {
+        return None;
+    }
+    Some(HiscoreEntry {
+        addr,
+        len,
+        start,
+        end,
+    })
+}
+
+/// Keeps the high score of one ROM set in `<dir>/<set>.hi` across sessions
+pub struct Hiscore {
+    descriptor: HiscoreDescriptor,
+    path: PathBuf,
+    /// Set once the saved score has been injected (or there was nothing to inject)
+    loaded: bool,
+}
+
+impl Hiscore {
+    pub fn new(descriptor: HiscoreDescriptor, dir: &Path, set: &str) -> Self {
+        Self {
+            descriptor,
+            path: dir.join(format!("{}.hi", set)),
+            loaded: false,
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Call when the machine resets: the game clears its table again, so the saved score has
+    /// to be injected again before [Hiscore::save] may write the file
+    pub fn rearm(&mut self) {
+        self.loaded = false;
+    }
+
+    /// Call once per frame. Injects the saved score the first time it is safe to do so.
+    pub fn update(&mut self, bus: &mut dyn AddressBus) -> io::Result<()> {
+        if self.loaded || !self.descriptor.is_safe(bus) {
+            return Ok(());
+        }
+        self.loaded = true;
+        let mut data = Vec::new();
+        match File::open(&self.path) {
+            Ok(mut file) => file.read_to_end(&mut data)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if data.len() != self.descriptor.size() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} has a wrong size", self.path.display()),
+            ));
+        }
+        self.descriptor.write(bus, &data);
+        Ok(())
+    }
+
+    /// Save the current score. Does nothing before the score was loaded, as RAM may not hold
+    /// a valid table yet.
+    pub fn save(&self, bus: &dyn AddressBus) -> io::Result<()> {
+        if !self.loaded {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(&self.path)?;
+        file.write_all(&self.descriptor.read(bus))
+    }
+}
@@ -0,0 +1,156 @@
+use crate::cpu::gdb::{AccessLog, Target};
+use crate::cpu::observer::Access;
+use crate::cpu::{Cpu, IoBus, Register};
+use crate::device::Watchdog;
+use crate::game::invaders::driver::{Driver, Input};
+use crate::game::invaders::gameio::{io_bus, InputPanel};
+use crate::game::invaders::InvadersAddressBus;
+use crate::memory::AddressBus;
+
+/// Cycles between the mid-screen and the vblank interrupt
+pub const HALF_FRAME_CYCLES: u32 = 17476;
+
+/// The Midway 8080 board running one driver: CPU, memory and I/O ports. The bus is the
+/// Invaders one, or a wrapper around it such as the [AccessLog] of the debugger.
+pub struct Board<B: AddressBus = InvadersAddressBus> {
+    pub cpu: Cpu<B, IoBus>,
+    io: IoBus,
+    /// Cycles since the last interrupt
+    half: u32,
+    /// Whether the next interrupt is the vblank one
+    vblank: bool,
+}
+
+impl<B: AddressBus> Board<B> {
+    pub fn new(driver: &'static Driver, bus: B) -> Self {
+        let io = io_bus(&driver.ports);
+        let cpu = Cpu::new(bus, 0);
+        Self {
+            cpu,
+            io,
+            half: 0,
+            vblank: false,
+        }
+    }
+
+    pub fn io(&self) -> &IoBus {
+        &self.io
+    }
+
+    /// Run one instruction, returns its cycles
+    pub fn step(&mut self) -> u8 {
+        self.cpu.step(&mut self.io)
+    }
+
+    pub fn set_inputs(&mut self, inputs: Vec<Input>) {
+        if let Some(panel) = self.io.device_mut::<InputPanel>() {
+            panel.set_inputs(inputs);
+        }
+    }
+
+    pub fn set_watchdog(&mut self, enabled: bool) {
+        if let Some(watchdog) = self.io.device_mut::<Watchdog>() {
+            watchdog.set_enabled(enabled);
+        }
+    }
+
+    /// Reset line, pulled by the reset button or the watchdog: the CPU and the devices on
+    /// the ports (shifter, sound latches) restart, RAM is kept
+    pub fn reset(&mut self) {
+        self.io.reset();
+        self.cpu.reset();
+    }
+
+    /// Switch the board off and on: RAM, including video RAM, is filled with `fill`
+    /// repeated and every device starts over
+    pub fn power_cycle(&mut self, fill: &[u8]) {
+        self.cpu.addring.fill_ram(fill);
+        self.io.reset();
+        self.cpu.power_on();
+    }
+
+    /// Run a whole frame without a display: RST 1 at mid-screen, RST 2 at vblank, then
+    /// [Board::end_frame]. Returns the cycles run.
+    pub fn run_frame(&mut self) -> u64 {
+        self.run_frame_with(|cpu, io| cpu.step(io))
+    }
+
+    /// [Board::run_frame] with each instruction run by `step`, e.g. through a profiler
+    pub fn run_frame_with(
+        &mut self,
+        mut step: impl FnMut(&mut Cpu<B, IoBus>, &mut IoBus) -> u8,
+    ) -> u64 {
+        let mut cycles = 0;
+        loop {
+            let (run, frame_done) = self.clock_with(&mut step);
+            cycles += run as u64;
+            if frame_done {
+                return cycles;
+            }
+        }
+    }
+
+    /// Run one instruction, then the interrupt when it is due, so the game keeps its timing
+    /// one instruction at a time. Returns the cycles run.
+    pub fn clock(&mut self) -> u8 {
+        self.clock_with(|cpu, io| cpu.step(io)).0
+    }
+
+    /// Also returns whether the frame ended
+    fn clock_with(
+        &mut self,
+        mut step: impl FnMut(&mut Cpu<B, IoBus>, &mut IoBus) -> u8,
+    ) -> (u8, bool) {
+        let cycles = step(&mut self.cpu, &mut self.io);
+        self.half += cycles as u32;
+        if self.half <= HALF_FRAME_CYCLES {
+            return (cycles, false);
+        }
+        self.half = 0;
+        self.vblank = !self.vblank;
+        if self.vblank {
+            self.cpu.interrupt(0xcf);
+            (cycles, false)
+        } else {
+            self.cpu.interrupt(0xd7);
+            self.end_frame();
+            (cycles, true)
+        }
+    }
+
+    /// Called once per frame. Returns true when the watchdog fired and reset the board.
+    pub fn end_frame(&mut self) -> bool {
+        let fired = (self.io.device_mut::<Watchdog>()).is_some_and(|w| w.tick());
+        if fired {
+            self.reset();
+        }
+        fired
+    }
+}
+
+/// Debugged one [Board::clock] at a time, so the interrupts keep coming
+impl<B: AddressBus> Target for Board<AccessLog<B>> {
+    fn registers(&self) -> &Register {
+        &self.cpu.register
+    }
+
+    fn registers_mut(&mut self) -> &mut Register {
+        &mut self.cpu.register
+    }
+
+    fn bus(&self) -> &dyn AddressBus {
+        &self.cpu.addring
+    }
+
+    fn bus_mut(&mut self) -> &mut dyn AddressBus {
+        &mut self.cpu.addring
+    }
+
+    fn step(&mut self) -> u8 {
+        self.clock()
+    }
+
+    fn access_log(&mut self) -> &mut Vec<(Access, u16)> {
+        self.cpu.addring.accesses()
+    }
+}
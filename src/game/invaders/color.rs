@@ -0,0 +1,74 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Offset of video RAM inside the 0x2000-0x3fff RAM block
+const VIDEO_OFS: usize = 0x0400;
+
+/// One colour map: an entry per 8 lines and 8 pixel column of the whole RAM block
+pub const PROM_BANK_SIZE: usize = 0x400;
+
+/// Where the colour of each 8 pixel group comes from
+pub enum ColorSource {
+    /// PROM addressed by the video address. Boards with more than one colour map hold
+    /// them one after the other and select `bank` on an OUT port.
+    Prom {
+        data: Vec<u8>,
+        background: u8,
+        bank: usize,
+    },
+    /// Colour RAM written by the CPU, indexed by `video address & mask`
+    Ram {
+        data: Rc<RefCell<Vec<u8>>>,
+        mask: u16,
+        background: u8,
+    },
+}
+
+impl ColorSource {
+    /// Foreground colour of the byte at `offs` in video RAM
+    pub fn color(&self, offs: usize) -> u32 {
+        let offs = offs + VIDEO_OFS;
+        let value = match self {
+            ColorSource::Prom { data, bank, .. } => {
+                let addr = bank * PROM_BANK_SIZE + ((offs >> 8 << 5) | (offs & 0x1f));
+                data.get(addr).copied().unwrap_or(0x07)
+            }
+            ColorSource::Ram { data, mask, .. } => {
+                let data = data.borrow();
+                data[(offs & *mask as usize) % data.len()]
+            }
+        };
+        rbg_3bit(value)
+    }
+
+    pub fn background(&self) -> u32 {
+        match self {
+            ColorSource::Prom { background, .. } | ColorSource::Ram { background, .. } => {
+                rbg_3bit(*background)
+            }
+        }
+    }
+
+    /// Switch to another colour map; colour RAM has only one
+    pub fn set_bank(&mut self, bank: usize) {
+        match self {
+            ColorSource::Prom { bank: current, .. } => *current = bank,
+            ColorSource::Ram { .. } => {}
+        }
+    }
+}
+
+/// The board's 3 bit colour: bit 0 red, bit 1 blue, bit 2 green
+pub fn rbg_3bit(value: u8) -> u32 {
+    let mut color = 0;
+    if value & 0x01 != 0 {
+        color |= 0x00ff_0000;
+    }
+    if value & 0x02 != 0 {
+        color |= 0x0000_00ff;
+    }
+    if value & 0x04 != 0 {
+        color |= 0x0000_ff00;
+    }
+    color
+}
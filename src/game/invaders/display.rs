@@ -1,23 +1,63 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::time::{SystemTime, UNIX_EPOCH};
 
-use minifb::{Key, Scale, ScaleMode, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+
+use crate::game::invaders::color::ColorSource;
+use crate::game::invaders::driver::{Input, OverlayRect};
 
 pub struct Display {
     window: Window,
-    buffer: Vec<u32>,
+    screen: Screen,
     video_arr: Rc<RefCell<Vec<u8>>>,
 }
 
-const GAME_NAME: &str = "Space Invaders";
+/// Video RAM turned into rotated, coloured pixels, without a window
+pub struct Screen {
+    buffer: Vec<u32>,
+    /// Colour of a lit pixel at each position, white outside the overlay
+    overlay: Vec<u32>,
+    color: Option<ColorSource>,
+}
+
+/// Machine controls outside the control panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// F3
+    Reset,
+    /// Shift+F3
+    PowerCycle,
+}
+
 const WIDTH: usize = 224;
 const HEIGHT: usize = 256;
 
+const KEY_MAP: &[(Key, Input)] = &[
+    (Key::C, Input::Coin),
+    (Key::Enter, Input::Start1),
+    (Key::Key2, Input::Start2),
+    (Key::Space, Input::Fire1),
+    (Key::Left, Input::Left1),
+    (Key::Right, Input::Right1),
+    (Key::Up, Input::Up1),
+    (Key::Down, Input::Down1),
+    (Key::W, Input::Fire2),
+    (Key::A, Input::Left2),
+    (Key::D, Input::Right2),
+    (Key::R, Input::Up2),
+    (Key::F, Input::Down2),
+    (Key::T, Input::Tilt),
+];
+
 impl Display {
-    pub fn new(video_arr: Rc<RefCell<Vec<u8>>>) -> Self {
+    pub fn new(
+        video_arr: Rc<RefCell<Vec<u8>>>,
+        game_name: &str,
+        overlay: &[OverlayRect],
+        color: Option<ColorSource>,
+    ) -> Self {
         let window = Window::new(
-            format!("{} - Powered by Jelipo", GAME_NAME).as_str(),
+            format!("{} - Powered by Jelipo", game_name).as_str(),
             WIDTH,
             HEIGHT,
             WindowOptions {
@@ -36,72 +76,101 @@ impl Display {
         });
         Self {
             window,
-            buffer: vec![0; WIDTH * HEIGHT],
+            screen: Screen::new(overlay, color),
             video_arr,
         }
     }
 
-    /// This is block method
-    pub fn start(&mut self) {
-        // 限制最高60帧
-        self.window
-            .limit_update_rate(Some(std::time::Duration::from_micros(16667)));
-
-        let mut lasttime = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-        while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
-            lasttime = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-            self.set_buffer(self.video_arr.clone());
-            self.window
-                .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
-                .unwrap();
-        }
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
     }
 
-    pub fn update_cycle(&mut self) -> Option<Key> {
-        self.set_buffer(self.video_arr.clone());
+    /// Draw a frame and return the controls held
+    pub fn update_cycle(&mut self) -> Vec<Input> {
+        self.screen.set_buffer(&self.video_arr.borrow());
         self.window
-            .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
+            .update_with_buffer(self.screen.buffer(), WIDTH, HEIGHT)
             .unwrap();
-        return if self.window.is_key_down(Key::Left) {
-            Some(Key::Left)
-        } else if self.window.is_key_down(Key::Right) {
-            Some(Key::Right)
-        } else if self.window.is_key_down(Key::Space) {
-            Some(Key::Space)
-        } else if self.window.is_key_down(Key::Enter) {
-            Some(Key::Enter)
-        } else if self.window.is_key_down(Key::C) {
-            Some(Key::C)
+        KEY_MAP
+            .iter()
+            .filter(|(key, _)| self.window.is_key_down(*key))
+            .map(|(_, input)| *input)
+            .collect()
+    }
+
+    /// See [Screen::set_color_bank]
+    pub fn set_color_bank(&mut self, bank: usize) {
+        self.screen.set_color_bank(bank);
+    }
+
+    /// Hotkey pressed since the last frame
+    pub fn command(&self) -> Option<Command> {
+        if !self.window.is_key_pressed(Key::F3, KeyRepeat::No) {
+            return None;
+        }
+        let shift =
+            self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift);
+        Some(if shift {
+            Command::PowerCycle
         } else {
-            None
-        };
+            Command::Reset
+        })
+    }
+}
+
+impl Screen {
+    pub fn new(overlay: &[OverlayRect], color: Option<ColorSource>) -> Self {
+        let mut overlay_buffer = vec![u32::MAX; WIDTH * HEIGHT];
+        for rect in overlay {
+            for y in rect.y..(rect.y + rect.height).min(HEIGHT) {
+                for x in rect.x..(rect.x + rect.width).min(WIDTH) {
+                    overlay_buffer[y * WIDTH + x] = rect.color;
+                }
+            }
+        }
+        Self {
+            buffer: vec![0; WIDTH * HEIGHT],
+            overlay: overlay_buffer,
+            color,
+        }
     }
 
-    fn set_buffer(&mut self, video_arr: Rc<RefCell<Vec<u8>>>) {
-        let gpu_ram = video_arr.borrow();
-        for i in 0..gpu_ram.len() {
-            let gpu_byte = gpu_ram[i];
+    /// Colour map used from the next frame on, for boards with more than one
+    pub fn set_color_bank(&mut self, bank: usize) {
+        if let Some(color) = self.color.as_mut() {
+            color.set_bank(bank);
+        }
+    }
+
+    /// `WIDTH * HEIGHT` pixels, row by row
+    pub fn buffer(&self) -> &[u32] {
+        &self.buffer
+    }
+
+    /// Convert the whole video RAM
+    pub fn set_buffer(&mut self, gpu_ram: &[u8]) {
+        for (i, &gpu_byte) in gpu_ram.iter().enumerate() {
+            let (fore, back) = match &self.color {
+                Some(color) => (color.color(i), color.background()),
+                None => (u32::MAX, 0),
+            };
             // display_point
             let dp = i * 8;
-            let _buffer_size = self.buffer.len();
-            self.set_point(dp, get_color(gpu_byte & 0b0000_0001));
-            self.set_point(dp + 1, get_color(gpu_byte & 0b0000_0010));
-            self.set_point(dp + 2, get_color(gpu_byte & 0b0000_0100));
-            self.set_point(dp + 3, get_color(gpu_byte & 0b0000_1000));
-            self.set_point(dp + 4, get_color(gpu_byte & 0b0001_0000));
-            self.set_point(dp + 5, get_color(gpu_byte & 0b0010_0000));
-            self.set_point(dp + 6, get_color(gpu_byte & 0b0100_0000));
-            self.set_point(dp + 7, get_color(gpu_byte & 0b1000_0000));
+            for bit in 0..8 {
+                let color = if gpu_byte & (1 << bit) == 0 {
+                    back
+                } else {
+                    fore
+                };
+                self.set_point(dp + bit, color);
+            }
         }
     }
 
     fn set_point(&mut self, display_point: usize, color: u32) {
         let new_x = display_point / HEIGHT;
         let new_y = HEIGHT - 1 - (display_point % HEIGHT);
-        self.buffer[new_y * WIDTH + new_x] = color;
+        let index = new_y * WIDTH + new_x;
+        self.buffer[index] = color & self.overlay[index];
     }
 }
-
-fn get_color(bit: u8) -> u32 {
-    return if bit == 0 { 0 } else { u32::max_value() };
-}
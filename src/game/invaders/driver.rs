@@ -0,0 +1,355 @@
+use crate::game::invaders::siaddressing::ROM_SIZE;
+
+/// A ROM chip and where it is mapped. Two 1K chips share a 2K socket.
+pub struct RomRegion {
+    /// File name under the ROM directory
+    pub file: &'static str,
+    pub addr: u16,
+    pub size: usize,
+}
+
+impl RomRegion {
+    /// First address past the chip
+    pub fn end(&self) -> u16 {
+        self.addr + self.size as u16
+    }
+}
+
+/// Player controls the frontend can feed to the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Coin,
+    Start1,
+    Start2,
+    Fire1,
+    Left1,
+    Right1,
+    Up1,
+    Down1,
+    Fire2,
+    Left2,
+    Right2,
+    Up2,
+    Down2,
+    Tilt,
+}
+
+/// One input bit on an IN port
+pub struct InputBit {
+    pub input: Input,
+    pub port: u8,
+    pub mask: u8,
+    /// The bit reads 0 while the control is held
+    pub active_low: bool,
+}
+
+/// Port layout of a board
+pub struct PortMap {
+    /// IN: the shifted byte of the barrel shifter
+    pub shift_result: u8,
+    /// OUT: shift amount (0-7)
+    pub shift_count: u8,
+    /// OUT: data pushed into the shifter
+    pub shift_data: u8,
+    /// OUT: watchdog kick
+    pub watchdog: u8,
+    /// OUT: sound triggers
+    pub sound: [u8; 2],
+    pub inputs: &'static [InputBit],
+    /// Value of each IN port with no control held, DIP switches included
+    pub defaults: [u8; 4],
+}
+
+/// A rectangle of coloured cellophane, in output pixels (224x256, portrait)
+pub struct OverlayRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub color: u32,
+}
+
+/// Colour hardware of the board variant
+pub enum ColorMap {
+    /// Black and white monitor, only the overlay adds colour
+    None,
+    /// Colour PROMs indexed by the video address, one colour map each
+    Prom {
+        /// File names under the ROM directory
+        files: &'static [&'static str],
+        /// OUT port and bit that select the second colour map, on boards with two
+        bank_select: Option<(u8, u8)>,
+        background: u8,
+    },
+    /// Colour RAM the CPU writes at `addr`, indexed by `video address & mask`
+    Ram {
+        addr: u16,
+        size: u16,
+        mask: u16,
+        background: u8,
+    },
+}
+
+/// Everything that differs between the games running on the Midway/Taito 8080 board
+pub struct Driver {
+    /// ROM set name, also used for `.hi` and `.cht` files
+    pub name: &'static str,
+    pub description: &'static str,
+    pub roms: &'static [RomRegion],
+    pub ports: PortMap,
+    pub overlay: &'static [OverlayRect],
+    pub color: ColorMap,
+}
+
+const fn rom(file: &'static str, addr: u16) -> RomRegion {
+    RomRegion {
+        file,
+        addr,
+        size: ROM_SIZE,
+    }
+}
+
+const fn rom_1k(file: &'static str, addr: u16) -> RomRegion {
+    RomRegion {
+        file,
+        addr,
+        size: ROM_SIZE / 2,
+    }
+}
+
+const fn high(input: Input, port: u8, mask: u8) -> InputBit {
+    InputBit {
+        input,
+        port,
+        mask,
+        active_low: false,
+    }
+}
+
+const fn rect(x: usize, y: usize, width: usize, height: usize, color: u32) -> OverlayRect {
+    OverlayRect {
+        x,
+        y,
+        width,
+        height,
+        color,
+    }
+}
+
+const RED: u32 = 0x00ff_2020;
+const GREEN: u32 = 0x0020_ff20;
+
+/// Inputs of Space Invaders and Part II, with the tilt switch on port 2
+const MIDWAY_INPUTS: &[InputBit] = &[
+    high(Input::Coin, 1, 0x01),
+    high(Input::Start2, 1, 0x02),
+    high(Input::Start1, 1, 0x04),
+    high(Input::Fire1, 1, 0x10),
+    high(Input::Left1, 1, 0x20),
+    high(Input::Right1, 1, 0x40),
+    high(Input::Tilt, 2, 0x04),
+    high(Input::Fire2, 2, 0x10),
+    high(Input::Left2, 2, 0x20),
+    high(Input::Right2, 2, 0x40),
+];
+
+/// Lunar Rescue: the button fires on the way up and thrusts while landing. Port 2 bit 2
+/// is a DIP switch rather than a tilt switch.
+const LRESCUE_INPUTS: &[InputBit] = &[
+    high(Input::Coin, 1, 0x01),
+    high(Input::Start2, 1, 0x02),
+    high(Input::Start1, 1, 0x04),
+    high(Input::Fire1, 1, 0x10),
+    high(Input::Left1, 1, 0x20),
+    high(Input::Right1, 1, 0x40),
+    high(Input::Fire2, 2, 0x10),
+    high(Input::Left2, 2, 0x20),
+    high(Input::Right2, 2, 0x40),
+];
+
+/// Balloon Bomber: a cannon moving left and right, no tilt switch
+const BALLBOMB_INPUTS: &[InputBit] = &[
+    high(Input::Coin, 1, 0x01),
+    high(Input::Start2, 1, 0x02),
+    high(Input::Start1, 1, 0x04),
+    high(Input::Fire1, 1, 0x10),
+    high(Input::Left1, 1, 0x20),
+    high(Input::Right1, 1, 0x40),
+    high(Input::Fire2, 2, 0x10),
+    high(Input::Left2, 2, 0x20),
+    high(Input::Right2, 2, 0x40),
+];
+
+/// Space Chaser: a four-way joystick per player, the coin switch sits with the DIP
+/// switches on port 2
+const SCHASER_INPUTS: &[InputBit] = &[
+    high(Input::Right2, 0, 0x01),
+    high(Input::Left2, 0, 0x02),
+    high(Input::Up2, 0, 0x04),
+    high(Input::Down2, 0, 0x08),
+    high(Input::Fire2, 0, 0x10),
+    high(Input::Right1, 1, 0x01),
+    high(Input::Left1, 1, 0x02),
+    high(Input::Up1, 1, 0x04),
+    high(Input::Down1, 1, 0x08),
+    high(Input::Fire1, 1, 0x10),
+    high(Input::Start1, 1, 0x40),
+    high(Input::Start2, 1, 0x80),
+    high(Input::Coin, 2, 0x40),
+];
+
+pub const INVADERS: Driver = Driver {
+    name: "invaders",
+    description: "Space Invaders",
+    roms: &[
+        rom("invaders.h", 0x0000),
+        rom("invaders.g", 0x0800),
+        rom("invaders.f", 0x1000),
+        rom("invaders.e", 0x1800),
+    ],
+    ports: PortMap {
+        shift_result: 3,
+        shift_count: 2,
+        shift_data: 4,
+        watchdog: 6,
+        sound: [3, 5],
+        inputs: MIDWAY_INPUTS,
+        // 3 ships, extra ship at 1500, coin info hidden
+        defaults: [0x0e, 0x08, 0x80, 0x00],
+    },
+    overlay: &[
+        rect(0, 32, 224, 32, RED),
+        rect(0, 184, 224, 56, GREEN),
+        rect(16, 240, 118, 16, GREEN),
+    ],
+    color: ColorMap::None,
+};
+
+pub const INVADPT2: Driver = Driver {
+    name: "invadpt2",
+    description: "Space Invaders Part II",
+    roms: &[
+        rom("pv01", 0x0000),
+        rom("pv02", 0x0800),
+        rom("pv03", 0x1000),
+        rom("pv04", 0x1800),
+        rom("pv05", 0x4000),
+    ],
+    ports: PortMap {
+        shift_result: 3,
+        shift_count: 2,
+        shift_data: 4,
+        watchdog: 6,
+        sound: [3, 5],
+        inputs: MIDWAY_INPUTS,
+        defaults: [0x00, 0x08, 0x00, 0x00],
+    },
+    overlay: &[],
+    // pv07.2 is the colour map of the second player, port 5 bit 5 selects it
+    color: ColorMap::Prom {
+        files: &["pv06.1", "pv07.2"],
+        bank_select: Some((5, 0x20)),
+        background: 0,
+    },
+};
+
+pub const LRESCUE: Driver = Driver {
+    name: "lrescue",
+    description: "Lunar Rescue",
+    roms: &[
+        rom("lrescue.1", 0x0000),
+        rom("lrescue.2", 0x0800),
+        rom("lrescue.3", 0x1000),
+        rom("lrescue.4", 0x1800),
+        rom("lrescue.5", 0x4000),
+        rom("lrescue.6", 0x4800),
+    ],
+    ports: PortMap {
+        shift_result: 3,
+        shift_count: 2,
+        shift_data: 4,
+        watchdog: 6,
+        sound: [3, 5],
+        inputs: LRESCUE_INPUTS,
+        // Port 2: bits 0-1 lives (3), bit 2 unused, bit 3 bonus ship, bit 7 coin info
+        defaults: [0x00, 0x08, 0x00, 0x00],
+    },
+    overlay: &[],
+    color: ColorMap::Prom {
+        files: &["7643-1.cpu"],
+        bank_select: None,
+        background: 0,
+    },
+};
+
+pub const BALLBOMB: Driver = Driver {
+    name: "ballbomb",
+    description: "Balloon Bomber",
+    roms: &[
+        rom("tn01", 0x0000),
+        rom("tn02", 0x0800),
+        rom("tn03", 0x1000),
+        rom("tn04", 0x1800),
+        rom("tn05-1", 0x4000),
+    ],
+    ports: PortMap {
+        shift_result: 3,
+        shift_count: 2,
+        shift_data: 4,
+        watchdog: 6,
+        sound: [3, 5],
+        inputs: BALLBOMB_INPUTS,
+        // Port 2: bits 0-1 lives (3), bit 2 unknown, bit 3 bonus life, bit 7 coin info
+        defaults: [0x00, 0x08, 0x00, 0x00],
+    },
+    overlay: &[],
+    // tn07 is the colour map of the second player, port 5 bit 5 selects it
+    color: ColorMap::Prom {
+        files: &["tn06", "tn07"],
+        bank_select: Some((5, 0x20)),
+        // The sky is blue
+        background: 2,
+    },
+};
+
+pub const SCHASER: Driver = Driver {
+    name: "schaser",
+    description: "Space Chaser",
+    roms: &[
+        rom_1k("rt13.bin", 0x0000),
+        rom_1k("rt14.bin", 0x0400),
+        rom_1k("rt15.bin", 0x0800),
+        rom_1k("rt16.bin", 0x0c00),
+        rom_1k("rt17.bin", 0x1000),
+        rom_1k("rt18.bin", 0x1400),
+        rom_1k("rt19.bin", 0x1800),
+        rom_1k("rt20.bin", 0x1c00),
+        rom_1k("rt21.bin", 0x4000),
+        rom_1k("rt22.bin", 0x4400),
+    ],
+    ports: PortMap {
+        shift_result: 3,
+        shift_count: 2,
+        shift_data: 4,
+        watchdog: 6,
+        sound: [3, 5],
+        inputs: SCHASER_INPUTS,
+        // Port 2: bits 0-1 lives (3), bit 3 difficulty (easy), bit 7 cocktail
+        defaults: [0x00, 0x00, 0x00, 0x00],
+    },
+    overlay: &[],
+    // The game paints each 8 pixel group itself, A15 selects the colour RAM
+    color: ColorMap::Ram {
+        addr: 0xc000,
+        size: 0x2000,
+        mask: 0x1f9f,
+        background: 2,
+    },
+};
+
+/// All supported games, the first one is the default
+pub const DRIVERS: &[&Driver] = &[&INVADERS, &INVADPT2, &LRESCUE, &BALLBOMB, &SCHASER];
+
+pub fn find_driver(name: &str) -> Option<&'static Driver> {
+    DRIVERS.iter().copied().find(|d| d.name == name)
+}
@@ -0,0 +1,54 @@
+//! Random instruction streams through [DynCpu::next] on the Space Invaders bus. Whatever
+//! the program reads, writes, jumps to or calls into, including empty ROM sockets and
+//! addresses outside of RAM, the CPU must not panic.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cpu::Variant;
+use crate::device::Watchdog;
+use crate::game::invaders::driver::INVADERS;
+use crate::game::invaders::siaddressing::ROM_SIZE;
+use crate::game::invaders::{io_bus, InvadersAddressBus, HALF_FRAME_CYCLES};
+use crate::DynCpu;
+
+/// Run `steps` instructions of `data`, with RST 1 and RST 2 every half frame. The first
+/// byte picks the variant (bits 0-1) and which of the four ROM sockets hold a chip
+/// (bits 4-7); the rest is spread over the chips, the empty sockets read 0.
+pub fn fuzz_one(data: &[u8], steps: u32) {
+    let Some((&config, mut program)) = data.split_first() else {
+        return;
+    };
+    let variant = match config & 0x03 {
+        1 => Variant::I8085,
+        2 => Variant::Z80,
+        _ => Variant::I8080,
+    };
+    let mut roms = Vec::new();
+    for socket in 0..4 {
+        if config & (0x10 << socket) == 0 {
+            continue;
+        }
+        let mut rom = Box::new([0u8; ROM_SIZE]);
+        let len = program.len().min(ROM_SIZE);
+        rom[..len].copy_from_slice(&program[..len]);
+        program = &program[len..];
+        roms.push((socket * ROM_SIZE as u16, rom));
+    }
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let bus = InvadersAddressBus::new(roms, video).unwrap();
+    let mut io = io_bus(&INVADERS.ports);
+    io.device_mut::<Watchdog>().unwrap().set_enabled(false);
+    let mut cpu = DynCpu::with_variant(Box::new(bus), 0, Rc::new(RefCell::new(io)), variant);
+
+    let mut half = 0;
+    let mut rst = 0xcf;
+    for _ in 0..steps {
+        half += u32::from(cpu.next());
+        if half > HALF_FRAME_CYCLES {
+            half = 0;
+            cpu.interrupt(rst);
+            rst ^= 0xcf ^ 0xd7;
+        }
+    }
+}
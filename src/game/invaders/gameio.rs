@@ -1,64 +1,51 @@
-use minifb::Key;
+use crate::cpu::{IoBus, PortDevice};
+use crate::device::{Latch, ShiftRegister, Watchdog, WATCHDOG_FRAMES};
+use crate::game::invaders::driver::{Input, PortMap};
 
-use crate::cpu::{Cpu, IO};
-use crate::cpu::Register;
-
-pub struct InvadersIO {
-    input_temp: Option<Key>,
+/// Control panel and DIP switches, read on the IN ports of the driver's [PortMap]
+pub struct InputPanel {
+    ports: &'static PortMap,
+    /// Controls held during the current frame
+    inputs: Vec<Input>,
 }
 
-impl InvadersIO {
-    pub fn new() -> Self {
-        Self { input_temp: None }
-    }
-
-    pub fn clean_temp(&mut self) {
-        self.input_temp = None;
+impl InputPanel {
+    pub fn new(ports: &'static PortMap) -> Self {
+        Self {
+            ports,
+            inputs: Vec::new(),
+        }
     }
 
-    pub fn set_input_temp(&mut self, key: Key) {
-        self.input_temp = Some(key)
+    pub fn set_inputs(&mut self, inputs: Vec<Input>) {
+        self.inputs = inputs
     }
 }
 
-impl IO for InvadersIO {
-    fn input(&mut self, cpu: &mut Register, byte: u8) {
-        let input_key = match self.input_temp {
-            None => {
-                //println!("执行input {:X}", byte);
-                return;
+impl PortDevice for InputPanel {
+    fn input(&mut self, port: u8) -> u8 {
+        let mut value = self.ports.defaults.get(port as usize).copied().unwrap_or(0);
+        for bit in self.ports.inputs.iter().filter(|b| b.port == port) {
+            if self.inputs.contains(&bit.input) != bit.active_low {
+                value |= bit.mask;
+            } else {
+                value &= !bit.mask;
             }
-            Some(i) => i,
-        };
-        let reg_a = match byte {
-            1 => match input_key {
-                Key::C => Some(0b0000_0001),
-                Key::Enter => Some(0b0000_0100),
-                Key::Space => Some(0b0001_0000),
-                Key::Left => Some(0b0010_0000),
-                Key::Right => Some(0b0100_0000),
-                _ => None,
-            },
-            2 => match input_key {
-                // Key::C => { Some(0b0000_0001) }
-                // Key::Enter => { Some(0b0000_0100) }
-                // Key::Space => { Some(0b0001_0000) }
-                // Key::Left => { Some(0b0010_0000) }
-                // Key::Right => { Some(0b0100_0000) }
-                _ => Some(0b1000_0000),
-            },
-            3 => None,
-            _ => None,
-        };
-        match reg_a {
-            Some(a) => cpu.a = a,
-            _ => {}
         }
-        self.input_temp = None;
-        //println!("执行input {:X}", byte);
+        value
     }
+}
 
-    fn output(&mut self, _cpu: &mut Cpu, _byte: u8) {
-        println!("执行output");
-    }
+/// I/O ports of the board: control panel, barrel shifter, sound latches and watchdog
+pub fn io_bus(ports: &'static PortMap) -> IoBus {
+    let mut io = IoBus::new();
+    let panel = (0..ports.defaults.len() as u8).filter(|p| *p != ports.shift_result);
+    io.attach(InputPanel::new(ports)).input(panel);
+    io.attach(ShiftRegister::new(ports.shift_count, ports.shift_data))
+        .input([ports.shift_result])
+        .output([ports.shift_count, ports.shift_data]);
+    io.attach(Latch::new()).output(ports.sound);
+    io.attach(Watchdog::new(WATCHDOG_FRAMES))
+        .output([ports.watchdog]);
+    io
 }
@@ -1,18 +1,43 @@
 use std::cell::RefCell;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{io, thread};
 
-use crate::cpu::Cpu;
-use crate::game::invaders::display::Display;
-use crate::game::invaders::gameio::InvadersIO;
-use crate::game::invaders::InvadersAddressBus;
-use crate::game::Launch;
+use crate::cpu::disasm::listing;
+use crate::cpu::gdb::{AccessLog, GdbStub};
+use crate::cpu::profiler::Profiler;
+use crate::cpu::symbols::SymbolTable;
+use crate::cpu::IoBus;
+use crate::device::Latch;
+use crate::game::invaders::color::{ColorSource, PROM_BANK_SIZE};
+use crate::game::invaders::display::{Command, Display};
+use crate::game::invaders::driver::{ColorMap, Driver, INVADERS};
+use crate::game::invaders::siaddressing::ROM_SIZE;
+use crate::game::invaders::{builtin_symbols, Board, InvadersAddressBus, HALF_FRAME_CYCLES};
+use crate::game::{CheatEngine, Hiscore, HiscoreDescriptor, Launch};
+use crate::memory::AddressBus;
 
-pub struct InvadersLaunch {}
+/// Directory holding the ROM files
+const ROM_DIR: &str = "./res";
+/// Directory for `.hi` files
+const HISCORE_DIR: &str = "./hiscore";
+/// Directory for `.cht` files
+const CHEAT_DIR: &str = "./cheat";
+/// Clock of the Midway board, in Hz
+const CPU_CLOCK: f64 = 1_996_800.0;
+/// RAM content after a power cycle
+const POWER_ON_FILL: &[u8] = &[0x00];
+
+pub struct InvadersLaunch {
+    driver: &'static Driver,
+    watchdog: bool,
+    /// Symbol file added to the built-in symbols
+    symbols: Option<PathBuf>,
+}
 
 impl Launch for InvadersLaunch {
     fn start(&self) {
@@ -21,31 +46,46 @@ impl Launch for InvadersLaunch {
         let video_arr = Rc::new(RefCell::new(gpu_ram));
         let video_arr_cloned = video_arr.clone();
 
-        let addressing = init_address(video_arr_cloned).unwrap();
+        let (addressing, color) = load_roms(self.driver, video_arr_cloned).unwrap_or_else(|e| {
+            panic!("Failed to load ROMs of {}: {}", self.driver.name, e);
+        });
 
-        let io = Rc::new(RefCell::new(InvadersIO::new()));
-        let mut cpu = Cpu::new(Box::new(addressing), 0, io.clone());
+        let mut board = Board::new(self.driver, addressing);
+        board.set_watchdog(self.watchdog);
         let mut int_num: bool = false;
         let mut time = get_mill_time();
         let mut int_times = 0;
-        let cycle_max: u32 = 17476;
         let _max_fps: u8 = 60;
         let mut fps_temp: u8 = 0;
         let mut fps_timelinei128 = get_mill_time();
-        let mut video = Display::new(video_arr);
+        let mut video = Display::new(
+            video_arr,
+            self.driver.description,
+            self.driver.overlay,
+            color,
+        );
         //video.start();
-        let loop_io = io;
-        loop {
+        let set_name = self.driver.name;
+        let mut hiscore = HiscoreDescriptor::builtin(set_name)
+            .map(|desc| Hiscore::new(desc, Path::new(HISCORE_DIR), set_name));
+        let cheat_path = Path::new(CHEAT_DIR).join(format!("{}.cht", set_name));
+        let mut cheats = CheatEngine::load(&cheat_path).unwrap_or_else(|e| {
+            eprintln!("Failed to load cheats: {}", e);
+            CheatEngine::new()
+        });
+        while video.is_open() {
             let mut cycle_temp: u32 = 0;
             loop {
-                let cycle = cpu.next();
+                let cycle = board.step();
                 cycle_temp += cycle as u32;
-                if cycle_temp > cycle_max {
+                if cycle_temp > HALF_FRAME_CYCLES {
                     cycle_temp = 0;
                     break;
                 }
             }
-            let result = cpu.interrupt(if int_num { 0x10 } else { 0x08 });
+            // RST 2 at the end of the screen
+            let vblank = int_num;
+            let result = board.cpu.interrupt(if vblank { 0xd7 } else { 0xcf });
             if result {
                 int_num = !int_num;
                 int_times += 1;
@@ -57,17 +97,35 @@ impl Launch for InvadersLaunch {
             }
             if result {
                 loop {
-                    let cycle = cpu.next();
+                    let cycle = board.step();
                     cycle_temp += cycle as u32;
-                    if cycle_temp > cycle_max {
-                        cycle_temp = 0;
+                    if cycle_temp > HALF_FRAME_CYCLES {
                         break;
                     }
                 }
-                let key_option = video.update_cycle();
-                match key_option {
-                    Some(key) => loop_io.borrow_mut().set_input_temp(key),
-                    None => loop_io.borrow_mut().clean_temp(),
+                video.set_color_bank(color_bank(self.driver, board.io()));
+                let inputs = video.update_cycle();
+                board.set_inputs(inputs);
+                match video.command() {
+                    Some(Command::Reset) => {
+                        rearm_hiscore(&mut hiscore, &board.cpu.addring);
+                        board.reset();
+                    }
+                    Some(Command::PowerCycle) => {
+                        rearm_hiscore(&mut hiscore, &board.cpu.addring);
+                        board.power_cycle(POWER_ON_FILL);
+                    }
+                    None => {}
+                }
+                // The watchdog reset keeps RAM, so the score can still be saved after it
+                if vblank && board.end_frame() {
+                    rearm_hiscore(&mut hiscore, &board.cpu.addring);
+                }
+                cheats.apply(&mut board.cpu.addring);
+                if let Some(hiscore) = hiscore.as_mut() {
+                    if let Err(e) = hiscore.update(&mut board.cpu.addring) {
+                        eprintln!("Failed to load hiscore: {}", e);
+                    }
                 }
 
                 fps_temp += 1;
@@ -94,6 +152,35 @@ impl Launch for InvadersLaunch {
                 println!("not")
             }
         }
+        if let Some(hiscore) = hiscore {
+            if let Err(e) = hiscore.save(&board.cpu.addring) {
+                eprintln!("Failed to save hiscore: {}", e);
+            }
+        }
+    }
+}
+
+/// The colour map the game selected on the board's OUT port, 0 on boards with one
+fn color_bank(driver: &Driver, io: &IoBus) -> usize {
+    match driver.color {
+        ColorMap::Prom {
+            bank_select: Some((port, mask)),
+            ..
+        } => io
+            .device::<Latch>()
+            .map_or(0, |latch| usize::from(latch.get(port) & mask != 0)),
+        _ => 0,
+    }
+}
+
+/// Save the score reached so far, then wait for the reset game to clear its table before
+/// injecting the saved one again
+fn rearm_hiscore(hiscore: &mut Option<Hiscore>, bus: &dyn AddressBus) {
+    if let Some(hiscore) = hiscore {
+        if let Err(e) = hiscore.save(bus) {
+            eprintln!("Failed to save hiscore: {}", e);
+        }
+        hiscore.rearm();
     }
 }
 
@@ -104,42 +191,184 @@ fn get_mill_time() -> u128 {
         .as_millis()
 }
 
-fn get_micro_time() -> u128 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_micros()
+impl InvadersLaunch {
+    pub fn new(driver: &'static Driver) -> Self {
+        Self {
+            driver,
+            watchdog: true,
+            symbols: None,
+        }
+    }
+
+    /// Run `frames` frames headless as fast as possible and print the emulated clock
+    pub fn bench(&self, frames: u32) -> io::Result<()> {
+        let video_arr = Rc::new(RefCell::new(vec![0u8; 7168]));
+        let (addressing, _) = load_roms(self.driver, video_arr)?;
+        let mut board = Board::new(self.driver, addressing);
+        board.set_watchdog(self.watchdog);
+        let start = Instant::now();
+        let cycles: u64 = (0..frames).map(|_| board.run_frame()).sum();
+        let secs = start.elapsed().as_secs_f64();
+        let mhz = cycles as f64 / secs / 1_000_000.0;
+        println!(
+            "{}: {} frames, {} cycles in {:.3} s, {:.1} MHz ({:.1}x real time)",
+            self.driver.name,
+            frames,
+            cycles,
+            secs,
+            mhz,
+            mhz * 1_000_000.0 / CPU_CLOCK
+        );
+        Ok(())
+    }
+
+    /// Run `frames` frames headless under a [Profiler]: print the hotspots, the routines
+    /// and how much of each ROM ran, and write the ROMs disassembled with hit counts to
+    /// `listing`
+    pub fn profile(&self, frames: u32, listing: Option<&Path>) -> io::Result<()> {
+        let video_arr = Rc::new(RefCell::new(vec![0u8; 7168]));
+        let (addressing, _) = load_roms(self.driver, video_arr)?;
+        let mut board = Board::new(self.driver, addressing);
+        board.set_watchdog(self.watchdog);
+        let mut profiler = Profiler::new();
+        profiler.set_symbols(self.symbols()?);
+        for _ in 0..frames {
+            board.run_frame_with(|cpu, io| profiler.step(cpu, io));
+        }
+        print!("{}", profiler.report(20));
+        println!();
+        let bus = &board.cpu.addring;
+        for region in self.driver.roms {
+            let end = region.end();
+            let covered = profiler.coverage(bus, region.addr, end);
+            println!(
+                "{:<12} {:04x}-{:04x}: {:>4} of {} bytes run ({:.1}%)",
+                region.file,
+                region.addr,
+                end - 1,
+                covered,
+                region.size,
+                covered as f64 * 100.0 / region.size as f64
+            );
+        }
+        if let Some(path) = listing {
+            let text: String = self
+                .driver
+                .roms
+                .iter()
+                .map(|region| profiler.annotate(bus, region.addr, region.end()))
+                .collect();
+            fs::write(path, text)?;
+        }
+        Ok(())
+    }
+
+    /// Run headless under a [GdbStub]: wait for gdb on `addr`, e.g. `127.0.0.1:1234`, and
+    /// serve it until it detaches
+    pub fn gdb(&self, addr: &str) -> io::Result<()> {
+        let video_arr = Rc::new(RefCell::new(vec![0u8; 7168]));
+        let (addressing, _) = load_roms(self.driver, video_arr)?;
+        let mut board = Board::new(self.driver, AccessLog::new(addressing));
+        board.set_watchdog(self.watchdog);
+        let mut stub = GdbStub::new(board);
+        stub.set_symbols(self.symbols()?);
+        println!("{}: waiting for gdb on {}", self.driver.name, addr);
+        stub.listen(addr)
+    }
+
+    /// Write the ROMs disassembled, with labels and data areas from the symbols, to `path`
+    pub fn disassemble(&self, path: &Path) -> io::Result<()> {
+        let video_arr = Rc::new(RefCell::new(vec![0u8; 7168]));
+        let (addressing, _) = load_roms(self.driver, video_arr)?;
+        let symbols = self.symbols()?;
+        let text: String = self
+            .driver
+            .roms
+            .iter()
+            .map(|region| listing(&addressing, region.addr, region.end(), &symbols))
+            .collect();
+        fs::write(path, text)
+    }
+
+    /// Turn the watchdog off, so the game survives being stopped in a debugger
+    pub fn with_watchdog(mut self, enabled: bool) -> Self {
+        self.watchdog = enabled;
+        self
+    }
+
+    /// Load symbols from `path` on top of the built-in ones of the ROM set
+    pub fn with_symbols(mut self, path: impl Into<PathBuf>) -> Self {
+        self.symbols = Some(path.into());
+        self
+    }
+
+    fn symbols(&self) -> io::Result<SymbolTable> {
+        let mut symbols = builtin_symbols(self.driver.name).unwrap_or_default();
+        if let Some(path) = &self.symbols {
+            symbols.merge(SymbolTable::load(path)?);
+        }
+        Ok(symbols)
+    }
 }
 
-impl InvadersLaunch {
-    pub fn new() -> Self {
-        Self {}
+impl Default for InvadersLaunch {
+    fn default() -> Self {
+        Self::new(&INVADERS)
     }
 }
 
-fn init_address(video_arr: Rc<RefCell<Vec<u8>>>) -> io::Result<InvadersAddressBus> {
-    let mut arr_h = [0u8; 2048];
-    let mut h = File::open("./res/invaders.h")?;
-    h.read_exact(&mut arr_h)?;
-
-    let mut arr_g = [0u8; 2048];
-    let mut g = File::open("./res/invaders.g")?;
-    g.read_exact(&mut arr_g)?;
-
-    let mut arr_f = [0u8; 2048];
-    let mut f = File::open("./res/invaders.f")?;
-    f.read_exact(&mut arr_f)?;
-
-    let mut arr_e = [0u8; 2048];
-    let mut e = File::open("./res/invaders.e")?;
-    e.read_exact(&mut arr_e)?;
-
-    let addressing = InvadersAddressBus::new(
-        Box::new(arr_h),
-        Box::new(arr_g),
-        Box::new(arr_f),
-        Box::new(arr_e),
-        video_arr,
-    );
-    Ok(addressing)
+/// Load the ROMs of `driver` from `./res`, with its colour source if it has one
+pub fn load_roms(
+    driver: &Driver,
+    video_arr: Rc<RefCell<Vec<u8>>>,
+) -> io::Result<(InvadersAddressBus, Option<ColorSource>)> {
+    let mut roms: Vec<(u16, Box<[u8; ROM_SIZE]>)> = Vec::with_capacity(driver.roms.len());
+    for region in driver.roms {
+        // 1K chips go in pairs into a 2K socket
+        let socket = region.addr - region.addr % ROM_SIZE as u16;
+        let index = match roms.iter().position(|(addr, _)| *addr == socket) {
+            Some(index) => index,
+            None => {
+                roms.push((socket, Box::new([0u8; ROM_SIZE])));
+                roms.len() - 1
+            }
+        };
+        let start = usize::from(region.addr - socket);
+        let mut file = File::open(Path::new(ROM_DIR).join(region.file))?;
+        file.read_exact(&mut roms[index].1[start..start + region.size])?;
+    }
+    let addressing = InvadersAddressBus::new(roms, video_arr)?;
+    Ok(match driver.color {
+        ColorMap::None => (addressing, None),
+        ColorMap::Prom {
+            files, background, ..
+        } => {
+            let mut data = Vec::with_capacity(files.len() * PROM_BANK_SIZE);
+            for (i, file) in files.iter().enumerate() {
+                File::open(Path::new(ROM_DIR).join(file))?.read_to_end(&mut data)?;
+                // Keep the banks aligned, missing entries are white
+                data.resize((i + 1) * PROM_BANK_SIZE, 0x07);
+            }
+            let color = ColorSource::Prom {
+                data,
+                background,
+                bank: 0,
+            };
+            (addressing, Some(color))
+        }
+        ColorMap::Ram {
+            addr,
+            size,
+            mask,
+            background,
+        } => {
+            let data = Rc::new(RefCell::new(vec![0u8; size as usize]));
+            let color = ColorSource::Ram {
+                data: data.clone(),
+                mask,
+                background,
+            };
+            (addressing.with_color_ram(addr, data)?, Some(color))
+        }
+    })
 }
@@ -1,7 +1,17 @@
+mod board;
+pub mod color;
 mod display;
+pub mod driver;
+pub mod fuzz;
 mod gameio;
 mod launch;
 pub mod siaddressing;
+mod symbols;
 
-pub use launch::InvadersLaunch;
+pub use board::{Board, HALF_FRAME_CYCLES};
+pub use display::Screen;
+pub use driver::{find_driver, Driver, DRIVERS};
+pub use gameio::{io_bus, InputPanel};
+pub use launch::{load_roms, InvadersLaunch};
 pub use siaddressing::InvadersAddressBus;
+pub use symbols::{builtin_symbols, INVADERS_SYM};
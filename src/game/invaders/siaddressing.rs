@@ -1,62 +1,58 @@
 use std::cell::RefCell;
+use std::io;
 use std::rc::Rc;
 
-use crate::memory::{AddressBus, Memory, ReadOnly, Video, Work};
+use crate::memory::{Access, AddressBus, MemoryMap, Region};
 
+/// Size of one ROM socket
+pub const ROM_SIZE: usize = 2048;
+
+/// Bus of the Midway/Taito 8080 board. Only A0-A14 are decoded, and RAM at
+/// 0x2000-0x3fff is mirrored at 0x6000-0x7fff. Empty ROM sockets read 0. Boards with
+/// colour RAM also decode A15 to reach it.
 pub struct InvadersAddressBus {
-    read_only_h: ReadOnly,
-    read_only_g: ReadOnly,
-    read_only_f: ReadOnly,
-    read_only_e: ReadOnly,
-    work_ram: Work,
-    pub video_ram: Video,
-    work_ram2: Work,
+    map: MemoryMap,
 }
 
 impl AddressBus for InvadersAddressBus {
     fn get_mem(&self, addr: u16) -> u8 {
-        match addr {
-            0x0000..=0x07ff => self.read_only_h.get(addr),
-            0x0800..=0x0fff => self.read_only_g.get(addr),
-            0x1000..=0x17ff => self.read_only_f.get(addr),
-            0x1800..=0x1fff => self.read_only_e.get(addr),
-            0x2000..=0x23ff => self.work_ram.get(addr),
-            0x2400..=0x3fff => self.video_ram.get(addr),
-            0x4000..=0xFFFF => self.work_ram2.get(addr),
-            _n => panic!("address not support."),
-        }
+        self.map.get_mem(addr)
     }
 
     fn set_mem(&mut self, addr: u16, val: u8) {
-        match addr {
-            0x0000..=0x07ff => self.read_only_h.set(addr, val),
-            0x0800..=0x0fff => self.read_only_g.set(addr, val),
-            0x1000..=0x17ff => self.read_only_f.set(addr, val),
-            0x1800..=0x1fff => self.read_only_e.set(addr, val),
-            0x2000..=0x23ff => self.work_ram.set(addr, val),
-            0x2400..=0x3fff => self.video_ram.set(addr, val),
-            0x4000..=0xFFFF => self.work_ram2.set(addr, val),
-            _n => println!("Unsupport Address {:X}", _n),
-        }
+        self.map.set_mem(addr, val)
+    }
+
+    fn canonical(&self, addr: u16) -> u16 {
+        self.map.canonical(addr)
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        self.map.fill_ram(pattern)
     }
 }
 
 impl InvadersAddressBus {
+    /// `roms` are (address, data) pairs of 2K chips, they may not overlap RAM or each other
     pub fn new(
-        h_arr: Box<[u8; 2048]>,
-        g_arr: Box<[u8; 2048]>,
-        f_arr: Box<[u8; 2048]>,
-        e_arr: Box<[u8; 2048]>,
+        roms: Vec<(u16, Box<[u8; ROM_SIZE]>)>,
         video_arr: Rc<RefCell<Vec<u8>>>,
-    ) -> Self {
-        Self {
-            read_only_h: ReadOnly::init(0, h_arr),
-            read_only_g: ReadOnly::init(0x0800, g_arr),
-            read_only_f: ReadOnly::init(0x1000, f_arr),
-            read_only_e: ReadOnly::init(0x1800, e_arr),
-            work_ram: Work::init(0x2000, Box::new([0u8; 1024])),
-            video_ram: Video::init(0x2400, video_arr),
-            work_ram2: Work::init(0x4000, Box::new([0u8; 1024])),
+    ) -> io::Result<Self> {
+        let mut builder = MemoryMap::builder()
+            .open_bus(0)
+            .region(Region::ram(0x2000, 1024).mirror(0x3fff))
+            .region(Region::shared(0x2400, video_arr, Access::Ram).mirror(0x3fff));
+        for (addr, data) in roms {
+            builder = builder.region(Region::rom(addr, data.to_vec()).mirror(0x7fff));
         }
+        Ok(Self {
+            map: builder.build()?,
+        })
+    }
+
+    /// Map a colour RAM starting at `addr`; it takes priority over the mirrors
+    pub fn with_color_ram(mut self, addr: u16, data: Rc<RefCell<Vec<u8>>>) -> io::Result<Self> {
+        self.map.add(Region::shared(addr, data, Access::Ram))?;
+        Ok(self)
     }
 }
@@ -0,0 +1,95 @@
+use crate::cpu::symbols::SymbolTable;
+
+/// Symbols of the original Space Invaders ROM set (`invaders`), named after the community
+/// disassembly at computerarcheology.com. In the format of [SymbolTable::parse].
+pub const INVADERS_SYM: &str = "\
+; Interrupts and start up
+0000 code Reset             ; Jumps to init
+0008 code ScanLine96        ; RST 1, the beam is in the middle of the screen
+0010 code ScanLine224       ; RST 2, vertical blank
+18d4 code init
+01e4 code CopyRAMMirror     ; Copy the initial work RAM from ROM
+1b00 data RAMMirror         ; Initial values of 2000-20bf
+
+; Game objects, run from the interrupts
+0248 code RunGameObjs
+028e code GameObj0          ; Player ship
+03bb code GameObj1          ; Player shot
+0476 code GameObj2          ; Alien rolling shot
+04b6 code GameObj3          ; Alien plunger shot
+0682 code GameObj4          ; Flying saucer, and the squiggly shot
+0abf code ISRSplTasks       ; Splash screen animations
+17cd code CheckHandleTilt
+
+; Aliens and shields
+0100 code DrawAlien
+0141 code CursorNextAlien
+017a code GetAlienCoords
+01c0 code InitAliens
+01cf code DrawBottomLine
+01d9 code AddDelta
+01ef code DrawShieldPl1
+
+; Drawing
+08f3 code PrintMessage      ; C characters from DE, at HL
+08ff code DrawChar          ; Character A at HL
+09ad code Print4Digits      ; BCD DE at HL
+09b2 code DrawHexByte
+0a93 code PrintMessageDel   ; PrintMessage, waiting after each character
+1400 code DrawShiftedSprite
+1424 code EraseSimpleSprite
+1439 code DrawSimpSprite
+1452 code EraseShifted
+1474 code CnvtPixNumber     ; Pixel coordinates in HL to a video RAM address
+1491 code DrawSprCollision
+1a32 code BlockCopy         ; B bytes from DE to HL
+1a5c code ClearScreen
+1e00 data Characters        ; 8 bytes per character, for DrawChar
+
+; Delays and DIP switches
+08d1 code GetShipsPerCred
+0ab1 code OneSecDelay
+0ab6 code TwoSecDelay
+0ad7 code WaitOnDelay       ; Until the interrupts count isrDelay down to 0
+
+; Work RAM
+2000 data waitOnDraw
+2002 data alienIsExploding
+2004 data alienRow
+2005 data alienFrame
+2006 data alienCurIndex
+200d data rackDirection
+2010 data obj0Timer         ; Player object, GameObj0
+2015 data playerAlive       ; FF while alive
+201a data playerYr
+201b data playerXr
+2020 data obj1Timer         ; Player shot, GameObj1
+2030 data obj2Timer         ; Rolling shot, GameObj2
+2040 data obj3Timer         ; Plunger shot, GameObj3
+2050 data obj4Timer         ; Saucer and squiggly shot, GameObj4
+2067 data playerDataMSB     ; 21 for player 1, 22 for player 2
+2068 data playerOK
+2069 data enableAlienFire
+2072 data vblankStatus
+2082 data numAliens
+20c0 data isrDelay
+20c1 data isrSplashTask
+20eb data numCoins          ; BCD
+20ef data gameMode          ; 1 while a game is played
+20f4 data HiScor            ; BCD, 2 bytes
+20f8 data P1Scor            ; BCD, 2 bytes
+20fc data P2Scor            ; BCD, 2 bytes
+2100 data player1Data       ; Aliens and shields of player 1
+21ff data p1ShipsRem
+2200 data player2Data
+22ff data p2ShipsRem
+2400 data videoRAM
+";
+
+/// Built-in symbols of `set`, if there are any
+pub fn builtin_symbols(set: &str) -> Option<SymbolTable> {
+    match set {
+        "invaders" => Some(SymbolTable::parse(INVADERS_SYM).unwrap()),
+        _ => None,
+    }
+}
@@ -1,5 +1,9 @@
-pub use invaders::InvadersLaunch;
+pub use cheat::{Cheat, CheatCode, CheatEngine, CheatKind, RamSearch, SearchCondition};
+pub use hiscore::{Hiscore, HiscoreDescriptor, HiscoreEntry};
+pub use invaders::{find_driver, Driver, InvadersLaunch, DRIVERS};
 pub use launch::Launch;
 
-mod invaders;
+pub mod cheat;
+pub mod hiscore;
+pub mod invaders;
 mod launch;
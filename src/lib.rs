@@ -1,9 +1,11 @@
 pub use cpu::Cpu;
+pub use cpu::DynCpu;
 pub use cpu::Register;
 pub use memory::TestAddressing;
 
-mod game;
-
 pub mod cpu;
+pub mod device;
+pub mod game;
+pub mod machine;
 pub mod memory;
 pub mod util;
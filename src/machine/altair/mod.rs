@@ -0,0 +1,82 @@
+mod sio;
+
+use std::io;
+use std::marker::PhantomData;
+
+use crate::cpu::{Cpu, IoBus};
+use crate::machine::Terminal;
+use crate::memory::loader;
+use crate::memory::{AddressBus, FlatRam};
+
+pub use sio::{SenseSwitches, Sio2, SENSE_SWITCH_PORT};
+
+/// Base port of the 88-2SIO
+pub const SIO_BASE: u8 = 0x10;
+
+/// MITS Altair 8800 with 64K of RAM, the front panel sense switches and an 88-2SIO serial
+/// board wired to a terminal
+pub struct Altair<T: Terminal> {
+    pub cpu: Cpu<FlatRam, IoBus>,
+    io: IoBus,
+    terminal: PhantomData<T>,
+}
+
+impl<T: Terminal + 'static> Altair<T> {
+    pub fn new(terminal: T) -> Self {
+        let mut io = IoBus::new();
+        let sio = Sio2::new(terminal, SIO_BASE);
+        let ports = sio.ports();
+        io.attach(sio).input(ports.clone()).output(ports);
+        io.attach(SenseSwitches::default())
+            .input([SENSE_SWITCH_PORT]);
+        let cpu = Cpu::new(FlatRam::new(), 0);
+        Self {
+            cpu,
+            io,
+            terminal: PhantomData,
+        }
+    }
+
+    /// Load a raw image at `addr`
+    pub fn load_binary(&mut self, data: &[u8], addr: u16) {
+        loader::load_binary(&mut self.cpu.addring, data, addr);
+    }
+
+    /// Load an Intel HEX image, jumping to its start address record if it has one
+    pub fn load_hex(&mut self, text: &str) -> io::Result<Option<u16>> {
+        loader::boot_hex(&mut self.cpu, text)
+    }
+
+    pub fn set_sense_switches(&mut self, value: u8) {
+        self.io.device_mut::<SenseSwitches>().unwrap().value = value;
+    }
+
+    pub fn sio(&self) -> &Sio2<T> {
+        self.io.device::<Sio2<T>>().unwrap()
+    }
+
+    pub fn sio_mut(&mut self) -> &mut Sio2<T> {
+        self.io.device_mut::<Sio2<T>>().unwrap()
+    }
+
+    /// RESET switch on the front panel: the CPU restarts at 0, memory is kept
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    /// Switch off and on: RAM is filled with `fill` repeated, the serial board and the
+    /// CPU start over. The sense switches keep their position.
+    pub fn power_cycle(&mut self, fill: &[u8]) {
+        self.cpu.addring.fill_ram(fill);
+        self.io.reset();
+        self.cpu.power_on();
+    }
+
+    /// Run at least `cycles` clock cycles
+    pub fn run(&mut self, cycles: u64) {
+        let mut done: u64 = 0;
+        while done < cycles {
+            done += u64::from(self.cpu.step(&mut self.io));
+        }
+    }
+}
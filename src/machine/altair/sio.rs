@@ -0,0 +1,91 @@
+use crate::cpu::PortDevice;
+use crate::machine::Terminal;
+
+/// Status register bits of the MC6850 ACIA
+const RDRF: u8 = 0b0000_0001;
+const TDRE: u8 = 0b0000_0010;
+
+/// MITS 88-2SIO serial board. Channel A (status/control at `base`, data at `base + 1`) is
+/// connected to a [Terminal], channel B (`base + 2`) has nothing attached.
+pub struct Sio2<T: Terminal> {
+    terminal: T,
+    base: u8,
+    /// Byte received but not read by the CPU yet
+    rx: Option<u8>,
+}
+
+impl<T: Terminal> Sio2<T> {
+    /// The usual 88-2SIO base port is 0x10
+    pub fn new(terminal: T, base: u8) -> Self {
+        Self {
+            terminal,
+            base,
+            rx: None,
+        }
+    }
+
+    /// Ports of the board, to map it on an [IoBus](crate::cpu::IoBus)
+    pub fn ports(&self) -> std::ops::RangeInclusive<u8> {
+        self.base..=self.base + 3
+    }
+
+    pub fn terminal(&self) -> &T {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut T {
+        &mut self.terminal
+    }
+
+    fn poll(&mut self) {
+        if self.rx.is_none() {
+            self.rx = self.terminal.read();
+        }
+    }
+}
+
+impl<T: Terminal + 'static> PortDevice for Sio2<T> {
+    fn input(&mut self, port: u8) -> u8 {
+        match port.wrapping_sub(self.base) {
+            0 => {
+                self.poll();
+                TDRE | if self.rx.is_some() { RDRF } else { 0 }
+            }
+            1 => {
+                self.poll();
+                self.rx.take().unwrap_or(0)
+            }
+            // Channel B: always ready to send, never receives
+            2 => TDRE,
+            _ => 0xff,
+        }
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        match port.wrapping_sub(self.base) {
+            // Master reset
+            0 if val & 0x03 == 0x03 => self.rx = None,
+            1 => self.terminal.write(val),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.rx = None;
+    }
+}
+
+/// Port of the front panel sense switches
+pub const SENSE_SWITCH_PORT: u8 = 0xff;
+
+/// The eight sense switches on the front panel, A8-A15
+#[derive(Default)]
+pub struct SenseSwitches {
+    pub value: u8,
+}
+
+impl PortDevice for SenseSwitches {
+    fn input(&mut self, _port: u8) -> u8 {
+        self.value
+    }
+}
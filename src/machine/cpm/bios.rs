@@ -0,0 +1,366 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cpu::Register;
+use crate::cpu::IO;
+use crate::machine::cpm::disk::DiskImage;
+use crate::machine::Terminal;
+
+/// OUT port of the BIOS stubs, A holds the function number
+pub const BIOS_PORT: u8 = 0xfe;
+/// OUT port of the BDOS stub used when running a program without a system disk
+pub const BDOS_PORT: u8 = 0xfd;
+
+/// Number of BIOS entry points in CP/M 2.2
+const BIOS_FUNCTIONS: u16 = 17;
+pub const DRIVES: usize = 4;
+const SECTOR_SIZE: usize = 128;
+
+/// Offsets of the BIOS tables from the BIOS base
+const STUBS: u16 = 0x40;
+const STUB_SIZE: u16 = 5;
+const DPB: u16 = 0x100;
+const XLT: u16 = 0x110;
+const DPH: u16 = 0x130;
+const DIRBUF: u16 = 0x170;
+const ALV: u16 = 0x1f0;
+const ALV_SIZE: u16 = 31;
+const CSV: u16 = 0x26c;
+const CSV_SIZE: u16 = 16;
+
+/// Disk parameter block of the 8" IBM 3740 format: 26 sectors per track, 1K blocks,
+/// 243 blocks, 64 directory entries, 2 system tracks
+const DPB_3740: [u8; 15] = [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xc0, 0x00, 16, 0, 2, 0];
+
+/// Standard sector skew of 6
+pub const XLT_3740: [u8; 26] = [
+    1, 7, 13, 19, 25, 5, 11, 17, 23, 3, 9, 15, 21, 2, 8, 14, 20, 26, 6, 12, 18, 24, 4, 10, 16, 22,
+];
+
+/// Size of CCP + BDOS loaded from the system tracks
+pub const SYSTEM_SIZE: u16 = 0x1600;
+/// Offset of the BDOS entry from the CCP base
+const BDOS_ENTRY: u16 = 0x0806;
+
+/// BIOS of a CP/M 2.2 machine. The jump table in emulated memory points at stubs that
+/// `OUT` to [BIOS_PORT]; the functions are then carried out here, with DMA straight into
+/// the shared memory.
+pub struct CpmBios<T: Terminal> {
+    memory: Rc<RefCell<Vec<u8>>>,
+    terminal: T,
+    pub disks: [Option<DiskImage>; DRIVES],
+    /// Address of the BIOS jump table
+    base: u16,
+    disk: usize,
+    track: u16,
+    sector: u16,
+    dma: u16,
+    /// Console byte read ahead by a status call
+    pending: Option<u8>,
+    /// No system disk: warm boot ends the program instead of reloading the CCP
+    shim: bool,
+    exited: bool,
+}
+
+impl<T: Terminal> CpmBios<T> {
+    pub fn new(memory: Rc<RefCell<Vec<u8>>>, terminal: T, base: u16) -> Self {
+        Self {
+            memory,
+            terminal,
+            disks: Default::default(),
+            base,
+            disk: 0,
+            track: 0,
+            sector: 1,
+            dma: 0x0080,
+            pending: None,
+            shim: false,
+            exited: false,
+        }
+    }
+
+    pub fn terminal(&self) -> &T {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut T {
+        &mut self.terminal
+    }
+
+    /// Whether a shimmed program has returned to the system
+    pub fn is_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Base address of the CCP that matches this BIOS
+    pub fn ccp_base(&self) -> u16 {
+        self.base - SYSTEM_SIZE
+    }
+
+    /// Write the jump table, stubs and disk tables into memory
+    pub fn install(&self) {
+        let mut mem = self.memory.borrow_mut();
+        let base = self.base as usize;
+        for i in 0..BIOS_FUNCTIONS {
+            let entry = base + i as usize * 3;
+            let stub = self.base + STUBS + i * STUB_SIZE;
+            mem[entry] = 0xc3;
+            mem[entry + 1..entry + 3].copy_from_slice(&stub.to_le_bytes());
+            // MVI A,i; OUT BIOS_PORT; RET
+            let stub = stub as usize;
+            mem[stub..stub + 5].copy_from_slice(&[0x3e, i as u8, 0xd3, BIOS_PORT, 0xc9]);
+        }
+        mem[base + DPB as usize..base + DPB as usize + DPB_3740.len()].copy_from_slice(&DPB_3740);
+        mem[base + XLT as usize..base + XLT as usize + XLT_3740.len()].copy_from_slice(&XLT_3740);
+        for drive in 0..DRIVES as u16 {
+            let dph = base + (DPH + drive * 16) as usize;
+            let words = [
+                self.base + XLT,
+                0,
+                0,
+                0,
+                self.base + DIRBUF,
+                self.base + DPB,
+                self.base + CSV + drive * CSV_SIZE,
+                self.base + ALV + drive * ALV_SIZE,
+            ];
+            for (i, word) in words.iter().enumerate() {
+                mem[dph + i * 2..dph + i * 2 + 2].copy_from_slice(&word.to_le_bytes());
+            }
+        }
+    }
+
+    /// Point page zero at the BIOS and at `bdos`
+    fn set_page_zero(&self, bdos: u16) {
+        let mut mem = self.memory.borrow_mut();
+        let wboot = self.base + 3;
+        mem[0..3].copy_from_slice(&[0xc3, wboot as u8, (wboot >> 8) as u8]);
+        mem[5..8].copy_from_slice(&[0xc3, bdos as u8, (bdos >> 8) as u8]);
+    }
+
+    /// Load CCP and BDOS from the system tracks of drive A, skipping the boot sector.
+    /// Returns false when drive A is missing.
+    pub fn load_system(&mut self) -> bool {
+        let disk = match &self.disks[0] {
+            Some(disk) => disk,
+            None => return false,
+        };
+        let sectors = disk.geometry().sectors;
+        let mut mem = self.memory.borrow_mut();
+        let mut addr = self.ccp_base() as usize;
+        let mut buf = [0u8; SECTOR_SIZE];
+        // Track 0 sector 2 onwards, in physical order
+        let (mut track, mut sector) = (0, 2);
+        while addr < self.base as usize {
+            if !disk.read_sector(track, sector, &mut buf) {
+                return false;
+            }
+            mem[addr..addr + SECTOR_SIZE].copy_from_slice(&buf);
+            addr += SECTOR_SIZE;
+            sector += 1;
+            if sector > sectors {
+                track += 1;
+                sector = 1;
+            }
+        }
+        drop(mem);
+        self.install();
+        self.set_page_zero(self.ccp_base() + BDOS_ENTRY);
+        self.shim = false;
+        true
+    }
+
+    /// Set up page zero for running a program with the built-in console BDOS, whose entry
+    /// sits where the real BDOS entry would be
+    pub fn install_shim(&mut self) {
+        self.install();
+        let bdos = self.ccp_base() + BDOS_ENTRY;
+        // OUT BDOS_PORT; RET
+        let stub = bdos as usize;
+        self.memory.borrow_mut()[stub..stub + 3].copy_from_slice(&[0xd3, BDOS_PORT, 0xc9]);
+        self.set_page_zero(bdos);
+        self.shim = true;
+        self.exited = false;
+    }
+
+    /// Start the CCP after a cold or warm boot
+    fn enter_ccp(&mut self, cpu: &mut Register) {
+        if self.shim || !self.load_system() {
+            self.exited = true;
+            // Spin on the stub until the machine notices
+            cpu.pc = cpu.pc.wrapping_sub(4);
+            return;
+        }
+        cpu.c = self.memory.borrow()[4] & 0x0f;
+        cpu.sp = 0x0080;
+        cpu.pc = self.ccp_base();
+    }
+
+    fn bios(&mut self, cpu: &mut Register, function: u8) {
+        match function {
+            // BOOT
+            0 => {
+                self.memory.borrow_mut()[3..5].copy_from_slice(&[0, 0]);
+                self.enter_ccp(cpu);
+            }
+            // WBOOT
+            1 => self.enter_ccp(cpu),
+            // CONST
+            2 => cpu.a = if self.console_ready() { 0xff } else { 0 },
+            // CONIN
+            3 => match self.console_read() {
+                Some(byte) => cpu.a = byte & 0x7f,
+                // Run the stub again until a key arrives
+                None => cpu.pc = cpu.pc.wrapping_sub(4),
+            },
+            // CONOUT
+            4 => self.terminal.write(cpu.c),
+            // LIST, PUNCH
+            5 | 6 => {}
+            // READER: end of file
+            7 => cpu.a = 0x1a,
+            // HOME
+            8 => self.track = 0,
+            // SELDSK
+            9 => {
+                let disk = cpu.c as usize;
+                let dph = if disk < DRIVES && self.disks[disk].is_some() {
+                    self.disk = disk;
+                    self.base + DPH + disk as u16 * 16
+                } else {
+                    0
+                };
+                cpu.set_hl(dph);
+            }
+            // SETTRK
+            10 => self.track = cpu.get_bc(),
+            // SETSEC
+            11 => self.sector = cpu.get_bc(),
+            // SETDMA
+            12 => self.dma = cpu.get_bc(),
+            // READ
+            13 => cpu.a = u8::from(!self.transfer(false)),
+            // WRITE
+            14 => cpu.a = u8::from(!self.transfer(true)),
+            // LISTST: always ready
+            15 => cpu.a = 0xff,
+            // SECTRAN
+            16 => {
+                let sector = cpu.get_bc();
+                let translated = if cpu.get_de() == 0 {
+                    sector + 1
+                } else {
+                    XLT_3740
+                        .get(sector as usize)
+                        .map_or(sector + 1, |s| *s as u16)
+                };
+                cpu.set_hl(translated);
+            }
+            _ => {}
+        }
+    }
+
+    /// Move one sector between the selected disk and the DMA address
+    fn transfer(&mut self, write: bool) -> bool {
+        let disk = match self.disks[self.disk].as_mut() {
+            Some(disk) => disk,
+            None => return false,
+        };
+        let mut mem = self.memory.borrow_mut();
+        let dma = self.dma as usize;
+        if dma + SECTOR_SIZE > mem.len() {
+            return false;
+        }
+        let buf = &mut mem[dma..dma + SECTOR_SIZE];
+        if write {
+            disk.write_sector(self.track, self.sector, buf)
+        } else {
+            disk.read_sector(self.track, self.sector, buf)
+        }
+    }
+
+    /// Console functions of the BDOS, enough for programs that only talk to the terminal
+    fn bdos(&mut self, cpu: &mut Register) {
+        let mut result: u8 = 0;
+        match cpu.c {
+            // System reset
+            0 => {
+                self.exited = true;
+                cpu.pc = cpu.pc.wrapping_sub(2);
+                return;
+            }
+            // Console input, with echo
+            1 => match self.console_read() {
+                Some(byte) => {
+                    self.terminal.write(byte);
+                    result = byte;
+                }
+                None => {
+                    cpu.pc = cpu.pc.wrapping_sub(2);
+                    return;
+                }
+            },
+            // Console output
+            2 => self.terminal.write(cpu.e),
+            // Direct console I/O
+            6 => {
+                if cpu.e == 0xff {
+                    result = self.console_read().unwrap_or(0);
+                } else {
+                    self.terminal.write(cpu.e);
+                }
+            }
+            // Print string terminated by '$'
+            9 => {
+                let mem = self.memory.borrow();
+                let mut addr = cpu.get_de();
+                while mem[addr as usize] != b'$' {
+                    self.terminal.write(mem[addr as usize]);
+                    addr = addr.wrapping_add(1);
+                }
+            }
+            // Console status
+            11 => result = if self.console_ready() { 0xff } else { 0 },
+            // Version: CP/M 2.2
+            12 => {
+                cpu.set_hl(0x0022);
+                cpu.a = 0x22;
+                cpu.b = 0;
+                return;
+            }
+            _ => result = 0xff,
+        }
+        cpu.a = result;
+        cpu.set_hl(u16::from(result));
+    }
+
+    /// A byte read ahead by a status call
+    fn console_ready(&mut self) -> bool {
+        if self.pending.is_none() {
+            self.pending = self.terminal.read();
+        }
+        self.pending.is_some()
+    }
+
+    fn console_read(&mut self) -> Option<u8> {
+        self.console_ready();
+        self.pending.take()
+    }
+}
+
+impl<T: Terminal> IO for CpmBios<T> {
+    fn input(&mut self, cpu: &mut Register, _byte: u8) {
+        cpu.a = 0xff;
+    }
+
+    fn output(&mut self, cpu: &mut Register, byte: u8) {
+        match byte {
+            BIOS_PORT => {
+                let function = cpu.a;
+                self.bios(cpu, function)
+            }
+            BDOS_PORT => self.bdos(cpu),
+            _ => {}
+        }
+    }
+}
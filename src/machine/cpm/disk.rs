@@ -0,0 +1,126 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Physical layout of a disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub tracks: u16,
+    pub sectors: u16,
+    pub sector_size: usize,
+}
+
+impl Geometry {
+    /// 8" single sided single density: 77 tracks of 26 sectors of 128 bytes
+    pub const IBM_3740: Geometry = Geometry {
+        tracks: 77,
+        sectors: 26,
+        sector_size: 128,
+    };
+
+    pub fn size(&self) -> usize {
+        self.tracks as usize * self.sectors as usize * self.sector_size
+    }
+}
+
+/// Value of a freshly formatted byte
+const FORMAT_FILL: u8 = 0xe5;
+
+/// A `.dsk` image: every sector in track order, sectors numbered from 1.
+/// Images opened from a file are written through on every sector write.
+pub struct DiskImage {
+    geometry: Geometry,
+    data: Vec<u8>,
+    file: Option<File>,
+    write_protected: bool,
+}
+
+impl DiskImage {
+    /// A blank formatted disk held in memory
+    pub fn blank(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            data: vec![FORMAT_FILL; geometry.size()],
+            file: None,
+            write_protected: false,
+        }
+    }
+
+    /// An in-memory disk with the given content, shorter images are padded
+    pub fn from_bytes(geometry: Geometry, bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() > geometry.size() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "image is larger than the disk geometry",
+            ));
+        }
+        let mut disk = Self::blank(geometry);
+        disk.data[..bytes.len()].copy_from_slice(bytes);
+        Ok(disk)
+    }
+
+    /// Open an image file. Read-only files are mounted write protected.
+    pub fn open(path: &Path, geometry: Geometry) -> io::Result<Self> {
+        let (mut file, writable) = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => (file, true),
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => (File::open(path)?, false),
+            Err(e) => return Err(e),
+        };
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let mut disk = Self::from_bytes(geometry, &bytes)?;
+        disk.write_protected = !writable;
+        if writable {
+            disk.file = Some(file);
+        }
+        Ok(disk)
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protected
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn offset(&self, track: u16, sector: u16) -> Option<usize> {
+        let g = self.geometry;
+        if track >= g.tracks || sector == 0 || sector > g.sectors {
+            return None;
+        }
+        Some((track as usize * g.sectors as usize + sector as usize - 1) * g.sector_size)
+    }
+
+    /// Returns false for a sector outside the disk
+    pub fn read_sector(&self, track: u16, sector: u16, buf: &mut [u8]) -> bool {
+        match self.offset(track, sector) {
+            Some(ofs) => {
+                buf.copy_from_slice(&self.data[ofs..ofs + self.geometry.sector_size]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false for a sector outside the disk, a write protected disk or a failed write
+    /// to the image file
+    pub fn write_sector(&mut self, track: u16, sector: u16, buf: &[u8]) -> bool {
+        let ofs = match self.offset(track, sector) {
+            Some(ofs) if !self.write_protected => ofs,
+            _ => return false,
+        };
+        self.data[ofs..ofs + self.geometry.sector_size].copy_from_slice(buf);
+        match self.file.as_mut() {
+            Some(file) => file
+                .seek(SeekFrom::Start(ofs as u64))
+                .and_then(|_| file.write_all(buf))
+                .is_ok(),
+            None => true,
+        }
+    }
+}
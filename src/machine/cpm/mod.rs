@@ -0,0 +1,102 @@
+mod bios;
+mod disk;
+
+use std::cell::RefCell;
+use std::io::{self, ErrorKind};
+use std::rc::Rc;
+
+use crate::cpu::{Cpu, Variant};
+use crate::machine::Terminal;
+use crate::memory::{AddressBus, SharedRam};
+
+pub use bios::{CpmBios, BDOS_PORT, BIOS_PORT, DRIVES, SYSTEM_SIZE, XLT_3740};
+pub use disk::{DiskImage, Geometry};
+
+/// BIOS base of a 64K CP/M 2.2 system
+pub const BIOS_BASE_64K: u16 = 0xfa00;
+/// Programs are loaded and started here
+pub const TPA: u16 = 0x0100;
+
+/// A CP/M 2.2 computer: 64K of RAM, up to four 8" drives and a console terminal.
+///
+/// Boots CCP and BDOS from the system tracks of drive A, or runs a single `.COM`
+/// program with a console-only BDOS when there is no system disk.
+pub struct Cpm<T: Terminal> {
+    pub cpu: Cpu<SharedRam, CpmBios<T>>,
+    bios: CpmBios<T>,
+}
+
+impl<T: Terminal + 'static> Cpm<T> {
+    /// `bios_base` must match the system on the boot disk, see [BIOS_BASE_64K]
+    pub fn new(terminal: T, bios_base: u16) -> Self {
+        Self::with_variant(terminal, bios_base, Variant::I8080)
+    }
+
+    /// Same machine with an 8085 or a Z80, e.g. to run ZEXDOC/ZEXALL
+    pub fn with_variant(terminal: T, bios_base: u16, variant: Variant) -> Self {
+        let memory = Rc::new(RefCell::new(vec![0u8; 65536]));
+        let bios = CpmBios::new(memory.clone(), terminal, bios_base);
+        let cpu = Cpu::with_variant(SharedRam::new(memory), 0, variant);
+        Self { cpu, bios }
+    }
+
+    /// Insert a disk, `drive` 0 is A:
+    pub fn mount(&mut self, drive: usize, disk: DiskImage) {
+        self.bios.disks[drive] = Some(disk);
+    }
+
+    /// Cold boot from drive A
+    pub fn boot(&mut self) -> io::Result<()> {
+        let bios = &mut self.bios;
+        if !bios.load_system() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "drive A is missing or has no system tracks",
+            ));
+        }
+        self.cpu.register.pc = bios.ccp_base();
+        self.cpu.register.sp = 0x0080;
+        self.cpu.register.c = 0;
+        Ok(())
+    }
+
+    /// Load a `.COM` program at 0x0100 and start it with the console-only BDOS.
+    /// Returning or calling BDOS function 0 ends it, see [Cpm::is_exited].
+    pub fn load_com(&mut self, data: &[u8]) {
+        let bios = &mut self.bios;
+        bios.install_shim();
+        for (i, byte) in data.iter().enumerate() {
+            self.cpu.addring.set_mem(TPA.wrapping_add(i as u16), *byte);
+        }
+        // Return address 0 warm boots
+        self.cpu.register.sp = bios.ccp_base();
+        self.cpu.register.sp -= 2;
+        self.cpu.addring.set_word(self.cpu.register.sp, 0x0000);
+        self.cpu.register.pc = TPA;
+    }
+
+    pub fn is_exited(&self) -> bool {
+        self.bios.is_exited()
+    }
+
+    pub fn bios(&self) -> &CpmBios<T> {
+        &self.bios
+    }
+
+    pub fn bios_mut(&mut self) -> &mut CpmBios<T> {
+        &mut self.bios
+    }
+
+    /// Run one instruction, returns its cycles
+    pub fn step(&mut self) -> u8 {
+        self.cpu.step(&mut self.bios)
+    }
+
+    /// Run at least `cycles` clock cycles, stopping early when the program exits
+    pub fn run(&mut self, cycles: u64) {
+        let mut done: u64 = 0;
+        while done < cycles && !self.is_exited() {
+            done += u64::from(self.step());
+        }
+    }
+}
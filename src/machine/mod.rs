@@ -0,0 +1,7 @@
+pub mod altair;
+pub mod cpm;
+mod terminal;
+
+pub use altair::Altair;
+pub use cpm::Cpm;
+pub use terminal::{BufferTerminal, HostTerminal, Terminal};
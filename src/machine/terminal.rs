@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Character device a serial or console port talks to
+pub trait Terminal {
+    /// Next received byte, without blocking
+    fn read(&mut self) -> Option<u8>;
+
+    fn write(&mut self, byte: u8);
+}
+
+/// The host's stdin/stdout. Stdin is read on a background thread so polling never blocks.
+pub struct HostTerminal {
+    rx: Receiver<u8>,
+    /// Set once stdin is closed
+    eof: bool,
+}
+
+impl HostTerminal {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for byte in stdin.lock().bytes() {
+                let byte = match byte {
+                    Ok(b'\n') => b'\r',
+                    Ok(b) => b,
+                    Err(_) => break,
+                };
+                if tx.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { rx, eof: false }
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.eof
+    }
+}
+
+impl Default for HostTerminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Terminal for HostTerminal {
+    fn read(&mut self) -> Option<u8> {
+        match self.rx.try_recv() {
+            Ok(byte) => Some(byte),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.eof = true;
+                None
+            }
+        }
+    }
+
+    fn write(&mut self, byte: u8) {
+        let mut stdout = io::stdout();
+        // Programs of the era often set bit 7
+        let _ = stdout.write_all(&[byte & 0x7f]);
+        let _ = stdout.flush();
+    }
+}
+
+/// In-memory terminal, for tests and scripted sessions
+#[derive(Default)]
+pub struct BufferTerminal {
+    pub input: VecDeque<u8>,
+    pub output: Vec<u8>,
+}
+
+impl BufferTerminal {
+    pub fn new(input: &[u8]) -> Self {
+        Self {
+            input: input.iter().copied().collect(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Terminal for BufferTerminal {
+    fn read(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+}
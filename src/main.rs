@@ -1,12 +1,105 @@
-use crate::game::{InvadersLaunch, Launch};
+use std::env;
+use std::path::PathBuf;
+use std::process;
 
-mod util;
+use space_invaders_8080::game::{find_driver, Driver, InvadersLaunch, Launch, DRIVERS};
 
-mod cpu;
-mod game;
-mod memory;
+fn usage() {
+    eprintln!(
+        "Usage: space-invaders-8080 [--list | [--no-watchdog] [--symbols FILE] [--bench FRAMES | --profile FRAMES [--listing FILE] | --disasm FILE | --gdb PORT] <game>]"
+    );
+}
 
 fn main() {
-    let launch = InvadersLaunch::new();
-    launch.start();
+    let mut driver: Option<&'static Driver> = None;
+    let mut watchdog = true;
+    let mut bench = None;
+    let mut profile = None;
+    let mut listing = None;
+    let mut symbols = None;
+    let mut disasm = None;
+    let mut gdb = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" => {
+                for driver in DRIVERS {
+                    println!("{:<10} {}", driver.name, driver.description);
+                }
+                return;
+            }
+            "-h" | "--help" => {
+                usage();
+                return;
+            }
+            "--no-watchdog" => watchdog = false,
+            "--bench" => match args.next().and_then(|v| v.parse::<u32>().ok()) {
+                Some(frames) => bench = Some(frames),
+                None => {
+                    usage();
+                    process::exit(1);
+                }
+            },
+            "--profile" => match args.next().and_then(|v| v.parse::<u32>().ok()) {
+                Some(frames) => profile = Some(frames),
+                None => {
+                    usage();
+                    process::exit(1);
+                }
+            },
+            "--listing" => match args.next() {
+                Some(file) => listing = Some(PathBuf::from(file)),
+                None => {
+                    usage();
+                    process::exit(1);
+                }
+            },
+            "--symbols" => match args.next() {
+                Some(file) => symbols = Some(PathBuf::from(file)),
+                None => {
+                    usage();
+                    process::exit(1);
+                }
+            },
+            "--disasm" => match args.next() {
+                Some(file) => disasm = Some(PathBuf::from(file)),
+                None => {
+                    usage();
+                    process::exit(1);
+                }
+            },
+            "--gdb" => match args.next().and_then(|v| v.parse::<u16>().ok()) {
+                Some(port) => gdb = Some(port),
+                None => {
+                    usage();
+                    process::exit(1);
+                }
+            },
+            name if driver.is_none() && !name.starts_with('-') => {
+                driver = Some(find_driver(name).unwrap_or_else(|| {
+                    eprintln!("Unknown game: {}, try --list", name);
+                    process::exit(1);
+                }))
+            }
+            _ => {
+                usage();
+                process::exit(1);
+            }
+        }
+    }
+    let mut launch = InvadersLaunch::new(driver.unwrap_or(DRIVERS[0])).with_watchdog(watchdog);
+    if let Some(file) = symbols {
+        launch = launch.with_symbols(file);
+    }
+    let headless = match (bench, profile, disasm, gdb) {
+        (Some(frames), ..) => launch.bench(frames),
+        (_, Some(frames), ..) => launch.profile(frames, listing.as_deref()),
+        (_, _, Some(file), _) => launch.disassemble(&file),
+        (.., Some(port)) => launch.gdb(&format!("127.0.0.1:{}", port)),
+        _ => return launch.start(),
+    };
+    headless.unwrap_or_else(|e| {
+        eprintln!("Failed to run: {}", e);
+        process::exit(1);
+    });
 }
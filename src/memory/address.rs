@@ -5,11 +5,47 @@ pub trait AddressBus {
     fn set_mem(&mut self, addr: u16, val: u8);
 
     fn get_word(&self, addr: u16) -> u16 {
-        u16::from(self.get_mem(addr)) | (u16::from(self.get_mem(addr + 1)) << 8)
+        u16::from(self.get_mem(addr)) | (u16::from(self.get_mem(addr.wrapping_add(1))) << 8)
     }
 
+    /// The address `addr` decodes to: two addresses of the same byte give the same
+    /// answer. Buses that mirror memory override it so the block cache sees writes
+    /// through a mirror.
+    fn canonical(&self, addr: u16) -> u16 {
+        addr
+    }
+
+    /// Power on: overwrite all RAM with `pattern` repeated, leaving ROM and devices alone
+    fn fill_ram(&mut self, _pattern: &[u8]) {}
+
     fn set_word(&mut self, addr: u16, value: u16) {
         self.set_mem(addr, (value & 0xFF) as u8);
-        self.set_mem(addr + 1, (value >> 8) as u8)
+        self.set_mem(addr.wrapping_add(1), (value >> 8) as u8)
+    }
+}
+
+impl<T: AddressBus + ?Sized> AddressBus for Box<T> {
+    fn get_mem(&self, addr: u16) -> u8 {
+        (**self).get_mem(addr)
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        (**self).set_mem(addr, val)
+    }
+
+    fn get_word(&self, addr: u16) -> u16 {
+        (**self).get_word(addr)
+    }
+
+    fn canonical(&self, addr: u16) -> u16 {
+        (**self).canonical(addr)
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        (**self).fill_ram(pattern)
+    }
+
+    fn set_word(&mut self, addr: u16, value: u16) {
+        (**self).set_word(addr, value)
     }
 }
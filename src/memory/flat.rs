@@ -0,0 +1,74 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::memory::AddressBus;
+
+/// 64K of RAM filling the whole address space
+pub struct FlatRam {
+    data: Box<[u8; 65536]>,
+}
+
+impl AddressBus for FlatRam {
+    fn get_mem(&self, addr: u16) -> u8 {
+        self.data[addr as usize]
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        self.data[addr as usize] = val
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        fill(self.data.as_mut_slice(), pattern);
+    }
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        Self {
+            data: Box::new([0u8; 65536]),
+        }
+    }
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 64K of RAM shared with devices that do DMA, such as a disk controller
+pub struct SharedRam {
+    data: Rc<RefCell<Vec<u8>>>,
+}
+
+impl AddressBus for SharedRam {
+    fn get_mem(&self, addr: u16) -> u8 {
+        self.data.borrow()[addr as usize]
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        self.data.borrow_mut()[addr as usize] = val
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        fill(&mut self.data.borrow_mut(), pattern);
+    }
+}
+
+impl SharedRam {
+    /// `data` must hold 64K
+    pub fn new(data: Rc<RefCell<Vec<u8>>>) -> Self {
+        assert_eq!(data.borrow().len(), 65536);
+        Self { data }
+    }
+}
+
+/// Repeat `pattern` over `data`
+pub(crate) fn fill(data: &mut [u8], pattern: &[u8]) {
+    if pattern.is_empty() {
+        return;
+    }
+    for (byte, value) in data.iter_mut().zip(pattern.iter().cycle()) {
+        *byte = *value;
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{self, ErrorKind};
+
+use crate::cpu::{Cpu, IO};
+use crate::memory::AddressBus;
+
+/// Copy a raw image into memory at `addr`, wrapping at the top of the address space
+pub fn load_binary(bus: &mut dyn AddressBus, data: &[u8], addr: u16) {
+    for (i, byte) in data.iter().enumerate() {
+        bus.set_mem(addr.wrapping_add(i as u16), *byte);
+    }
+}
+
+/// Load an Intel HEX image. Data records are written to `bus`; extended segment (02) and
+/// extended linear (04) address records are honoured as long as the data stays inside
+/// 64K. Returns the start address of a type 03 or 05 record, if any.
+/// Anything after the EOF record is ignored.
+pub fn load_hex(bus: &mut dyn AddressBus, text: &str) -> io::Result<Option<u16>> {
+    let mut base: u32 = 0;
+    let mut start = None;
+    for (no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |msg: &str| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} at line {}", msg, no + 1),
+            )
+        };
+        let hex = line.strip_prefix(':').ok_or_else(|| error("missing ':'"))?;
+        if hex.len() % 2 != 0 || hex.len() < 10 {
+            return Err(error("bad HEX record"));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| error("bad HEX digit"))?;
+        let len = bytes[0] as usize;
+        if bytes.len() != len + 5 {
+            return Err(error("bad record length"));
+        }
+        if bytes.iter().fold(0u8, |s, b| s.wrapping_add(*b)) != 0 {
+            return Err(error("bad checksum"));
+        }
+        let addr = u16::from_be_bytes([bytes[1], bytes[2]]);
+        let data = &bytes[4..4 + len];
+        match (bytes[3], len) {
+            (0x00, _) => {
+                if base + u32::from(addr) + len as u32 > 0x10000 {
+                    return Err(error("data outside the 64K address space"));
+                }
+                // Only an empty record can start at 10000h, and it writes nothing
+                load_binary(bus, data, (base as u16).wrapping_add(addr));
+            }
+            (0x01, _) => return Ok(start),
+            (0x02, 2) => base = u32::from(u16::from_be_bytes([data[0], data[1]])) << 4,
+            (0x04, 2) => base = u32::from(u16::from_be_bytes([data[0], data[1]])) << 16,
+            // Start segment address, CS:IP
+            (0x03, 4) => {
+                let cs = u32::from(u16::from_be_bytes([data[0], data[1]]));
+                let ip = u32::from(u16::from_be_bytes([data[2], data[3]]));
+                let addr = u16::try_from((cs << 4) + ip)
+                    .map_err(|_| error("start address outside the 64K address space"))?;
+                start = Some(addr);
+            }
+            // Start linear address
+            (0x05, 4) => {
+                if data[0] != 0 || data[1] != 0 {
+                    return Err(error("start address outside the 64K address space"));
+                }
+                start = Some(u16::from_be_bytes([data[2], data[3]]));
+            }
+            (0x02..=0x05, _) => return Err(error("bad record length")),
+            _ => return Err(error("unknown record type")),
+        }
+    }
+    Ok(start)
+}
+
+/// Load an Intel HEX image into the CPU's bus and jump to its start address, if it has one
+pub fn boot_hex<B: AddressBus, I: IO + ?Sized>(
+    cpu: &mut Cpu<B, I>,
+    text: &str,
+) -> io::Result<Option<u16>> {
+    let start = load_hex(&mut cpu.addring, text)?;
+    if let Some(pc) = start {
+        cpu.register.pc = pc;
+    }
+    Ok(start)
+}
+
+/// Load a raw image at `addr` and jump to `pc`
+pub fn boot_binary<B: AddressBus, I: IO + ?Sized>(
+    cpu: &mut Cpu<B, I>,
+    data: &[u8],
+    addr: u16,
+    pc: u16,
+) {
+    load_binary(&mut cpu.addring, data, addr);
+    cpu.register.pc = pc;
+}
@@ -0,0 +1,367 @@
+use std::cell::{Ref, RefCell};
+use std::io::{self, ErrorKind};
+use std::rc::Rc;
+
+use crate::memory::flat::fill;
+use crate::memory::mmio::{downcast_mut, downcast_ref, Callbacks};
+use crate::memory::{AddressBus, MmioDevice};
+
+/// Size of one page of the dispatch table
+const PAGE_SIZE: usize = 256;
+const PAGES: usize = 0x10000 / PAGE_SIZE;
+
+/// Read callback of an MMIO region, gets the offset into the region
+pub type ReadFn = Box<dyn FnMut(u16) -> u8>;
+/// Write callback of an MMIO region, gets the offset into the region
+pub type WriteFn = Box<dyn FnMut(u16, u8)>;
+
+/// What the CPU may do with a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Writes are ignored
+    Rom,
+    Ram,
+    /// Reads and writes go to an [MmioDevice]
+    Mmio,
+}
+
+enum Storage {
+    Owned(Box<[u8]>),
+    /// Shared with a device, such as the video RAM a display reads
+    Shared(Rc<RefCell<Vec<u8>>>),
+    /// Reads have side effects but `get_mem` only has `&self`, hence the `RefCell`
+    Mmio(RefCell<Box<dyn MmioDevice>>),
+}
+
+/// A block of the address space: `size` bytes at `base`, answered at every address `a`
+/// with `a & mask` inside the block
+pub struct Region {
+    base: u16,
+    size: usize,
+    mask: u16,
+    access: Access,
+    storage: Storage,
+}
+
+impl Region {
+    pub fn rom(base: u16, data: Vec<u8>) -> Self {
+        Self::new(
+            base,
+            data.len(),
+            Access::Rom,
+            Storage::Owned(data.into_boxed_slice()),
+        )
+    }
+
+    /// RAM cleared to zero
+    pub fn ram(base: u16, size: usize) -> Self {
+        let data = vec![0u8; size].into_boxed_slice();
+        Self::new(base, size, Access::Ram, Storage::Owned(data))
+    }
+
+    /// Storage owned by someone else as well; `access` must be ROM or RAM
+    pub fn shared(base: u16, data: Rc<RefCell<Vec<u8>>>, access: Access) -> Self {
+        assert_ne!(access, Access::Mmio, "shared storage is plain memory");
+        let size = data.borrow().len();
+        Self::new(base, size, access, Storage::Shared(data))
+    }
+
+    /// `size` addresses answered by `device`
+    pub fn device(base: u16, size: usize, device: impl MmioDevice) -> Self {
+        let device: Box<dyn MmioDevice> = Box::new(device);
+        Self::new(
+            base,
+            size,
+            Access::Mmio,
+            Storage::Mmio(RefCell::new(device)),
+        )
+    }
+
+    /// `size` addresses answered by a pair of callbacks
+    pub fn mmio(
+        base: u16,
+        size: usize,
+        read: impl FnMut(u16) -> u8 + 'static,
+        write: impl FnMut(u16, u8) + 'static,
+    ) -> Self {
+        Self::device(
+            base,
+            size,
+            Callbacks {
+                read: Box::new(read),
+                write: Box::new(write),
+            },
+        )
+    }
+
+    fn new(base: u16, size: usize, access: Access, storage: Storage) -> Self {
+        Self {
+            base,
+            size,
+            mask: 0xffff,
+            access,
+            storage,
+        }
+    }
+
+    /// Only decode the address lines in `mask`, so the region repeats through memory
+    pub fn mirror(mut self, mask: u16) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn access(&self) -> Access {
+        self.access
+    }
+
+    fn end(&self) -> usize {
+        self.base as usize + self.size
+    }
+
+    /// Whether the region itself, not one of its mirrors, covers `addr`
+    fn covers(&self, addr: usize) -> bool {
+        (self.base as usize..self.end()).contains(&addr)
+    }
+
+    fn decodes(&self, addr: u16) -> bool {
+        self.covers((addr & self.mask) as usize)
+    }
+
+    fn offset(&self, addr: u16) -> u16 {
+        (addr & self.mask) - self.base
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        let offset = self.offset(addr);
+        match &self.storage {
+            Storage::Owned(data) => data[offset as usize],
+            Storage::Shared(data) => data.borrow()[offset as usize],
+            Storage::Mmio(device) => device.borrow_mut().read(offset),
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        let offset = self.offset(addr);
+        match &mut self.storage {
+            _ if self.access == Access::Rom => {}
+            Storage::Owned(data) => data[offset as usize] = val,
+            Storage::Shared(data) => data.borrow_mut()[offset as usize] = val,
+            Storage::Mmio(device) => device.get_mut().write(offset, val),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Unmapped,
+    /// The whole page belongs to one region
+    Region(u8),
+    /// Several regions or holes share the page, search them
+    Split,
+}
+
+/// Address bus assembled from [Region]s, dispatched through a 256-byte page table.
+/// Regions may not overlap each other; where a mirror image lands on a region the region
+/// wins, and mirror images of different regions may not collide.
+pub struct MemoryMap {
+    regions: Vec<Region>,
+    pages: [Page; PAGES],
+    /// Value read from unmapped addresses
+    open_bus: u8,
+}
+
+impl AddressBus for MemoryMap {
+    fn get_mem(&self, addr: u16) -> u8 {
+        match self.pages[addr as usize / PAGE_SIZE] {
+            Page::Region(i) => self.regions[i as usize].read(addr),
+            Page::Unmapped => self.open_bus,
+            Page::Split => match self.find(addr) {
+                Some(i) => self.regions[i].read(addr),
+                None => self.open_bus,
+            },
+        }
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        if let Some(i) = self.region_at(addr) {
+            self.regions[i].write(addr, val);
+        }
+    }
+
+    fn canonical(&self, addr: u16) -> u16 {
+        match self.region_at(addr) {
+            Some(i) => addr & self.regions[i].mask,
+            None => addr,
+        }
+    }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        for region in self.regions.iter_mut().filter(|r| r.access == Access::Ram) {
+            match &mut region.storage {
+                Storage::Owned(data) => fill(data, pattern),
+                Storage::Shared(data) => fill(&mut data.borrow_mut(), pattern),
+                Storage::Mmio(_) => {}
+            }
+        }
+    }
+}
+
+impl MemoryMap {
+    pub fn builder() -> MemoryMapBuilder {
+        MemoryMapBuilder {
+            regions: Vec::new(),
+            open_bus: 0xff,
+        }
+    }
+
+    /// Add a region to a built map
+    pub fn add(&mut self, region: Region) -> io::Result<()> {
+        check(&self.regions, &region)?;
+        self.regions.push(region);
+        match page_table(&self.regions) {
+            Ok(pages) => {
+                self.pages = pages;
+                Ok(())
+            }
+            Err(e) => {
+                self.regions.pop();
+                Err(e)
+            }
+        }
+    }
+
+    /// The first attached device of type `T`
+    pub fn device<T: MmioDevice>(&self) -> Option<Ref<'_, T>> {
+        self.regions.iter().find_map(|r| match &r.storage {
+            Storage::Mmio(device) => {
+                Ref::filter_map(device.borrow(), |d| downcast_ref::<T>(d.as_ref())).ok()
+            }
+            _ => None,
+        })
+    }
+
+    pub fn device_mut<T: MmioDevice>(&mut self) -> Option<&mut T> {
+        self.regions.iter_mut().find_map(|r| match &mut r.storage {
+            Storage::Mmio(device) => downcast_mut::<T>(device.get_mut().as_mut()),
+            _ => None,
+        })
+    }
+
+    fn region_at(&self, addr: u16) -> Option<usize> {
+        match self.pages[addr as usize / PAGE_SIZE] {
+            Page::Region(i) => Some(i as usize),
+            Page::Unmapped => None,
+            Page::Split => self.find(addr),
+        }
+    }
+
+    /// Slow path for pages shared by several regions
+    fn find(&self, addr: u16) -> Option<usize> {
+        let regions = &self.regions;
+        (regions.iter().position(|r| r.covers(addr as usize)))
+            .or_else(|| regions.iter().position(|r| r.decodes(addr)))
+    }
+}
+
+pub struct MemoryMapBuilder {
+    regions: Vec<Region>,
+    open_bus: u8,
+}
+
+impl MemoryMapBuilder {
+    pub fn region(mut self, region: Region) -> Self {
+        self.regions.push(region);
+        self
+    }
+
+    /// Value read from unmapped addresses, 0xff by default
+    pub fn open_bus(mut self, value: u8) -> Self {
+        self.open_bus = value;
+        self
+    }
+
+    /// Fails when regions overlap or do not fit in their mirror mask
+    pub fn build(self) -> io::Result<MemoryMap> {
+        let mut regions = Vec::with_capacity(self.regions.len());
+        for region in self.regions {
+            check(&regions, &region)?;
+            regions.push(region);
+        }
+        let pages = page_table(&regions)?;
+        Ok(MemoryMap {
+            regions,
+            pages,
+            open_bus: self.open_bus,
+        })
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Validate `region` against the ones already in the map
+fn check(regions: &[Region], region: &Region) -> io::Result<()> {
+    let (base, end) = (region.base as usize, region.end());
+    let outside = |addr: usize| addr & !(region.mask as usize) != 0;
+    if region.size == 0 || end > 0x10000 || outside(base) || outside(end - 1) {
+        return Err(invalid(format!(
+            "region {:04x}+{:x} does not fit mirror mask {:04x}",
+            base, region.size, region.mask
+        )));
+    }
+    if regions.len() >= u8::MAX as usize {
+        return Err(invalid("too many regions".to_string()));
+    }
+    match regions
+        .iter()
+        .find(|r| base < r.end() && (r.base as usize) < end)
+    {
+        Some(r) => Err(invalid(format!(
+            "region {:04x}-{:04x} overlaps {:04x}-{:04x}",
+            base,
+            end - 1,
+            r.base,
+            r.end() - 1
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn page_table(regions: &[Region]) -> io::Result<[Page; PAGES]> {
+    let mut pages = [Page::Unmapped; PAGES];
+    for (page, entry) in pages.iter_mut().enumerate() {
+        let first = page * PAGE_SIZE;
+        let addrs = first..first + PAGE_SIZE;
+        let owner = |addr: usize| {
+            let owned = regions.iter().position(|r| r.covers(addr));
+            if owned.is_some() {
+                return Ok(owned);
+            }
+            let mut images = regions
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| r.decodes(addr as u16));
+            match (images.next(), images.next()) {
+                (Some((i, _)), None) => Ok(Some(i)),
+                (None, _) => Ok(None),
+                (Some(_), Some(_)) => {
+                    Err(invalid(format!("mirror images collide at {:04x}", addr)))
+                }
+            }
+        };
+        let head = owner(first)?;
+        let mut split = false;
+        for addr in addrs {
+            if owner(addr)? != head {
+                split = true;
+            }
+        }
+        *entry = match (split, head) {
+            (true, _) => Page::Split,
+            (false, Some(i)) => Page::Region(i as u8),
+            (false, None) => Page::Unmapped,
+        };
+    }
+    Ok(pages)
+}
@@ -0,0 +1,37 @@
+use std::any::Any;
+
+use crate::memory::{ReadFn, WriteFn};
+
+/// Hardware answering a range of memory addresses. The device keeps its own state, so the
+/// map can own it outright; get it back with [MemoryMap::device](crate::memory::MemoryMap::device).
+/// `offset` is relative to the start of the region, after mirroring.
+pub trait MmioDevice: Any {
+    fn read(&mut self, offset: u16) -> u8;
+
+    fn write(&mut self, offset: u16, val: u8);
+}
+
+/// A pair of closures as a device, for hardware without state worth looking at
+pub struct Callbacks {
+    pub read: ReadFn,
+    pub write: WriteFn,
+}
+
+impl MmioDevice for Callbacks {
+    fn read(&mut self, offset: u16) -> u8 {
+        (self.read)(offset)
+    }
+
+    fn write(&mut self, offset: u16, val: u8) {
+        (self.write)(offset, val)
+    }
+}
+
+/// Downcast a boxed device
+pub(crate) fn downcast_mut<T: MmioDevice>(device: &mut dyn MmioDevice) -> Option<&mut T> {
+    (device as &mut dyn Any).downcast_mut::<T>()
+}
+
+pub(crate) fn downcast_ref<T: MmioDevice>(device: &dyn MmioDevice) -> Option<&T> {
+    (device as &dyn Any).downcast_ref::<T>()
+}
@@ -1,13 +1,12 @@
 pub mod address;
-mod memory;
-mod readonly;
+mod flat;
+pub mod loader;
+mod map;
+mod mmio;
 mod testadd;
-mod video;
-mod work;
 
 pub use address::AddressBus;
-pub use memory::Memory;
-pub use readonly::ReadOnly;
+pub use flat::{FlatRam, SharedRam};
+pub use map::{Access, MemoryMap, MemoryMapBuilder, ReadFn, Region, WriteFn};
+pub use mmio::{Callbacks, MmioDevice};
 pub use testadd::TestAddressing;
-pub use video::Video;
-pub use work::Work;
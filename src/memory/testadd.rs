@@ -1,6 +1,7 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::memory::flat::fill;
 use crate::memory::AddressBus;
 
 pub struct TestAddressing {
@@ -15,6 +16,10 @@ impl AddressBus for TestAddressing {
     fn set_mem(&mut self, addr: u16, val: u8) {
         self.rom.borrow_mut()[addr as usize] = val
     }
+
+    fn fill_ram(&mut self, pattern: &[u8]) {
+        fill(&mut self.rom.borrow_mut(), pattern);
+    }
 }
 
 impl TestAddressing {
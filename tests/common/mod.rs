@@ -0,0 +1,59 @@
+//! Fixtures shared by the integration tests. Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use space_invaders_8080::cpu::IO;
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::{Cpu, Register};
+
+/// Nothing on the ports: IN leaves A alone, OUT is ignored
+pub struct NoIO;
+
+impl IO for NoIO {
+    fn input(&mut self, _cpu: &mut Register, _byte: u8) {}
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+/// IN always reads the byte it holds, OUT is ignored
+pub struct ConstIO(pub u8);
+
+impl IO for ConstIO {
+    fn input(&mut self, cpu: &mut Register, _byte: u8) {
+        cpu.a = self.0;
+    }
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+/// 64K of RAM holding each `(address, bytes)` chunk
+pub fn ram(chunks: &[(u16, &[u8])]) -> FlatRam {
+    let mut memory = FlatRam::new();
+    for (addr, bytes) in chunks {
+        for (i, byte) in bytes.iter().enumerate() {
+            memory.set_mem(addr.wrapping_add(i as u16), *byte);
+        }
+    }
+    memory
+}
+
+/// An 8080 starting at 0000 in [ram] of `chunks`
+pub fn machine(chunks: &[(u16, &[u8])]) -> Cpu<FlatRam, NoIO> {
+    Cpu::new(ram(chunks), 0)
+}
+
+/// `len` random bytes from `seed` (xorshift), without HLT so that programs keep running
+pub fn random_program(seed: u64, len: usize) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 32) as u8
+    };
+    (0..len)
+        .map(|_| match next() {
+            0x76 => 0x00,
+            byte => byte,
+        })
+        .collect()
+}
@@ -0,0 +1,36 @@
+use space_invaders_8080::machine::{Altair, BufferTerminal};
+
+/// Echo the terminal until CR, then print '!' and spin
+const ECHO_HEX: &str = ":100000003E03D310DB10E601CA0400DB11FE0DCA6B
+:100010001A00D311C304000000003E21D311C31EF7
+:0100200000DF
+:00000001FF
+";
+
+#[test]
+fn test_sio_echo() {
+    let mut altair = Altair::new(BufferTerminal::new(b"hello\r"));
+    altair.load_hex(ECHO_HEX).unwrap();
+    altair.run(10_000);
+    assert_eq!(altair.sio().terminal().output, b"hello!");
+    assert!(altair.sio().terminal().input.is_empty());
+}
+
+#[test]
+fn test_bad_hex() {
+    let mut altair = Altair::new(BufferTerminal::new(b""));
+    // Wrong checksum
+    assert!(altair.load_hex(":0100200000DE\n").is_err());
+    assert!(altair.load_hex("0100200000DF\n").is_err());
+}
+
+#[test]
+fn test_binary_and_sense_switches() {
+    let mut altair = Altair::new(BufferTerminal::new(b""));
+    // IN 0FFH; OUT 11H; HLT-free spin
+    altair.load_binary(&[0xdb, 0xff, 0xd3, 0x11, 0xc3, 0x04, 0x01], 0x0100);
+    altair.set_sense_switches(0x42);
+    altair.cpu.register.pc = 0x0100;
+    altair.run(100);
+    assert_eq!(altair.sio().terminal().output, &[0x42]);
+}
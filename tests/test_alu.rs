@@ -0,0 +1,325 @@
+//! Every operand and carry combination of the ALU instructions, against a reference
+//! model written from the flag rules of the 8080 Assembly Language Programming Manual.
+
+mod common;
+
+use space_invaders_8080::cpu::Variant;
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::{Cpu, Register};
+
+/// The reference: plain binary arithmetic, each flag from its definition
+mod reference {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Flags {
+        pub s: bool,
+        pub z: bool,
+        pub ac: bool,
+        pub p: bool,
+        pub cy: bool,
+    }
+
+    /// S, Z and P of a result, with AC and CY given
+    pub fn flags(result: u8, ac: bool, cy: bool) -> Flags {
+        Flags {
+            s: result >= 0x80,
+            z: result == 0,
+            ac,
+            p: result.count_ones().is_multiple_of(2),
+            cy,
+        }
+    }
+
+    /// ADD and ADC: AC is the carry out of bit 3, CY the carry out of bit 7
+    pub fn add(a: u8, b: u8, carry: bool) -> (u8, Flags) {
+        let sum = a as u16 + b as u16 + carry as u16;
+        let ac = (a & 0x0f) + (b & 0x0f) + carry as u8 > 0x0f;
+        (sum as u8, flags(sum as u8, ac, sum > 0xff))
+    }
+
+    /// SUB, SBB and CMP add the two's complement: A + !B + !borrow. AC is the carry out
+    /// of bit 3 of that addition, CY the inverted carry out of bit 7, i.e. a borrow.
+    pub fn sub(a: u8, b: u8, borrow: bool) -> (u8, Flags) {
+        let sum = a as u16 + !b as u16 + !borrow as u16;
+        let ac = (a & 0x0f) + (!b & 0x0f) + !borrow as u8 > 0x0f;
+        (sum as u8, flags(sum as u8, ac, sum <= 0xff))
+    }
+
+    /// ANA: CY cleared, AC is the OR of bit 3 of the operands
+    pub fn ana(a: u8, b: u8) -> (u8, Flags) {
+        let ac = (a | b) & 0x08 != 0;
+        (a & b, flags(a & b, ac, false))
+    }
+
+    /// XRA and ORA clear AC and CY
+    pub fn logic(result: u8) -> (u8, Flags) {
+        (result, flags(result, false, false))
+    }
+
+    /// INR: AC is the carry out of bit 3, CY is kept
+    pub fn inr(r: u8, cy: bool) -> (u8, Flags) {
+        let result = r.wrapping_add(1);
+        (result, flags(result, r & 0x0f == 0x0f, cy))
+    }
+
+    /// DCR adds 0xff: AC is the carry out of bit 3 of that addition, CY is kept
+    pub fn dcr(r: u8, cy: bool) -> (u8, Flags) {
+        let result = r.wrapping_sub(1);
+        (result, flags(result, r & 0x0f != 0, cy))
+    }
+
+    /// DAA: add 6 to the low digit if it is over 9 or AC is set, then 6 to the high digit
+    /// if it is over 9 or CY is set. CY is only ever set.
+    pub fn daa(a: u8, ac: bool, cy: bool) -> (u8, Flags) {
+        let mut correction = 0;
+        if a & 0x0f > 9 || ac {
+            correction |= 0x06;
+        }
+        let high = (a as u16 + correction as u16) >> 4;
+        let mut carry = cy;
+        if high > 9 || cy {
+            correction |= 0x60;
+            carry = true;
+        }
+        let (result, flags) = add(a, correction, false);
+        (result, Flags { cy: carry, ..flags })
+    }
+
+    /// DAD: only CY, the carry out of bit 15
+    pub fn dad(hl: u16, rp: u16) -> (u16, bool) {
+        let sum = hl as u32 + rp as u32;
+        (sum as u16, sum > 0xffff)
+    }
+}
+
+use common::NoIO;
+use reference::Flags;
+
+fn cpu() -> Cpu<FlatRam, NoIO> {
+    Cpu::new(FlatRam::new(), 0)
+}
+
+/// Run the one byte instruction `op` with A, B, CY and AC given
+fn run(cpu: &mut Cpu<FlatRam, NoIO>, op: u8, a: u8, b: u8, cy: bool, ac: bool) -> Flags {
+    cpu.addring.set_mem(0, op);
+    cpu.register.pc = 0;
+    cpu.register.a = a;
+    cpu.register.b = b;
+    cpu.register.flag_cy = cy;
+    cpu.register.flag_ac = ac;
+    // S, Z and P start wrong, so that they must be computed
+    cpu.register.flag_s = a < 0x80;
+    cpu.register.flag_z = a != 0;
+    cpu.register.flag_p = !a.count_ones().is_multiple_of(2);
+    cpu.step(&mut NoIO);
+    flags(&cpu.register)
+}
+
+fn flags(r: &Register) -> Flags {
+    Flags {
+        s: r.flag_s,
+        z: r.flag_z,
+        ac: r.flag_ac,
+        p: r.flag_p,
+        cy: r.flag_cy,
+    }
+}
+
+/// `op` with B as operand, over all A, B and carry in
+fn exhaustive(op: u8, name: &str, model: impl Fn(u8, u8, bool) -> (u8, Flags)) {
+    let mut cpu = cpu();
+    for a in 0..=255 {
+        for b in 0..=255 {
+            for cy in [false, true] {
+                let flags = run(&mut cpu, op, a, b, cy, !cy);
+                let (result, expected) = model(a, b, cy);
+                let actual = (cpu.register.a, flags);
+                assert_eq!(
+                    actual,
+                    (result, expected),
+                    "{} {:02x},{:02x} cy={}",
+                    name,
+                    a,
+                    b,
+                    cy
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_add() {
+    exhaustive(0x80, "ADD", |a, b, _| reference::add(a, b, false));
+}
+
+#[test]
+fn test_adc() {
+    exhaustive(0x88, "ADC", reference::add);
+}
+
+#[test]
+fn test_sub() {
+    exhaustive(0x90, "SUB", |a, b, _| reference::sub(a, b, false));
+}
+
+#[test]
+fn test_sbb() {
+    exhaustive(0x98, "SBB", reference::sub);
+}
+
+#[test]
+fn test_ana() {
+    exhaustive(0xa0, "ANA", |a, b, _| reference::ana(a, b));
+}
+
+#[test]
+fn test_xra() {
+    exhaustive(0xa8, "XRA", |a, b, _| reference::logic(a ^ b));
+}
+
+#[test]
+fn test_ora() {
+    exhaustive(0xb0, "ORA", |a, b, _| reference::logic(a | b));
+}
+
+#[test]
+fn test_cmp() {
+    // A is kept
+    exhaustive(0xb8, "CMP", |a, b, _| (a, reference::sub(a, b, false).1));
+}
+
+#[test]
+fn test_immediate_forms() {
+    // ADI..CPI take the same path as the register forms
+    let mut cpu = cpu();
+    for (op, alu) in [(0xc6, 0x80), (0xce, 0x88), (0xd6, 0x90), (0xde, 0x98)] {
+        for (op, alu) in [(op, alu), (op + 0x20, alu + 0x20)] {
+            for a in 0..=255 {
+                for data in [0x00, 0x01, 0x0f, 0x10, 0x7f, 0x80, 0xf0, 0xff] {
+                    cpu.addring.set_mem(1, data);
+                    let immediate = (run(&mut cpu, op, a, 0, true, false), cpu.register.a);
+                    let register = (run(&mut cpu, alu, a, data, true, false), cpu.register.a);
+                    assert_eq!(immediate, register, "{:02x} {:02x},{:02x}", op, a, data);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_inr_dcr() {
+    let mut cpu = cpu();
+    for r in 0..=255 {
+        for cy in [false, true] {
+            let flags = run(&mut cpu, 0x04, 0, r, cy, !cy);
+            assert_eq!(
+                (cpu.register.b, flags),
+                reference::inr(r, cy),
+                "INR {:02x}",
+                r
+            );
+            let flags = run(&mut cpu, 0x05, 0, r, cy, !cy);
+            assert_eq!(
+                (cpu.register.b, flags),
+                reference::dcr(r, cy),
+                "DCR {:02x}",
+                r
+            );
+        }
+    }
+}
+
+#[test]
+fn test_inr_dcr_memory() {
+    let mut cpu = cpu();
+    cpu.register.set_hl(0x4000);
+    for r in 0..=255 {
+        cpu.addring.set_mem(0x4000, r);
+        let flags = run(&mut cpu, 0x34, 0, 0, true, false);
+        let expected = reference::inr(r, true);
+        assert_eq!(
+            (cpu.addring.get_mem(0x4000), flags),
+            expected,
+            "INR M {:02x}",
+            r
+        );
+        cpu.addring.set_mem(0x4000, r);
+        let flags = run(&mut cpu, 0x35, 0, 0, false, true);
+        let expected = reference::dcr(r, false);
+        assert_eq!(
+            (cpu.addring.get_mem(0x4000), flags),
+            expected,
+            "DCR M {:02x}",
+            r
+        );
+    }
+}
+
+#[test]
+fn test_daa() {
+    let mut cpu = cpu();
+    for a in 0..=255 {
+        for (ac, cy) in [(false, false), (false, true), (true, false), (true, true)] {
+            let flags = run(&mut cpu, 0x27, a, 0, cy, ac);
+            let expected = reference::daa(a, ac, cy);
+            assert_eq!(
+                (cpu.register.a, flags),
+                expected,
+                "DAA {:02x} ac={} cy={}",
+                a,
+                ac,
+                cy
+            );
+        }
+    }
+}
+
+#[test]
+fn test_daa_after_add() {
+    // Every pair of BCD numbers adds up to the right BCD sum
+    let mut cpu = cpu();
+    let bcd = |n: u8| (n / 10) << 4 | (n % 10);
+    for x in 0..100 {
+        for y in 0..100 {
+            run(&mut cpu, 0x80, bcd(x), bcd(y), false, false);
+            let (a, cy, ac) = (cpu.register.a, cpu.register.flag_cy, cpu.register.flag_ac);
+            run(&mut cpu, 0x27, a, 0, cy, ac);
+            assert_eq!(cpu.register.a, bcd((x + y) % 100), "{} + {}", x, y);
+            assert_eq!(cpu.register.flag_cy, x + y >= 100, "{} + {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_dad() {
+    let mut cpu = cpu();
+    cpu.addring.set_mem(0, 0x09);
+    for hl in (0..=0xffffu32).step_by(0x0101) {
+        for bc in (0..=0xffffu32).step_by(0x00ff).chain([0xffff]) {
+            let (hl, bc) = (hl as u16, bc as u16);
+            cpu.register.pc = 0;
+            cpu.register.set_hl(hl);
+            cpu.register.set_bc(bc);
+            cpu.register.set_flags(0xd4);
+            cpu.step(&mut NoIO);
+            let (sum, carry) = reference::dad(hl, bc);
+            assert_eq!(cpu.register.get_hl(), sum, "DAD {:04x},{:04x}", hl, bc);
+            // Only CY changes
+            let expected = 0xd4 | u8::from(carry);
+            assert_eq!(cpu.register.get_flags(), expected | 0x02);
+        }
+    }
+}
+
+#[test]
+fn test_ana_8085() {
+    // The 8085 always sets AC on ANA and ANI
+    let mut cpu: Cpu<FlatRam, NoIO> = Cpu::with_variant(FlatRam::new(), 0, Variant::I8085);
+    for (a, b) in [(0x00, 0x00), (0x0f, 0xf0), (0xff, 0xff)] {
+        let flags = run(&mut cpu, 0xa0, a, b, true, false);
+        assert_eq!(
+            (cpu.register.a, flags),
+            (a & b, reference::flags(a & b, true, false))
+        );
+    }
+}
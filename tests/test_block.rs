@@ -0,0 +1,182 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::cpu::{BlockCpu, IoBus};
+use space_invaders_8080::device::Watchdog;
+use space_invaders_8080::game::invaders::driver::{Input, INVADERS};
+use space_invaders_8080::game::invaders::siaddressing::ROM_SIZE;
+use space_invaders_8080::game::invaders::{
+    io_bus, load_roms, InputPanel, InvadersAddressBus, HALF_FRAME_CYCLES,
+};
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::Cpu;
+
+/// The interpreter and the block engine side by side, each with its own IO
+struct Lockstep<B: AddressBus> {
+    cpu: Cpu<B, IoBus>,
+    io: IoBus,
+    blocks: BlockCpu<B, IoBus>,
+    block_io: IoBus,
+}
+
+impl<B: AddressBus> Lockstep<B> {
+    fn new(cpu: Cpu<B, IoBus>, blocks: Cpu<B, IoBus>, io: fn() -> IoBus) -> Self {
+        Self {
+            cpu,
+            io: io(),
+            blocks: BlockCpu::new(blocks),
+            block_io: io(),
+        }
+    }
+
+    /// One block of the engine against as many interpreter steps, returns the cycles
+    fn step(&mut self) -> u32 {
+        let before = self.blocks.steps();
+        let cycles = self.blocks.step(&mut self.block_io);
+        let mut expected = 0;
+        for _ in before..self.blocks.steps() {
+            expected += u32::from(self.cpu.step(&mut self.io));
+        }
+        assert_eq!(cycles, expected, "cycles of the block at {:04x}", before);
+        self.check();
+        cycles
+    }
+
+    fn check(&self) {
+        let (a, b) = (&self.cpu.register, &self.blocks.cpu.register);
+        let state = |r: &space_invaders_8080::Register| {
+            (r.a, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, r.pc, r.get_flags())
+        };
+        assert_eq!(state(a), state(b), "after {} steps", self.blocks.steps());
+        assert_eq!(
+            self.cpu.interrupts_enabled(),
+            self.blocks.cpu.interrupts_enabled()
+        );
+        assert_eq!(self.cpu.is_halted(), self.blocks.cpu.is_halted());
+    }
+
+    fn interrupt(&mut self, code: u8) {
+        assert_eq!(self.cpu.interrupt(code), self.blocks.cpu.interrupt(code));
+    }
+}
+
+fn invaders_io() -> IoBus {
+    let mut io = io_bus(&INVADERS.ports);
+    io.device_mut::<Watchdog>().unwrap().set_enabled(false);
+    io
+}
+
+#[test]
+fn test_invaders_lockstep() {
+    let (video, block_video) = (
+        Rc::new(RefCell::new(vec![0u8; 7168])),
+        Rc::new(RefCell::new(vec![0u8; 7168])),
+    );
+    let (bus, _) = load_roms(&INVADERS, video.clone()).unwrap();
+    let (block_bus, _) = load_roms(&INVADERS, block_video.clone()).unwrap();
+    let mut machines = Lockstep::new(Cpu::new(bus, 0), Cpu::new(block_bus, 0), invaders_io);
+
+    // Attract mode, then a coin, a one player game and some shooting
+    for frame in 0..1500 {
+        let inputs = match frame {
+            600..=610 => vec![Input::Coin],
+            700..=710 => vec![Input::Start1],
+            800.. if frame % 60 < 30 => vec![Input::Fire1, Input::Left1],
+            800.. => vec![Input::Right1],
+            _ => Vec::new(),
+        };
+        for io in [&mut machines.io, &mut machines.block_io] {
+            io.device_mut::<InputPanel>()
+                .unwrap()
+                .set_inputs(inputs.clone());
+        }
+        for rst in [0xcf, 0xd7] {
+            let mut half = 0;
+            while half <= HALF_FRAME_CYCLES {
+                half += machines.step();
+            }
+            machines.interrupt(rst);
+        }
+        for addr in 0x2000..0x4000 {
+            assert_eq!(
+                machines.cpu.addring.get_mem(addr),
+                machines.blocks.cpu.addring.get_mem(addr),
+                "RAM at {:04x}, frame {}",
+                addr,
+                frame
+            );
+        }
+    }
+    assert_eq!(*video.borrow(), *block_video.borrow());
+}
+
+#[test]
+fn test_run_stops_like_the_interpreter() {
+    let (video, block_video) = (
+        Rc::new(RefCell::new(vec![0u8; 7168])),
+        Rc::new(RefCell::new(vec![0u8; 7168])),
+    );
+    let (bus, _) = load_roms(&INVADERS, video).unwrap();
+    let (block_bus, _) = load_roms(&INVADERS, block_video).unwrap();
+    let mut machines = Lockstep::new(Cpu::new(bus, 0), Cpu::new(block_bus, 0), invaders_io);
+    for _ in 0..200 {
+        let done = machines
+            .blocks
+            .run(&mut machines.block_io, HALF_FRAME_CYCLES + 1);
+        let mut half = 0;
+        while half <= HALF_FRAME_CYCLES {
+            half += u32::from(machines.cpu.step(&mut machines.io));
+        }
+        assert_eq!(done, half);
+        machines.check();
+    }
+}
+
+fn no_io() -> IoBus {
+    IoBus::new()
+}
+
+fn flat(program: &[u8]) -> Cpu<FlatRam, IoBus> {
+    let mut memory = FlatRam::new();
+    for (i, byte) in program.iter().enumerate() {
+        memory.set_mem(i as u16, *byte);
+    }
+    Cpu::new(memory, 0)
+}
+
+#[test]
+fn test_self_modifying_code() {
+    // MVI A,5; STA 0007h; NOP; MVI B,0 (patched to MVI B,5); HLT
+    let program = [0x3e, 0x05, 0x32, 0x07, 0x00, 0x00, 0x06, 0x00, 0x76];
+    let mut machines = Lockstep::new(flat(&program), flat(&program), no_io);
+    // Decode the whole block once, patch, then run it again
+    machines.step();
+    while !machines.blocks.cpu.is_halted() {
+        machines.step();
+    }
+    assert_eq!(machines.blocks.cpu.register.b, 5);
+}
+
+#[test]
+fn test_write_through_mirror() {
+    // 0000: JMP 6000h, into RAM seen through its mirror
+    let mut rom = Box::new([0u8; ROM_SIZE]);
+    rom[..3].copy_from_slice(&[0xc3, 0x00, 0x60]);
+    // 2000: MVI B,1; STA 2001h; JMP 6000h, the STA patches the MVI through 2000h
+    let code = [0x06, 0x01, 0x32, 0x01, 0x20, 0xc3, 0x00, 0x60];
+    let bus = || {
+        let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+        let mut bus = InvadersAddressBus::new(vec![(0x0000, rom.clone())], video).unwrap();
+        for (i, byte) in code.iter().enumerate() {
+            bus.set_mem(0x2000 + i as u16, *byte);
+        }
+        let mut cpu = Cpu::new(bus, 0);
+        cpu.register.a = 7;
+        cpu
+    };
+    let mut machines = Lockstep::new(bus(), bus(), no_io);
+    for _ in 0..4 {
+        machines.step();
+    }
+    assert_eq!(machines.blocks.cpu.register.b, 7);
+}
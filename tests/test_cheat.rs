@@ -0,0 +1,93 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::game::{
+    Cheat, CheatCode, CheatEngine, CheatKind, RamSearch, SearchCondition,
+};
+use space_invaders_8080::TestAddressing;
+
+#[test]
+fn test_ram_search() {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    let bus = TestAddressing::new(mem.clone());
+    mem.borrow_mut()[0x21ff] = 3;
+    let mut search = RamSearch::new(&bus, 0x2000, 0x23ff);
+    assert_eq!(search.candidates().len(), 0x400);
+
+    // Lose a life, something else changes too
+    mem.borrow_mut()[0x21ff] = 2;
+    mem.borrow_mut()[0x2010] = 7;
+    search.filter(&bus, SearchCondition::Decreased);
+    assert_eq!(search.candidates(), &[0x21ff]);
+
+    search.filter(&bus, SearchCondition::Unchanged);
+    search.filter(&bus, SearchCondition::Equal(2));
+    assert_eq!(search.candidates(), &[0x21ff]);
+    search.filter(&bus, SearchCondition::Changed);
+    assert!(search.candidates().is_empty());
+}
+
+#[test]
+fn test_freeze_and_patch() {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    let mut bus = TestAddressing::new(mem.clone());
+    let mut engine =
+        CheatEngine::parse("[Infinite lives]\nfreeze 21ff 03\n\n[Credits] off\npatch 20eb 99\n")
+            .unwrap();
+    assert!(engine.is_active());
+
+    engine.apply(&mut bus);
+    assert_eq!(mem.borrow()[0x21ff], 0x03);
+    assert_eq!(mem.borrow()[0x20eb], 0x00);
+
+    assert!(engine.set_enabled("Credits", true));
+    mem.borrow_mut()[0x21ff] = 0x01;
+    engine.apply(&mut bus);
+    assert_eq!(mem.borrow()[0x21ff], 0x03);
+    assert_eq!(mem.borrow()[0x20eb], 0x99);
+
+    // A patch is only written once
+    mem.borrow_mut()[0x20eb] = 0x98;
+    engine.apply(&mut bus);
+    assert_eq!(mem.borrow()[0x20eb], 0x98);
+}
+
+#[test]
+fn test_disabled_does_not_touch_memory() {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    let mut bus = TestAddressing::new(mem.clone());
+    let mut engine = CheatEngine::parse("[Lives] off\nfreeze 21ff 03\n").unwrap();
+    assert!(!engine.is_active());
+    engine.apply(&mut bus);
+    assert!(mem.borrow().iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_text_round_trip() {
+    let mut engine = CheatEngine::new();
+    engine.add(Cheat::new(
+        "Infinite lives",
+        vec![CheatCode {
+            kind: CheatKind::Freeze,
+            addr: 0x21ff,
+            value: 3,
+        }],
+    ));
+    engine.add(Cheat::new(
+        "Credits",
+        vec![CheatCode {
+            kind: CheatKind::Patch,
+            addr: 0x20eb,
+            value: 0x99,
+        }],
+    ));
+    engine.set_enabled("Credits", false);
+    let text = engine.to_text();
+    assert_eq!(
+        text,
+        "[Infinite lives]\nfreeze 21ff 03\n\n[Credits] off\npatch 20eb 99\n"
+    );
+    assert_eq!(CheatEngine::parse(&text).unwrap().cheats(), engine.cheats());
+    assert!(CheatEngine::parse("freeze 21ff 03\n").is_err());
+    assert!(CheatEngine::parse("[Lives]\npoke 21ff 03\n").is_err());
+}
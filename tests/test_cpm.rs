@@ -0,0 +1,90 @@
+use space_invaders_8080::machine::cpm::{DiskImage, Geometry, BIOS_BASE_64K, SYSTEM_SIZE};
+use space_invaders_8080::machine::{BufferTerminal, Cpm};
+use space_invaders_8080::memory::AddressBus;
+
+fn new_cpm(input: &[u8]) -> Cpm<BufferTerminal> {
+    Cpm::new(BufferTerminal::new(input), BIOS_BASE_64K)
+}
+
+#[test]
+fn test_com_console() {
+    let mut cpm = new_cpm(b"x");
+    // C=1: read a key; C=9: print "HI$"; RET
+    cpm.load_com(&[
+        0x0e, 0x01, 0xcd, 0x05, 0x00, // MVI C,1; CALL 5
+        0x0e, 0x09, 0x11, 0x0f, 0x01, 0xcd, 0x05, 0x00, // MVI C,9; LXI D,010F; CALL 5
+        0xc9, 0x00, b'H', b'I', b'$',
+    ]);
+    cpm.run(100_000);
+    assert!(cpm.is_exited());
+    assert_eq!(cpm.bios().terminal().output, b"xHI");
+}
+
+#[test]
+fn test_bios_sector_io() {
+    let mut cpm = new_cpm(b"");
+    cpm.mount(0, DiskImage::blank(Geometry::IBM_3740));
+    let bios = |function: u16| (BIOS_BASE_64K + function * 3).to_le_bytes();
+    let mut program = vec![0x0e, 0x00, 0xcd];
+    program.extend(bios(9)); // SELDSK 0
+    program.extend([0x01, 0x02, 0x00, 0xcd]);
+    program.extend(bios(10)); // SETTRK 2
+    program.extend([0x01, 0x05, 0x00, 0xcd]);
+    program.extend(bios(11)); // SETSEC 5
+    program.extend([0x01, 0x00, 0x02, 0xcd]);
+    program.extend(bios(12)); // SETDMA 0200
+    program.push(0xcd);
+    program.extend(bios(14)); // WRITE
+    program.extend([0x32, 0x00, 0x03]); // STA 0300
+    program.extend([0x01, 0x00, 0x04, 0xcd]);
+    program.extend(bios(12)); // SETDMA 0400
+    program.push(0xcd);
+    program.extend(bios(13)); // READ
+    program.extend([0x32, 0x01, 0x03, 0xc9]); // STA 0301; RET
+    cpm.load_com(&program);
+    for i in 0..128u16 {
+        cpm.cpu.addring.set_mem(0x0200 + i, i as u8);
+        cpm.cpu.addring.set_mem(0x0300 + i, 0xff);
+    }
+    cpm.run(100_000);
+    assert!(cpm.is_exited());
+    assert_eq!(cpm.cpu.addring.get_mem(0x0300), 0);
+    assert_eq!(cpm.cpu.addring.get_mem(0x0301), 0);
+    for i in 0..128u16 {
+        assert_eq!(cpm.cpu.addring.get_mem(0x0400 + i), i as u8);
+    }
+    let bios = cpm.bios();
+    let data = bios.disks[0].as_ref().unwrap().data();
+    let ofs = (2 * 26 + 4) * 128;
+    assert_eq!(data[ofs + 127], 127);
+}
+
+#[test]
+fn test_boot_from_system_tracks() {
+    let mut image = vec![0xe5u8; 2 * 26 * 128];
+    // "CCP": print 'A' through BIOS CONOUT, then warm boot
+    let conout = (BIOS_BASE_64K + 4 * 3).to_le_bytes();
+    let ccp = [0x0e, b'A', 0xcd, conout[0], conout[1], 0xc3, 0x00, 0x00];
+    image[128..128 + ccp.len()].copy_from_slice(&ccp);
+    let mut cpm = new_cpm(b"");
+    cpm.mount(
+        0,
+        DiskImage::from_bytes(Geometry::IBM_3740, &image).unwrap(),
+    );
+    cpm.boot().unwrap();
+    assert_eq!(cpm.cpu.register.pc, BIOS_BASE_64K - SYSTEM_SIZE);
+    // BDOS entry in page zero
+    assert_eq!(
+        cpm.cpu.addring.get_word(0x0006),
+        BIOS_BASE_64K - SYSTEM_SIZE + 0x0806
+    );
+    cpm.run(1_000);
+    assert!(!cpm.is_exited());
+    assert!(cpm.bios().terminal().output.starts_with(b"AAA"));
+}
+
+#[test]
+fn test_boot_without_disk() {
+    let mut cpm = new_cpm(b"");
+    assert!(cpm.boot().is_err());
+}
@@ -0,0 +1,169 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::game::invaders::color::{rbg_3bit, ColorSource, PROM_BANK_SIZE};
+use space_invaders_8080::game::invaders::driver::{ColorMap, Input};
+use space_invaders_8080::game::invaders::siaddressing::ROM_SIZE;
+use space_invaders_8080::game::invaders::InvadersAddressBus;
+use space_invaders_8080::game::{find_driver, DRIVERS};
+use space_invaders_8080::memory::AddressBus;
+
+#[test]
+fn test_find_driver() {
+    assert_eq!(DRIVERS[0].name, "invaders");
+    assert_eq!(
+        find_driver("invadpt2").unwrap().description,
+        "Space Invaders Part II"
+    );
+    assert_eq!(find_driver("lrescue").unwrap().description, "Lunar Rescue");
+    assert!(find_driver("pacman").is_none());
+}
+
+#[test]
+fn test_input_maps() {
+    for driver in DRIVERS {
+        let inputs = driver.ports.inputs;
+        for (i, bit) in inputs.iter().enumerate() {
+            // One control per bit, and never on a port the board does not read
+            assert!((0..=2).contains(&bit.port), "{}", driver.name);
+            assert!(
+                inputs[i + 1..]
+                    .iter()
+                    .all(|other| other.port != bit.port || other.mask & bit.mask == 0),
+                "{}: {:?} shares its bit",
+                driver.name,
+                bit.input
+            );
+        }
+    }
+    // The Taito boards have a DIP switch where Space Invaders has its tilt switch
+    for name in ["lrescue", "ballbomb"] {
+        let inputs = find_driver(name).unwrap().ports.inputs;
+        assert!(
+            inputs.iter().all(|bit| bit.input != Input::Tilt),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_rom_maps() {
+    for driver in DRIVERS {
+        for rom in driver.roms {
+            // Aligned, and never straddling two sockets
+            assert_eq!(rom.addr as usize % rom.size, 0, "{}", rom.file);
+            assert!(
+                rom.addr as usize % ROM_SIZE + rom.size <= ROM_SIZE,
+                "{}",
+                rom.file
+            );
+            assert!(!(0x2000..0x4000).contains(&rom.addr), "{}", rom.file);
+        }
+    }
+}
+
+#[test]
+fn test_bus_layout() {
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let roms = vec![
+        (0x0000, Box::new([0x11u8; ROM_SIZE])),
+        (0x4000, Box::new([0x22u8; ROM_SIZE])),
+    ];
+    let mut bus = InvadersAddressBus::new(roms, video.clone()).unwrap();
+    assert_eq!(bus.get_mem(0x0000), 0x11);
+    assert_eq!(bus.get_mem(0x4000), 0x22);
+    // Empty socket
+    assert_eq!(bus.get_mem(0x0800), 0x00);
+    // ROM ignores writes
+    bus.set_mem(0x0001, 0xff);
+    assert_eq!(bus.get_mem(0x0001), 0x11);
+    // RAM mirrors
+    bus.set_mem(0x20f4, 0x42);
+    assert_eq!(bus.get_mem(0x60f4), 0x42);
+    assert_eq!(bus.get_mem(0xa0f4), 0x42);
+    bus.set_mem(0x2400, 0x0f);
+    assert_eq!(video.borrow()[0], 0x0f);
+}
+
+#[test]
+fn test_color_prom() {
+    let mut data = vec![0u8; 0x400];
+    // Cell of the first video byte: line 32, column 0
+    data[0x80] = 0x04;
+    data[0x81] = 0x01;
+    let color = ColorSource::Prom {
+        data,
+        background: 2,
+        bank: 0,
+    };
+    assert_eq!(color.color(0), rbg_3bit(0x04));
+    assert_eq!(color.color(1), rbg_3bit(0x01));
+    // Same cell 7 lines later
+    assert_eq!(color.color(7 * 32), rbg_3bit(0x04));
+    assert_eq!(color.background(), 0x0000_00ff);
+    assert_eq!(rbg_3bit(0x07), 0x00ff_ffff);
+}
+
+#[test]
+fn test_color_bank() {
+    // Part II carries a colour map per player, port 5 bit 5 picks one
+    let driver = find_driver("invadpt2").unwrap();
+    let ColorMap::Prom {
+        files, bank_select, ..
+    } = driver.color
+    else {
+        panic!("invadpt2 has no colour PROM");
+    };
+    assert_eq!(files, ["pv06.1", "pv07.2"]);
+    assert_eq!(bank_select, Some((5, 0x20)));
+
+    let mut data = vec![0u8; 2 * PROM_BANK_SIZE];
+    data[0x80] = 0x04;
+    data[PROM_BANK_SIZE + 0x80] = 0x02;
+    let mut color = ColorSource::Prom {
+        data,
+        background: 0,
+        bank: 0,
+    };
+    assert_eq!(color.color(0), rbg_3bit(0x04));
+    color.set_bank(1);
+    assert_eq!(color.color(0), rbg_3bit(0x02));
+    color.set_bank(0);
+    assert_eq!(color.color(0), rbg_3bit(0x04));
+}
+
+#[test]
+fn test_color_ram() {
+    // Space Chaser paints its colours into RAM at 0xc000
+    let driver = find_driver("schaser").unwrap();
+    let ColorMap::Ram {
+        addr, size, mask, ..
+    } = driver.color
+    else {
+        panic!("schaser has no colour RAM");
+    };
+    assert_eq!((addr, size, mask), (0xc000, 0x2000, 0x1f9f));
+
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let color_ram = Rc::new(RefCell::new(vec![0u8; 0x2000]));
+    let mut bus = InvadersAddressBus::new(Vec::new(), video)
+        .unwrap()
+        .with_color_ram(0xc000, color_ram.clone())
+        .unwrap();
+    bus.set_mem(0xc400, 0x05);
+    assert_eq!(color_ram.borrow()[0x400], 0x05);
+    assert_eq!(bus.get_mem(0xc400), 0x05);
+    // Not a mirror of 0x4000 RAM
+    assert_eq!(bus.get_mem(0x4400), 0x00);
+
+    let color = ColorSource::Ram {
+        data: color_ram,
+        mask,
+        background: 0,
+    };
+    assert_eq!(color.color(0), rbg_3bit(0x05));
+    // Bits 5 and 6 of the address are not decoded: 4 lines share one entry
+    assert_eq!(color.color(3 * 32), rbg_3bit(0x05));
+    assert_eq!(color.color(4 * 32), rbg_3bit(0x00));
+}
@@ -0,0 +1,48 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::game::invaders::driver::INVADERS;
+use space_invaders_8080::game::invaders::siaddressing::ROM_SIZE;
+use space_invaders_8080::game::invaders::{Board, InvadersAddressBus, Screen, HALF_FRAME_CYCLES};
+
+#[test]
+fn test_run_frame() {
+    // 0000: LXI SP,2400h; EI; JMP 0004
+    // 0008: INR B; EI; RET (RST 1)
+    // 0010: INR C; EI; RET (RST 2)
+    let mut rom = Box::new([0u8; ROM_SIZE]);
+    rom[..7].copy_from_slice(&[0x31, 0x00, 0x24, 0xfb, 0xc3, 0x04, 0x00]);
+    rom[0x08..0x0b].copy_from_slice(&[0x04, 0xfb, 0xc9]);
+    rom[0x10..0x13].copy_from_slice(&[0x0c, 0xfb, 0xc9]);
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let bus = InvadersAddressBus::new(vec![(0x0000, rom)], video).unwrap();
+    let mut board = Board::new(&INVADERS, bus);
+
+    let cycles = board.run_frame();
+    assert!(cycles > 2 * HALF_FRAME_CYCLES as u64);
+    assert!(cycles < 2 * HALF_FRAME_CYCLES as u64 + 40);
+    board.run_frame();
+    // The frame ends on the vblank interrupt
+    assert_eq!(board.cpu.register.pc, 0x0010);
+    assert_eq!(board.cpu.register.b, 2);
+    assert_eq!(board.cpu.register.c, 1);
+    board.step();
+    assert_eq!(board.cpu.register.c, 2);
+}
+
+#[test]
+fn test_screen_rotation() {
+    let mut video = vec![0u8; 7168];
+    // First byte: bottom left, going up. Last byte: top right.
+    video[0] = 0x03;
+    video[7167] = 0x80;
+    let mut screen = Screen::new(&[], None);
+    screen.set_buffer(&video);
+    let buffer = screen.buffer();
+    assert_eq!(buffer.len(), 224 * 256);
+    assert_eq!(buffer[255 * 224], u32::MAX);
+    assert_eq!(buffer[254 * 224], u32::MAX);
+    assert_eq!(buffer[253 * 224], 0);
+    assert_eq!(buffer[223], u32::MAX);
+    assert_eq!(buffer.iter().filter(|&&p| p != 0).count(), 3);
+}
@@ -0,0 +1,36 @@
+//! [fuzz_one], the `cpu` fuzz target, run over a fixed set of random programs, so that `cargo test`
+//! covers it on a stable toolchain. `cargo +nightly fuzz run cpu` in `fuzz/` explores more.
+
+mod common;
+
+use common::random_program;
+use space_invaders_8080::game::invaders::fuzz::fuzz_one;
+use space_invaders_8080::game::invaders::siaddressing::ROM_SIZE;
+
+#[test]
+fn test_random_programs() {
+    for seed in 0..48 {
+        let mut data = random_program(seed, 1 + 4 * ROM_SIZE);
+        // Every variant, with all sockets, none, and a random set
+        data[0] = match seed % 3 {
+            0 => 0xf0,
+            1 => 0x00,
+            _ => data[0],
+        } | (seed % 3) as u8;
+        fuzz_one(&data, 20_000);
+    }
+}
+
+#[test]
+fn test_short_inputs() {
+    // Programs that end in the middle of an instruction, with the rest of the chip 0
+    fuzz_one(&[], 100);
+    fuzz_one(&[0x10], 100);
+    for op in 0..=255u8 {
+        // Each opcode alone, after DD and after ED, on one of the variants
+        let variant = op % 3;
+        fuzz_one(&[0x10 | variant, op], 100);
+        fuzz_one(&[0x10 | variant, 0xdd, op], 100);
+        fuzz_one(&[0x10 | variant, 0xed, op], 100);
+    }
+}
@@ -0,0 +1,406 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+
+use common::NoIO;
+use space_invaders_8080::cpu::gdb::{AccessLog, CpuTarget, GdbStub, Target, TARGET_XML};
+use space_invaders_8080::cpu::observer::Access;
+use space_invaders_8080::cpu::symbols::SymbolTable;
+use space_invaders_8080::cpu::Variant;
+use space_invaders_8080::game::invaders::driver::INVADERS;
+use space_invaders_8080::game::invaders::{load_roms, Board};
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::{Cpu, Register};
+
+/// gdb's side of the connection
+struct Client {
+    stream: TcpStream,
+    no_ack: bool,
+}
+
+impl Client {
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+        if !self.no_ack {
+            assert_eq!(self.read_byte(), b'+');
+        }
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let sum = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{:02x}", sum)
+        );
+        if !self.no_ack {
+            self.stream.write_all(b"+").unwrap();
+        }
+        String::from_utf8(data).unwrap()
+    }
+
+    fn ask(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+}
+
+/// Serve one session of `client` talking to `target`, then hand the stub back
+fn session<T: Target>(target: T, client: impl FnOnce(&mut Client) + Send + 'static) -> GdbStub<T> {
+    serve(GdbStub::new(target), client)
+}
+
+/// [session] with a stub set up by the test
+fn serve<T: Target>(
+    mut stub: GdbStub<T>,
+    client: impl FnOnce(&mut Client) + Send + 'static,
+) -> GdbStub<T> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let gdb = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        client(&mut Client {
+            stream,
+            no_ack: false,
+        });
+    });
+    let (stream, _) = listener.accept().unwrap();
+    stub.serve(stream).unwrap();
+    gdb.join().unwrap();
+    stub
+}
+
+fn machine() -> CpuTarget<FlatRam, NoIO> {
+    let program = [
+        0x31, 0x00, 0x24, // 0000 LXI SP,2400h
+        0x3e, 0x00, // 0003 MVI A,0
+        0x3c, // 0005 INR A
+        0x32, 0x00, 0x20, // 0006 STA 2000h
+        0xcd, 0x20, 0x00, // 0009 CALL 0020h
+        0xc3, 0x05, 0x00, // 000c JMP 0005h
+    ];
+    // RET at 0020
+    CpuTarget::new(
+        common::machine(&[(0x0000, &program), (0x0020, &[0xc9])]),
+        NoIO,
+    )
+}
+
+#[test]
+fn test_handshake() {
+    session(machine(), |gdb| {
+        let supported = gdb.ask("qSupported:multiprocess+;swbreak+;hwbreak+;xmlRegisters=i386");
+        assert!(supported.contains("qXfer:features:read+"));
+        assert!(supported.contains("QStartNoAckMode+"));
+        assert_eq!(gdb.ask("QStartNoAckMode"), "OK");
+        gdb.no_ack = true;
+        assert_eq!(gdb.ask("?"), "S02");
+        assert_eq!(gdb.ask("qAttached"), "1");
+        assert_eq!(gdb.ask("vMustReplyEmpty"), "");
+
+        // The target description, in small chunks
+        let mut xml = String::new();
+        loop {
+            let chunk = gdb.ask(&format!(
+                "qXfer:features:read:target.xml:{:x},40",
+                xml.len()
+            ));
+            xml.push_str(&chunk[1..]);
+            if chunk.starts_with('l') {
+                break;
+            }
+            assert!(chunk.starts_with('m'));
+        }
+        assert_eq!(xml, TARGET_XML);
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let mut target = machine();
+    target.cpu.register.a = 0x12;
+    target.cpu.register.set_bc(0x3456);
+    target.cpu.register.set_hl(0x789a);
+    target.cpu.register.flag_z = true;
+    target.cpu.register.flag_cy = true;
+    target.cpu.register.sp = 0x2400;
+    target.cpu.register.pc = 0x0005;
+    let stub = session(target, |gdb| {
+        // A F B C D E H L SP PC
+        assert_eq!(gdb.ask("g"), "124334560000789a00240500");
+        assert_eq!(gdb.ask("p1"), "43");
+        assert_eq!(gdb.ask("p9"), "0500");
+        assert_eq!(gdb.ask("pa"), "E01");
+        assert_eq!(gdb.ask("P4=ab"), "OK");
+        assert_eq!(gdb.ask("P8=fe23"), "OK");
+        assert_eq!(gdb.ask("g"), "12433456ab00789afe230500");
+        assert_eq!(gdb.ask("G0102"), "E01");
+
+        assert_eq!(gdb.ask("m0006,3"), "320020");
+        assert_eq!(gdb.ask("M2000,2:beef"), "OK");
+        assert_eq!(gdb.ask("m1fff,4"), "00beef00");
+        assert_eq!(gdb.ask("M2000,2:be"), "E01");
+        gdb.send("k");
+    });
+    let r = stub.target.registers();
+    assert_eq!(r.d, 0xab);
+    assert_eq!(r.sp, 0x23fe);
+    assert_eq!(r.a, 0x12);
+    assert_eq!(stub.target.bus().get_word(0x2000), 0xefbe);
+}
+
+#[test]
+fn test_whole_register_file() {
+    let stub = session(machine(), |gdb| {
+        // F: S Z AC P CY, bit 1 always reads 1
+        assert_eq!(gdb.ask("G01d50203040506070823abcd"), "OK");
+        assert_eq!(gdb.ask("g"), "01d70203040506070823abcd");
+        gdb.send("k");
+    });
+    let r = stub.target.registers();
+    assert_eq!((r.a, r.b, r.c, r.d, r.e, r.h, r.l), (1, 2, 3, 4, 5, 6, 7));
+    assert!(r.flag_s && r.flag_z && r.flag_ac && r.flag_p && r.flag_cy);
+    assert_eq!(r.sp, 0x2308);
+    assert_eq!(r.pc, 0xcdab);
+}
+
+#[test]
+fn test_step_and_breakpoints() {
+    let stub = session(machine(), |gdb| {
+        gdb.ask("qSupported:swbreak+");
+        assert_eq!(gdb.ask("s"), "S05");
+        assert_eq!(gdb.ask("p9"), "0300");
+        assert_eq!(gdb.ask("Z0,9,1"), "OK");
+        assert_eq!(gdb.ask("c"), "T05swbreak:;");
+        assert_eq!(gdb.ask("p9"), "0900");
+        assert_eq!(gdb.ask("p0"), "01");
+        // Continuing from the breakpoint runs the loop once more
+        assert_eq!(gdb.ask("c"), "T05swbreak:;");
+        assert_eq!(gdb.ask("p0"), "02");
+        assert_eq!(gdb.ask("?"), "T05swbreak:;");
+        assert_eq!(gdb.ask("z0,9,1"), "OK");
+        assert_eq!(gdb.ask("Z1,20,1"), "OK");
+        assert_eq!(gdb.ask("c"), "T05swbreak:;");
+        assert_eq!(gdb.ask("p9"), "2000");
+        assert_eq!(gdb.ask("z1,20,1"), "OK");
+        // Step from an address
+        assert_eq!(gdb.ask("s5"), "S05");
+        assert_eq!(gdb.ask("p9"), "0600");
+        assert_eq!(gdb.ask("p0"), "03");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+    assert_eq!(stub.target.registers().pc, 0x0006);
+}
+
+#[test]
+fn test_watchpoints() {
+    session(machine(), |gdb| {
+        // Write: the STA stops after it ran
+        assert_eq!(gdb.ask("Z2,2000,1"), "OK");
+        assert_eq!(gdb.ask("c"), "T05watch:2000;");
+        assert_eq!(gdb.ask("p9"), "0900");
+        assert_eq!(gdb.ask("m2000,1"), "01");
+        assert_eq!(gdb.ask("z2,2000,1"), "OK");
+
+        // Read: RET pops the return address from 23fe
+        assert_eq!(gdb.ask("Z3,23ff,1"), "OK");
+        assert_eq!(gdb.ask("c"), "T05rwatch:23ff;");
+        assert_eq!(gdb.ask("p9"), "0c00");
+        assert_eq!(gdb.ask("z3,23ff,1"), "OK");
+
+        // Access: the CALL pushes to it
+        assert_eq!(gdb.ask("Z4,23fe,2"), "OK");
+        assert_eq!(gdb.ask("c"), "T05awatch:23fe;");
+        assert_eq!(gdb.ask("p9"), "2000");
+        assert_eq!(gdb.ask("m23fe,2"), "0c00");
+        assert_eq!(gdb.ask("c"), "T05awatch:23fe;");
+        assert_eq!(gdb.ask("p9"), "0c00");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+}
+
+#[test]
+fn test_break_while_running() {
+    let stub = session(machine(), |gdb| {
+        gdb.send("c");
+        thread::sleep(std::time::Duration::from_millis(50));
+        gdb.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(gdb.receive(), "S02");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+    let pc = stub.target.registers().pc;
+    assert!((0x0005..0x000f).contains(&pc) || pc == 0x0020);
+}
+
+#[test]
+fn test_bad_packet_resent() {
+    session(machine(), |gdb| {
+        gdb.stream.write_all(b"$g#00").unwrap();
+        assert_eq!(gdb.read_byte(), b'-');
+        assert_eq!(gdb.ask("m0,1"), "31");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+}
+
+#[test]
+fn test_invaders_board() {
+    let (bus, _) = load_roms(&INVADERS, Rc::new(RefCell::new(vec![0u8; 7168]))).unwrap();
+    let board = Board::new(&INVADERS, AccessLog::new(bus));
+    let stub = session(board, |gdb| {
+        // The vblank interrupt handler is reached while running instruction by instruction
+        assert_eq!(gdb.ask("Z0,10,1"), "OK");
+        assert_eq!(gdb.ask("c"), "S05");
+        assert_eq!(gdb.ask("p9"), "1000");
+        assert_eq!(gdb.ask("c"), "S05");
+        assert_eq!(gdb.ask("p9"), "1000");
+        // The ROM reads through the bus
+        assert_eq!(gdb.ask("m0,4"), "000000c3");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+    assert_eq!(stub.target.registers().pc, 0x0010);
+}
+
+#[test]
+fn test_watchpoints_by_variant() {
+    // 8085: SHLX writes HL to (DE), where the 8080 would take d9 for RET
+    let program = [
+        0x31, 0x00, 0x24, // 0000 LXI SP,2400h
+        0x21, 0x34, 0x12, // 0003 LXI H,1234h
+        0x11, 0x00, 0x20, // 0006 LXI D,2000h
+        0xd9, // 0009 SHLX
+        0x76, // 000a HLT
+    ];
+    let cpu = Cpu::with_variant(common::ram(&[(0x0000, &program)]), 0, Variant::I8085);
+    session(CpuTarget::new(cpu, NoIO), |gdb| {
+        assert_eq!(gdb.ask("Z2,2001,1"), "OK");
+        assert_eq!(gdb.ask("c"), "T05watch:2001;");
+        assert_eq!(gdb.ask("p9"), "0a00");
+        assert_eq!(gdb.ask("m2000,2"), "3412");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+
+    // Z80: an indexed load reads IX+d, past its own four bytes
+    let program = [
+        0xdd, 0x21, 0x00, 0x20, // 0000 LD IX,2000h
+        0xdd, 0x7e, 0x05, // 0004 LD A,(IX+5)
+        0x76, // 0007 HALT
+    ];
+    let cpu = Cpu::with_variant(common::ram(&[(0x0000, &program)]), 0, Variant::Z80);
+    session(CpuTarget::new(cpu, NoIO), |gdb| {
+        // The operand bytes are fetched, not read
+        assert_eq!(gdb.ask("Z3,0002,2"), "OK");
+        assert_eq!(gdb.ask("Z3,2005,1"), "OK");
+        assert_eq!(gdb.ask("c"), "T05rwatch:2005;");
+        assert_eq!(gdb.ask("p9"), "0700");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+}
+
+/// A CPU taking RST 7 after its third instruction, the way [Board] raises its interrupts
+/// between two instructions
+struct Interrupted {
+    inner: CpuTarget<FlatRam, NoIO>,
+    steps: u32,
+}
+
+impl Target for Interrupted {
+    fn registers(&self) -> &Register {
+        self.inner.registers()
+    }
+
+    fn registers_mut(&mut self) -> &mut Register {
+        self.inner.registers_mut()
+    }
+
+    fn bus(&self) -> &dyn AddressBus {
+        self.inner.bus()
+    }
+
+    fn bus_mut(&mut self) -> &mut dyn AddressBus {
+        self.inner.bus_mut()
+    }
+
+    fn step(&mut self) -> u8 {
+        let cycles = self.inner.step();
+        self.steps += 1;
+        if self.steps == 3 {
+            self.inner.cpu.interrupt(0xff);
+        }
+        cycles
+    }
+
+    fn access_log(&mut self) -> &mut Vec<(Access, u16)> {
+        self.inner.access_log()
+    }
+}
+
+#[test]
+fn test_watch_interrupt_push() {
+    let program = [
+        0x31, 0x00, 0x24, // 0000 LXI SP,2400h
+        0xfb, // 0003 EI
+        0x00, // 0004 NOP
+        0x00, // 0005 NOP
+        0x76, // 0006 HLT
+    ];
+    let target = Interrupted {
+        inner: CpuTarget::new(common::machine(&[(0x0000, &program)]), NoIO),
+        steps: 0,
+    };
+    session(target, |gdb| {
+        assert_eq!(gdb.ask("Z2,23fe,2"), "OK");
+        assert_eq!(gdb.ask("c"), "T05watch:23fe;");
+        assert_eq!(gdb.ask("p9"), "3800");
+        assert_eq!(gdb.ask("m23fe,2"), "0500");
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+}
+
+/// `monitor` command as gdb sends it, and its output
+fn monitor(gdb: &mut Client, command: &str) -> String {
+    let hex: String = command.bytes().map(|b| format!("{:02x}", b)).collect();
+    let reply = gdb.ask(&format!("qRcmd,{}", hex));
+    let bytes: Vec<u8> = (0..reply.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&reply[i..i + 2], 16).unwrap())
+        .collect();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn test_monitor_symbols() {
+    let mut stub = GdbStub::new(machine());
+    stub.set_symbols(
+        SymbolTable::parse("0000 code start\n0020 code sub\n2000 data counter").unwrap(),
+    );
+    serve(stub, |gdb| {
+        assert_eq!(monitor(gdb, "where"), "pc 0000 start\n");
+        assert_eq!(gdb.ask("Z0,20,1"), "OK");
+        gdb.ask("c");
+        assert_eq!(monitor(gdb, "where"), "pc 0020 sub\n");
+        assert_eq!(monitor(gdb, "sym counter"), "counter = 2000\n");
+        assert_eq!(monitor(gdb, "sym nothing"), "no symbol nothing\n");
+        assert!(monitor(gdb, "help").starts_with("commands:"));
+        assert_eq!(gdb.ask("D"), "OK");
+    });
+}
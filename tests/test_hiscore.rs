@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::game::{Hiscore, HiscoreDescriptor, HiscoreEntry};
+use space_invaders_8080::TestAddressing;
+
+#[test]
+fn test_builtin_invaders() {
+    let desc = HiscoreDescriptor::builtin("invaders").unwrap();
+    assert_eq!(
+        desc.entries,
+        vec![HiscoreEntry {
+            addr: 0x20f4,
+            len: 2,
+            start: 0,
+            end: 0
+        }]
+    );
+    assert!(HiscoreDescriptor::builtin("unknown").is_none());
+}
+
+#[test]
+fn test_parse_shared_sets() {
+    let text = "
+; comment
+lrescue:
+invadpt2:
+2100,3,00,00
+2200,1,ff,ff
+
+ballbomb:
+2000,1,00,00
+";
+    let desc = HiscoreDescriptor::parse(text, "invadpt2").unwrap().unwrap();
+    assert_eq!(desc.entries.len(), 2);
+    assert_eq!(desc.size(), 4);
+    let desc = HiscoreDescriptor::parse(text, "ballbomb").unwrap().unwrap();
+    assert_eq!(desc.entries[0].addr, 0x2000);
+    assert!(HiscoreDescriptor::parse("invaders:\n20f4,2,00\n", "invaders").is_err());
+}
+
+#[test]
+fn test_save_and_load() {
+    let dir = std::env::temp_dir().join(format!("si8080-hiscore-{}", std::process::id()));
+    let desc = HiscoreDescriptor::builtin("invaders").unwrap();
+
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    let mut bus = TestAddressing::new(mem.clone());
+    let mut hiscore = Hiscore::new(desc.clone(), &dir, "invaders");
+    hiscore.update(&mut bus).unwrap();
+    assert!(hiscore.is_loaded());
+    mem.borrow_mut()[0x20f4] = 0x50;
+    mem.borrow_mut()[0x20f5] = 0x12;
+    hiscore.save(&bus).unwrap();
+
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    let mut bus = TestAddressing::new(mem.clone());
+    let mut hiscore = Hiscore::new(desc, &dir, "invaders");
+    // Not safe yet: the game has not cleared the range
+    mem.borrow_mut()[0x20f4] = 0xaa;
+    hiscore.update(&mut bus).unwrap();
+    assert!(!hiscore.is_loaded());
+    mem.borrow_mut()[0x20f4] = 0x00;
+    hiscore.update(&mut bus).unwrap();
+    assert!(hiscore.is_loaded());
+    assert_eq!(mem.borrow()[0x20f4], 0x50);
+    assert_eq!(mem.borrow()[0x20f5], 0x12);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_reset_between_load_and_save() {
+    let dir = std::env::temp_dir().join(format!("si8080-hiscore-reset-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("invaders.hi"), [0x50, 0x12]).unwrap();
+    let desc = HiscoreDescriptor::builtin("invaders").unwrap();
+
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    let mut bus = TestAddressing::new(mem.clone());
+    let mut hiscore = Hiscore::new(desc, &dir, "invaders");
+    hiscore.update(&mut bus).unwrap();
+    assert_eq!(mem.borrow()[0x20f4], 0x50);
+
+    // A reset reruns the ROM init, which clears the score
+    hiscore.rearm();
+    assert!(!hiscore.is_loaded());
+    mem.borrow_mut()[0x20f4] = 0x00;
+    mem.borrow_mut()[0x20f5] = 0x00;
+    // Nothing is written while the table is not loaded again
+    hiscore.save(&bus).unwrap();
+    assert_eq!(
+        std::fs::read(dir.join("invaders.hi")).unwrap(),
+        [0x50, 0x12]
+    );
+
+    hiscore.update(&mut bus).unwrap();
+    assert!(hiscore.is_loaded());
+    assert_eq!(mem.borrow()[0x20f4], 0x50);
+    assert_eq!(mem.borrow()[0x20f5], 0x12);
+    hiscore.save(&bus).unwrap();
+    assert_eq!(
+        std::fs::read(dir.join("invaders.hi")).unwrap(),
+        [0x50, 0x12]
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
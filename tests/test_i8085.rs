@@ -0,0 +1,226 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::cpu::{Line8085, Variant, IO};
+use space_invaders_8080::{DynCpu, Register, TestAddressing};
+
+struct TestIO;
+
+impl IO for TestIO {
+    fn input(&mut self, _cpu: &mut Register, _byte: u8) {}
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+fn test_cpu(program: &[u8]) -> DynCpu {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    mem.borrow_mut()[..program.len()].copy_from_slice(program);
+    let mut cpu = DynCpu::with_variant(
+        Box::new(TestAddressing::new(mem)),
+        0,
+        Rc::new(RefCell::new(TestIO)),
+        Variant::I8085,
+    );
+    cpu.register.sp = 0x8000;
+    cpu
+}
+
+#[test]
+fn test_dsub() {
+    let mut cpu = test_cpu(&[0x08, 0x08]);
+    cpu.register.set_hl(0x1234);
+    cpu.register.set_bc(0x0234);
+    assert_eq!(cpu.next(), 10);
+    assert_eq!(cpu.register.get_hl(), 0x1000);
+    assert!(!cpu.register.flag_z);
+    assert!(!cpu.register.flag_cy);
+
+    cpu.register.set_bc(0x1001);
+    cpu.next();
+    assert_eq!(cpu.register.get_hl(), 0xffff);
+    assert!(cpu.register.flag_cy);
+    assert!(cpu.register.flag_s);
+}
+
+#[test]
+fn test_arhl() {
+    let mut cpu = test_cpu(&[0x10]);
+    cpu.register.set_hl(0x8003);
+    assert_eq!(cpu.next(), 7);
+    assert_eq!(cpu.register.get_hl(), 0xc001);
+    assert!(cpu.register.flag_cy);
+}
+
+#[test]
+fn test_rdel() {
+    let mut cpu = test_cpu(&[0x18, 0x18]);
+    cpu.register.set_de(0x4001);
+    cpu.register.flag_cy = true;
+    cpu.next();
+    assert_eq!(cpu.register.get_de(), 0x8003);
+    assert!(!cpu.register.flag_cy);
+    assert!(cpu.register.flag_v);
+    cpu.next();
+    assert_eq!(cpu.register.get_de(), 0x0006);
+    assert!(cpu.register.flag_cy);
+}
+
+#[test]
+fn test_ldhi_ldsi() {
+    // LDHI 10h; LDSI 0ffh
+    let mut cpu = test_cpu(&[0x28, 0x10, 0x38, 0xff]);
+    cpu.register.set_hl(0x12f8);
+    cpu.next();
+    assert_eq!(cpu.register.get_de(), 0x1308);
+    cpu.next();
+    assert_eq!(cpu.register.get_de(), 0x80ff);
+}
+
+#[test]
+fn test_shlx_lhlx() {
+    // SHLX; LHLX
+    let mut cpu = test_cpu(&[0xd9, 0xed]);
+    cpu.register.set_de(0x4000);
+    cpu.register.set_hl(0xbeef);
+    cpu.next();
+    assert_eq!(cpu.addring.get_word(0x4000), 0xbeef);
+    cpu.register.set_hl(0);
+    assert_eq!(cpu.next(), 10);
+    assert_eq!(cpu.register.get_hl(), 0xbeef);
+}
+
+#[test]
+fn test_jnk_jk() {
+    // INX B; JNK 1000h; JK 2000h
+    let mut cpu = test_cpu(&[0x03, 0xdd, 0x00, 0x10, 0xfd, 0x00, 0x20]);
+    cpu.register.set_bc(0xffff);
+    cpu.next();
+    assert!(cpu.register.flag_k);
+    assert_eq!(cpu.next(), 7);
+    assert_eq!(cpu.register.pc, 0x0004);
+    assert_eq!(cpu.next(), 10);
+    assert_eq!(cpu.register.pc, 0x2000);
+}
+
+#[test]
+fn test_rstv() {
+    // MVI A,7fh; ADI 1; RSTV; RSTV
+    let mut cpu = test_cpu(&[0x3e, 0x7f, 0xc6, 0x01, 0xcb]);
+    cpu.next();
+    cpu.next();
+    assert!(cpu.register.flag_v);
+    assert_eq!(cpu.next(), 12);
+    assert_eq!(cpu.register.pc, 0x0040);
+    assert_eq!(cpu.addring.get_word(cpu.register.sp), 0x0005);
+
+    // Not taken without overflow
+    let mut cpu = test_cpu(&[0xcb]);
+    assert_eq!(cpu.next(), 6);
+    assert_eq!(cpu.register.pc, 0x0001);
+}
+
+#[test]
+fn test_psw_keeps_v_and_k() {
+    // PUSH PSW; POP PSW
+    let mut cpu = test_cpu(&[0xf5, 0xf1]);
+    cpu.register.flag_v = true;
+    cpu.register.flag_k = true;
+    cpu.next();
+    assert_eq!(cpu.addring.get_mem(cpu.register.sp), 0b0010_0010);
+    cpu.register.flag_v = false;
+    cpu.register.flag_k = false;
+    cpu.next();
+    assert!(cpu.register.flag_v && cpu.register.flag_k);
+}
+
+#[test]
+fn test_rim_sim() {
+    // MVI A,0dh; SIM; RIM; MVI A,0c0h; SIM
+    let mut cpu = test_cpu(&[0x3e, 0x0d, 0x30, 0x20, 0x3e, 0xc0, 0x30]);
+    cpu.set_sid(true);
+    cpu.set_line(Line8085::Rst65, true);
+    cpu.next();
+    cpu.next();
+    cpu.next();
+    // SID, 6.5 pending, IE off, 7.5 and 5.5 masked
+    assert_eq!(cpu.register.a, 0b1010_0101);
+    assert!(!cpu.sod());
+    cpu.next();
+    cpu.next();
+    assert!(cpu.sod());
+}
+
+#[test]
+fn test_rst_lines() {
+    // EI; NOP; MVI A,08h; SIM; NOP
+    let mut cpu = test_cpu(&[0xfb, 0x00, 0x3e, 0x08, 0x30, 0x00]);
+    // RESET IN masks every RST line
+    cpu.reset();
+    cpu.register.sp = 0x8000;
+    cpu.set_line(Line8085::Rst55, true);
+    cpu.next();
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0004);
+
+    // Unmasked by SIM, 7.5 is latched on the rising edge and wins over 5.5
+    cpu.set_line(Line8085::Rst75, true);
+    cpu.set_line(Line8085::Rst75, false);
+    cpu.next();
+    assert_eq!(cpu.next(), 12);
+    assert_eq!(cpu.register.pc, 0x003c);
+    assert_eq!(cpu.addring.get_word(cpu.register.sp), 0x0005);
+    assert!(!cpu.interrupts_enabled());
+}
+
+#[test]
+fn test_trap() {
+    // HLT with interrupts off; RIM at the TRAP vector
+    let mut cpu = test_cpu(&[0x76]);
+    cpu.addring.set_mem(0x24, 0x20);
+    cpu.next();
+    assert!(cpu.is_halted());
+    cpu.set_line(Line8085::Trap, true);
+    assert_eq!(cpu.next(), 12);
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.register.pc, 0x0024);
+    // Held high, TRAP only fires once
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0025);
+    assert_eq!(cpu.register.a & 0x08, 0);
+}
+
+#[test]
+fn test_8080_ignores_8085_opcodes() {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    // RIM is a NOP and 0xcb a JMP on the 8080
+    mem.borrow_mut()[..4].copy_from_slice(&[0x20, 0xcb, 0x00, 0x30]);
+    let mut cpu = DynCpu::new(
+        Box::new(TestAddressing::new(mem)),
+        0,
+        Rc::new(RefCell::new(TestIO)),
+    );
+    cpu.register.a = 0x55;
+    cpu.next();
+    assert_eq!(cpu.register.a, 0x55);
+    assert_eq!(cpu.next(), 10);
+    assert_eq!(cpu.register.pc, 0x3000);
+}
+
+#[test]
+fn test_8080_psw_without_v_and_k() {
+    // MVI A,7fh; ADI 1 (overflows); PUSH PSW
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    mem.borrow_mut()[..5].copy_from_slice(&[0x3e, 0x7f, 0xc6, 0x01, 0xf5]);
+    let mut cpu = DynCpu::new(
+        Box::new(TestAddressing::new(mem)),
+        0,
+        Rc::new(RefCell::new(TestIO)),
+    );
+    cpu.register.sp = 0x8000;
+    cpu.next();
+    cpu.next();
+    cpu.next();
+    // S and AC: bits 5 and 3 stay 0 and bit 1 stays 1, as on the 8080
+    assert_eq!(cpu.addring.get_mem(cpu.register.sp), 0b1001_0010);
+}
@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::cpu::{HALT_CYCLES, IO};
+use space_invaders_8080::{DynCpu, Register, TestAddressing};
+
+struct TestIO;
+
+impl IO for TestIO {
+    fn input(&mut self, _cpu: &mut Register, _byte: u8) {}
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+fn test_cpu(program: &[u8]) -> DynCpu {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    mem.borrow_mut()[..program.len()].copy_from_slice(program);
+    let mut cpu = DynCpu::new(
+        Box::new(TestAddressing::new(mem)),
+        0,
+        Rc::new(RefCell::new(TestIO)),
+    );
+    cpu.register.sp = 0x8000;
+    cpu
+}
+
+#[test]
+fn test_ei_delay() {
+    // EI; NOP; NOP
+    let mut cpu = test_cpu(&[0xfb, 0x00, 0x00]);
+    cpu.request_interrupt(&[0xff]);
+    cpu.next();
+    assert!(cpu.interrupts_enabled());
+    // The instruction after EI runs before the interrupt
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0002);
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0038);
+    assert_eq!(cpu.addring.get_word(cpu.register.sp), 0x0002);
+    assert!(!cpu.interrupts_enabled());
+    assert!(!cpu.interrupt_pending());
+}
+
+#[test]
+fn test_pending_while_disabled() {
+    // DI; NOP; EI; NOP; NOP
+    let mut cpu = test_cpu(&[0xf3, 0x00, 0xfb, 0x00, 0x00]);
+    cpu.request_interrupt(&[0xcf]);
+    cpu.next();
+    cpu.next();
+    assert!(cpu.interrupt_pending());
+    assert!(!cpu.interrupt(0xcf));
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0004);
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0008);
+
+    cpu.request_interrupt(&[0xcf]);
+    cpu.clear_interrupt();
+    assert!(!cpu.interrupt_pending());
+}
+
+#[test]
+fn test_hlt_wake_up() {
+    // EI; HLT
+    let mut cpu = test_cpu(&[0xfb, 0x76]);
+    cpu.next();
+    cpu.next();
+    assert!(cpu.is_halted());
+    assert_eq!(cpu.next(), HALT_CYCLES);
+    assert_eq!(cpu.register.pc, 0x0002);
+
+    cpu.request_interrupt(&[0xd7]);
+    assert_eq!(cpu.next(), 11);
+    assert!(!cpu.is_halted());
+    assert_eq!(cpu.register.pc, 0x0010);
+    assert_eq!(cpu.addring.get_word(cpu.register.sp), 0x0002);
+
+    // With interrupts off only a reset ends HLT
+    let mut cpu = test_cpu(&[0x76]);
+    cpu.request_interrupt(&[0xd7]);
+    cpu.next();
+    cpu.next();
+    assert!(cpu.is_halted());
+    cpu.reset();
+    assert!(!cpu.is_halted());
+}
+
+#[test]
+fn test_call_on_data_bus() {
+    // EI; NOP
+    let mut cpu = test_cpu(&[0xfb, 0x00]);
+    cpu.next();
+    cpu.next();
+    cpu.request_interrupt(&[0xcd, 0x34, 0x12]);
+    assert_eq!(cpu.next(), 17);
+    assert_eq!(cpu.register.pc, 0x1234);
+    assert_eq!(cpu.addring.get_word(cpu.register.sp), 0x0002);
+}
+
+#[test]
+#[should_panic]
+fn test_empty_instruction() {
+    test_cpu(&[]).request_interrupt(&[]);
+}
@@ -0,0 +1,78 @@
+use space_invaders_8080::cpu::{IoBus, PortDevice};
+use space_invaders_8080::device::{Latch, ShiftRegister};
+use space_invaders_8080::game::invaders::driver::{Input, INVADERS};
+use space_invaders_8080::game::invaders::{io_bus, InputPanel};
+
+/// Counts the accesses it sees
+#[derive(Default)]
+struct Counter {
+    reads: usize,
+    writes: Vec<(u8, u8)>,
+}
+
+impl PortDevice for Counter {
+    fn input(&mut self, port: u8) -> u8 {
+        self.reads += 1;
+        port
+    }
+
+    fn output(&mut self, port: u8, val: u8) {
+        self.writes.push((port, val));
+    }
+}
+
+#[test]
+fn test_dispatch_and_open_bus() {
+    let mut io = IoBus::new();
+    io.attach(Counter::default())
+        .input(0x10..=0x11)
+        .output([0x20]);
+    assert_eq!(io.read(0x11), 0x11);
+    io.write(0x20, 0x55);
+    // Output only is not input
+    assert_eq!(io.read(0x20), 0xff);
+    io.write(0x10, 0x66);
+    io.set_open_bus(0x00);
+    assert_eq!(io.read(0x30), 0x00);
+    let counter = io.device::<Counter>().unwrap();
+    assert_eq!(counter.reads, 1);
+    assert_eq!(counter.writes, vec![(0x20, 0x55)]);
+}
+
+#[test]
+#[should_panic(expected = "mapped twice")]
+fn test_double_mapping() {
+    let mut io = IoBus::new();
+    io.attach(Latch::new()).output([3]);
+    io.attach(Counter::default()).output([3]);
+}
+
+#[test]
+fn test_shift_register() {
+    let mut shifter = ShiftRegister::new(2, 4);
+    shifter.output(4, 0xab);
+    shifter.output(4, 0xcd);
+    assert_eq!(shifter.input(3), 0xcd);
+    shifter.output(2, 4);
+    assert_eq!(shifter.input(3), 0xda);
+    // Only the low 3 bits count
+    shifter.output(2, 0x0f);
+    assert_eq!(shifter.input(3), 0xd5);
+}
+
+#[test]
+fn test_invaders_ports() {
+    let mut panel = InputPanel::new(&INVADERS.ports);
+    assert_eq!(panel.input(1), 0x08);
+    panel.set_inputs(vec![Input::Coin, Input::Fire2]);
+    assert_eq!(panel.input(1), 0x09);
+    assert_eq!(panel.input(2), 0x90);
+
+    let mut io = io_bus(&INVADERS.ports);
+    io.write(4, 0xff);
+    io.write(2, 0);
+    assert_eq!(io.read(3), 0xff);
+    assert_eq!(io.read(0), 0x0e);
+    io.write(5, 0x12);
+    assert_eq!(io.device::<Latch>().unwrap().get(5), 0x12);
+}
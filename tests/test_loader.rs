@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::cpu::IO;
+use space_invaders_8080::memory::loader;
+use space_invaders_8080::{DynCpu, Register, TestAddressing};
+
+struct TestIO;
+
+impl IO for TestIO {
+    fn input(&mut self, _cpu: &mut Register, _byte: u8) {}
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+fn test_cpu() -> (DynCpu, Rc<RefCell<Vec<u8>>>) {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    let cpu = DynCpu::new(
+        Box::new(TestAddressing::new(mem.clone())),
+        0,
+        Rc::new(RefCell::new(TestIO)),
+    );
+    (cpu, mem)
+}
+
+#[test]
+fn test_hex_start_address() {
+    let (mut cpu, mem) = test_cpu();
+    // MVI A,42H; HLT at 0x100, linear start address 0x100
+    let hex = ":020000040000FA\n:030100003E427606\n:0400000500000100F6\n:00000001FF\n";
+    assert_eq!(loader::boot_hex(&mut cpu, hex).unwrap(), Some(0x0100));
+    assert_eq!(cpu.register.pc, 0x0100);
+    assert_eq!(&mem.borrow()[0x100..0x103], &[0x3e, 0x42, 0x76]);
+
+    // Segment start address 0010:0005
+    let (mut cpu, _) = test_cpu();
+    assert_eq!(
+        loader::boot_hex(&mut cpu, ":0400000300100005E4\n").unwrap(),
+        Some(0x0105)
+    );
+    assert_eq!(cpu.register.pc, 0x0105);
+}
+
+#[test]
+fn test_hex_without_start_address() {
+    let (mut cpu, mem) = test_cpu();
+    cpu.register.pc = 0x1234;
+    // Extended segment 0x1000 moves the record to 0x10010; data after EOF is ignored
+    let hex = ":020000021000EC\n:00000001FF\n:0100000001FE\n";
+    assert_eq!(loader::boot_hex(&mut cpu, hex).unwrap(), None);
+    assert_eq!(cpu.register.pc, 0x1234);
+    assert_eq!(mem.borrow()[0], 0);
+}
+
+#[test]
+fn test_hex_errors() {
+    let (mut cpu, _) = test_cpu();
+    let bus = cpu.addring.as_mut();
+    // Checksum
+    assert!(loader::load_hex(bus, ":030100003E427607\n").is_err());
+    // Missing colon, odd length, bad digit
+    assert!(loader::load_hex(bus, "030100003E427606\n").is_err());
+    assert!(loader::load_hex(bus, ":030100003E42760\n").is_err());
+    assert!(loader::load_hex(bus, ":0301000G3E427606\n").is_err());
+    // Data above 64K
+    assert!(loader::load_hex(bus, ":020000040001F9\n:0100000001FE\n").is_err());
+    // Unknown record type
+    assert!(loader::load_hex(bus, ":00000006FA\n").is_err());
+}
+
+#[test]
+fn test_binary_at_offset() {
+    let (mut cpu, mem) = test_cpu();
+    loader::boot_binary(&mut cpu, &[1, 2, 3], 0xfffe, 0xfffe);
+    assert_eq!(cpu.register.pc, 0xfffe);
+    assert_eq!(mem.borrow()[0xfffe], 1);
+    assert_eq!(mem.borrow()[0xffff], 2);
+    assert_eq!(mem.borrow()[0x0000], 3);
+}
+
+#[test]
+fn test_hex_64k_boundary() {
+    let (mut cpu, mem) = test_cpu();
+    let bus = cpu.addring.as_mut();
+    // Segment 0001 puts FFF0 at 10000: an empty record there writes nothing
+    let hex = ":020000020001FB\n:00FFF00011\n:00000001FF\n";
+    assert_eq!(loader::load_hex(bus, hex).unwrap(), None);
+    // Up to the last byte of 64K is fine, one more is not
+    let hex = ":020000020001FB\n:02FFEE00AABBAC\n";
+    loader::load_hex(bus, hex).unwrap();
+    assert_eq!(&mem.borrow()[0xfffe..], &[0xaa, 0xbb]);
+    assert_eq!(mem.borrow()[0x0000], 0);
+    assert!(loader::load_hex(bus, ":020000020001FB\n:03FFEE00AABBCCDF\n").is_err());
+
+    // Start addresses: 0FFF:000F is FFFF, FFFF:0010 and 00010100 are above 64K
+    assert_eq!(
+        loader::load_hex(bus, ":040000030FFF000FDC\n").unwrap(),
+        Some(0xffff)
+    );
+    assert!(loader::load_hex(bus, ":04000003FFFF0010EB\n").is_err());
+    assert!(loader::load_hex(bus, ":0400000500010100F5\n").is_err());
+}
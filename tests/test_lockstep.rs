@@ -0,0 +1,130 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::random_program;
+use space_invaders_8080::cpu::lockstep::{BlockCore, CpuCore, Lockstep};
+use space_invaders_8080::cpu::symbols::SymbolTable;
+use space_invaders_8080::cpu::{IoBus, IO};
+use space_invaders_8080::device::Watchdog;
+use space_invaders_8080::game::invaders::driver::{Input, INVADERS};
+use space_invaders_8080::game::invaders::{io_bus, load_roms, InputPanel, HALF_FRAME_CYCLES};
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::{Cpu, Register};
+
+/// IN returns the port number, OUT is ignored
+struct EchoIO;
+
+impl IO for EchoIO {
+    fn input(&mut self, cpu: &mut Register, byte: u8) {
+        cpu.a = byte;
+    }
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+fn flat(program: &[u8]) -> Cpu<FlatRam, EchoIO> {
+    let mut memory = FlatRam::new();
+    for (i, byte) in program.iter().enumerate() {
+        memory.set_mem(i as u16, *byte);
+    }
+    let mut cpu = Cpu::new(memory, 0);
+    cpu.register.sp = 0xf000;
+    cpu
+}
+
+fn invaders() -> (
+    Cpu<space_invaders_8080::game::invaders::InvadersAddressBus, IoBus>,
+    IoBus,
+) {
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let (bus, _) = load_roms(&INVADERS, video).unwrap();
+    let mut io = io_bus(&INVADERS.ports);
+    io.device_mut::<Watchdog>().unwrap().set_enabled(false);
+    (Cpu::new(bus, 0), io)
+}
+
+#[test]
+fn test_invaders_interpreter_against_blocks() {
+    let (cpu, io) = invaders();
+    let a = CpuCore::new(cpu, io);
+    let (cpu, io) = invaders();
+    let b = BlockCore::new(cpu, io);
+    let mut lockstep = Lockstep::new(a, b);
+    for frame in 0..600 {
+        let inputs = match frame {
+            300..=305 => vec![Input::Coin],
+            400..=405 => vec![Input::Start1],
+            450.. => vec![Input::Fire1],
+            _ => Vec::new(),
+        };
+        for io in [&mut lockstep.a.io, &mut lockstep.b.io] {
+            let panel = io.device_mut::<InputPanel>().unwrap();
+            panel.set_inputs(inputs.clone());
+        }
+        for rst in [0xcf, 0xd7] {
+            lockstep
+                .run_cycles(HALF_FRAME_CYCLES + 1)
+                .unwrap_or_else(|e| panic!("frame {}: {}", frame, e));
+            lockstep.interrupt(rst).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_random_programs() {
+    for seed in 0..40 {
+        let program = random_program(seed, 0x1000);
+        let a = CpuCore::new(flat(&program), EchoIO);
+        let b = BlockCore::new(flat(&program), EchoIO);
+        let mut lockstep = Lockstep::new(a, b);
+        for _ in 0..20 {
+            lockstep
+                .run(500)
+                .unwrap_or_else(|e| panic!("seed {}: {}", seed, e));
+            lockstep.interrupt(0xd7).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_divergence_report() {
+    // MVI A,5; STA 2000h; INR A. The second core starts with another operand.
+    let a = CpuCore::new(flat(&[0x3e, 0x05, 0x32, 0x00, 0x20, 0x3c]), EchoIO);
+    let b = CpuCore::new(flat(&[0x3e, 0x05, 0x32, 0x00, 0x20, 0x3c]), EchoIO);
+    let mut lockstep = Lockstep::new(a, b);
+    lockstep.b.cpu.addring.get_mut().set_mem(0x0001, 0x06);
+    let e = lockstep.step().unwrap_err();
+    assert_eq!(e.step, 0);
+    assert_eq!(e.pc, 0x0000);
+    assert_eq!(e.diffs, vec!["a: 05 != 06"]);
+
+    let a = CpuCore::new(flat(&[0x3e, 0x05, 0x32, 0x00, 0x20, 0x3c]), EchoIO);
+    let b = CpuCore::new(flat(&[0x3e, 0x05, 0x32, 0x00, 0x20, 0x3c]), EchoIO);
+    let mut lockstep = Lockstep::new(a, b);
+    lockstep.step().unwrap();
+    lockstep.b.cpu.register.a = 0x06;
+    lockstep.b.cpu.register.flag_z = true;
+    let e = lockstep.step().unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "diverged at step 1, pc 0002\n  a: 05 != 06\n  flags: ..... != .Z...\n  \
+         writes: [2000=05] != [2000=06]"
+    );
+
+    // With symbols, PC and the written variables are named
+    let a = CpuCore::new(flat(&[0x3e, 0x05, 0x32, 0x00, 0x20, 0x3c]), EchoIO);
+    let b = CpuCore::new(flat(&[0x3e, 0x05, 0x32, 0x00, 0x20, 0x3c]), EchoIO);
+    let mut lockstep = Lockstep::new(a, b);
+    lockstep.set_symbols(SymbolTable::parse("0000 code start\n2000 data counter").unwrap());
+    lockstep.step().unwrap();
+    lockstep.b.cpu.register.a = 0x06;
+    let e = lockstep.step().unwrap_err();
+    assert_eq!(e.location.as_deref(), Some("start+2"));
+    assert_eq!(
+        e.to_string(),
+        "diverged at step 1, pc 0002 (start+2)\n  a: 05 != 06\n  \
+         writes: [2000(counter)=05] != [2000(counter)=06]"
+    );
+}
@@ -0,0 +1,142 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::memory::{Access, AddressBus, Callbacks, MemoryMap, MmioDevice, Region};
+
+#[test]
+fn test_rom_ram_mirror() {
+    let mut map = MemoryMap::builder()
+        .region(Region::rom(0x0000, vec![0x11; 0x1000]).mirror(0x7fff))
+        .region(Region::ram(0x2000, 0x400).mirror(0x3fff))
+        .build()
+        .unwrap();
+    assert_eq!(map.get_mem(0x0fff), 0x11);
+    assert_eq!(map.get_mem(0x8000), 0x11);
+    map.set_mem(0x0000, 0x22);
+    assert_eq!(map.get_mem(0x0000), 0x11);
+    map.set_mem(0x23ff, 0x33);
+    assert_eq!(map.get_mem(0x63ff), 0x33);
+    assert_eq!(map.get_mem(0xe3ff), 0x33);
+    // Unmapped
+    assert_eq!(map.get_mem(0x2400), 0xff);
+    map.set_mem(0x2400, 0x44);
+    assert_eq!(map.get_mem(0x2400), 0xff);
+}
+
+#[test]
+fn test_shared_and_split_page() {
+    let video = Rc::new(RefCell::new(vec![0u8; 0x10]));
+    let mut map = MemoryMap::builder()
+        .open_bus(0)
+        .region(Region::ram(0x1000, 0x08))
+        .region(Region::shared(0x1008, video.clone(), Access::Ram))
+        .build()
+        .unwrap();
+    map.set_mem(0x1007, 0x01);
+    map.set_mem(0x1008, 0x02);
+    assert_eq!(map.get_mem(0x1007), 0x01);
+    assert_eq!(video.borrow()[0], 0x02);
+    assert_eq!(map.get_mem(0x1018), 0x00);
+}
+
+#[test]
+fn test_mmio_callbacks() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let writes = log.clone();
+    let mut map = MemoryMap::builder()
+        .region(Region::ram(0x0000, 0xf000))
+        .region(Region::mmio(
+            0xf000,
+            2,
+            |offset| 0x80 | offset as u8,
+            move |offset, val| writes.borrow_mut().push((offset, val)),
+        ))
+        .build()
+        .unwrap();
+    assert_eq!(map.get_mem(0xf001), 0x81);
+    map.set_mem(0xf000, 0x55);
+    assert_eq!(*log.borrow(), vec![(0, 0x55)]);
+    assert_eq!(map.get_mem(0xf002), 0xff);
+}
+
+/// Keyboard latch: reading the data register clears the strobe bit in the status register
+#[derive(Default)]
+struct Keyboard {
+    key: Option<u8>,
+    /// Bytes written to the control register
+    control: Vec<u8>,
+}
+
+impl MmioDevice for Keyboard {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset {
+            0 => u8::from(self.key.is_some()),
+            _ => self.key.take().unwrap_or(0),
+        }
+    }
+
+    fn write(&mut self, _offset: u16, val: u8) {
+        self.control.push(val);
+    }
+}
+
+#[test]
+fn test_mmio_device() {
+    let mut map = MemoryMap::builder()
+        .region(Region::ram(0x0000, 0x8000))
+        .region(Region::device(0xd010, 2, Keyboard::default()).mirror(0xfff1))
+        .build()
+        .unwrap();
+    map.device_mut::<Keyboard>().unwrap().key = Some(b'A');
+    assert_eq!(map.get_mem(0xd010), 1);
+    // Mirror of the data register
+    assert_eq!(map.get_mem(0xd01f), b'A');
+    assert_eq!(map.get_mem(0xd010), 0);
+    map.set_mem(0xd010, 0x7f);
+    assert_eq!(map.device::<Keyboard>().unwrap().control, vec![0x7f]);
+    assert!(map.device::<Callbacks>().is_none());
+
+    // Attached to a map that is already built
+    let mut map = MemoryMap::builder().build().unwrap();
+    map.add(Region::device(0xc000, 2, Keyboard::default()))
+        .unwrap();
+    map.device_mut::<Keyboard>().unwrap().key = Some(b'B');
+    assert_eq!(map.get_mem(0xc001), b'B');
+}
+
+#[test]
+fn test_layout_errors() {
+    // Overlap
+    assert!(MemoryMap::builder()
+        .region(Region::ram(0x0000, 0x2000))
+        .region(Region::rom(0x1fff, vec![0; 2]))
+        .build()
+        .is_err());
+    // Beyond the mirror mask and beyond 64K
+    assert!(MemoryMap::builder()
+        .region(Region::ram(0x4000, 0x100).mirror(0x3fff))
+        .build()
+        .is_err());
+    assert!(MemoryMap::builder()
+        .region(Region::ram(0xff00, 0x200))
+        .build()
+        .is_err());
+    // Mirror images of two regions landing on the same address
+    assert!(MemoryMap::builder()
+        .region(Region::ram(0x0000, 0x100).mirror(0x0fff))
+        .region(Region::ram(0x1000, 0x100).mirror(0x1fff))
+        .build()
+        .is_err());
+
+    // A region wins over a mirror image, and a failed add leaves the map alone
+    let mut map = MemoryMap::builder()
+        .region(Region::rom(0x0000, vec![0x11; 0x100]).mirror(0x7fff))
+        .build()
+        .unwrap();
+    map.add(Region::ram(0x8000, 0x100)).unwrap();
+    map.set_mem(0x8000, 0x22);
+    assert_eq!(map.get_mem(0x8000), 0x22);
+    assert!(map.add(Region::ram(0x8080, 0x100)).is_err());
+    assert_eq!(map.get_mem(0x8080), 0x00);
+    assert_eq!(map.get_mem(0x8180), 0xff);
+}
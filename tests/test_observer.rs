@@ -0,0 +1,208 @@
+mod common;
+
+use common::ConstIO;
+use space_invaders_8080::cpu::observer::{Access, BusCycle, BusObserver, Line, LineChange};
+use space_invaders_8080::cpu::symbols::SymbolTable;
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::Cpu;
+
+/// IN always reads 0x5a
+fn machine() -> BusObserver<FlatRam, ConstIO> {
+    let program = [
+        0x31, 0x00, 0x24, // 0000 LXI SP,2400h
+        0x3e, 0x42, // 0003 MVI A,42h
+        0x32, 0x00, 0x20, // 0005 STA 2000h
+        0xc5, // 0008 PUSH B
+        0xd3, 0x10, // 0009 OUT 10h
+        0xdb, 0x20, // 000b IN 20h
+        0xcd, 0x20, 0x00, // 000d CALL 0020h
+        0xfb, // 0010 EI
+        0x76, // 0011 HLT
+    ];
+    // RET at 0020
+    let mut cpu = Cpu::new(common::ram(&[(0x0000, &program), (0x0020, &[0xc9])]), 0);
+    cpu.register.set_bc(0x1234);
+    BusObserver::new(cpu, ConstIO(0x5a))
+}
+
+fn cycle(cycle: u64, access: Access, addr: u16, data: u8) -> BusCycle {
+    BusCycle {
+        cycle,
+        access,
+        addr,
+        data,
+    }
+}
+
+#[test]
+fn test_bus_cycles() {
+    use Access::*;
+    let mut observer = machine();
+    for _ in 0..8 {
+        observer.step();
+    }
+    let expected = [
+        // LXI SP: 10 cycles
+        cycle(0, Fetch, 0x0000, 0x31),
+        cycle(4, Read, 0x0001, 0x00),
+        cycle(7, Read, 0x0002, 0x24),
+        // MVI A: 7
+        cycle(10, Fetch, 0x0003, 0x3e),
+        cycle(14, Read, 0x0004, 0x42),
+        // STA: 13
+        cycle(17, Fetch, 0x0005, 0x32),
+        cycle(21, Read, 0x0006, 0x00),
+        cycle(24, Read, 0x0007, 0x20),
+        cycle(27, Write, 0x2000, 0x42),
+        // PUSH B: 11, M1 is 5 cycles, high byte first
+        cycle(30, Fetch, 0x0008, 0xc5),
+        cycle(35, StackWrite, 0x23ff, 0x12),
+        cycle(38, StackWrite, 0x23fe, 0x34),
+        // OUT: 10, the port is on both halves of the address
+        cycle(41, Fetch, 0x0009, 0xd3),
+        cycle(45, Read, 0x000a, 0x10),
+        cycle(48, Out, 0x1010, 0x42),
+        // IN: 10
+        cycle(51, Fetch, 0x000b, 0xdb),
+        cycle(55, Read, 0x000c, 0x20),
+        cycle(58, In, 0x2020, 0x5a),
+        // CALL: 17
+        cycle(61, Fetch, 0x000d, 0xcd),
+        cycle(66, Read, 0x000e, 0x20),
+        cycle(69, Read, 0x000f, 0x00),
+        cycle(72, StackWrite, 0x23fd, 0x00),
+        cycle(75, StackWrite, 0x23fc, 0x10),
+        // RET: 10
+        cycle(78, Fetch, 0x0020, 0xc9),
+        cycle(82, StackRead, 0x23fc, 0x10),
+        cycle(85, StackRead, 0x23fd, 0x00),
+    ];
+    assert_eq!(observer.bus_cycles(), expected);
+    assert_eq!(observer.cycles(), 88);
+}
+
+#[test]
+fn test_lines_and_interrupt() {
+    let mut observer = machine();
+    while !observer.cpu.is_halted() {
+        observer.step();
+    }
+    observer.step();
+    observer.clear();
+    observer.request_interrupt(&[0xd7]);
+    observer.step();
+    let at = |cycle, line, level| LineChange { cycle, line, level };
+    assert_eq!(
+        observer.line_changes(),
+        [
+            at(103, Line::Int, true),
+            at(114, Line::Int, false),
+            at(114, Line::Inte, false),
+            at(114, Line::Hlta, false),
+        ]
+    );
+    // The RST comes from the data bus, then PC is pushed
+    let bus = observer.bus_cycles();
+    assert_eq!(bus[0], cycle(103, Access::Inta, 0x0012, 0xd7));
+    assert_eq!(bus[1].access, Access::StackWrite);
+    assert_eq!(bus[1].addr, 0x23fd);
+    assert_eq!(bus[2].access, Access::StackWrite);
+    assert_eq!(observer.cpu.register.pc, 0x0010);
+
+    // RST from interrupt() is acknowledged the same way
+    let mut acked = machine();
+    acked.cpu.register.pc = 0x0010;
+    acked.step();
+    acked.step();
+    assert!(acked.interrupt(0xcf));
+    let bus = acked.bus_cycles();
+    assert_eq!(bus[bus.len() - 3].access, Access::Inta);
+    assert_eq!(bus[bus.len() - 3].data, 0xcf);
+    assert_eq!(acked.cpu.register.pc, 0x0008);
+}
+
+#[test]
+fn test_untouched_outside_step() {
+    let mut observer = machine();
+    observer.cpu.addring.get_mem(0x1234);
+    observer.cpu.addring.set_mem(0x2000, 1);
+    assert!(observer.bus_cycles().is_empty());
+    observer.step();
+    let (cpu, _) = observer.into_inner();
+    assert_eq!(cpu.register.sp, 0x2400);
+    assert_eq!(cpu.addring.get_mem(0x2000), 1);
+}
+
+#[test]
+fn test_csv() {
+    let mut observer = machine();
+    for _ in 0..3 {
+        observer.step();
+    }
+    let mut csv = Vec::new();
+    observer.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "cycle,access,addr,data,label");
+    assert_eq!(&lines[1..4], ["0,int,,0,", "0,inte,,0,", "0,hlta,,0,"]);
+    assert_eq!(lines[4], "0,fetch,0000,31,");
+    assert_eq!(lines.last(), Some(&"27,write,2000,42,"));
+}
+
+#[test]
+fn test_csv_labels() {
+    let mut observer = machine();
+    observer.set_symbols(SymbolTable::parse("0000 code start\n2000 data counter").unwrap());
+    for _ in 0..3 {
+        observer.step();
+    }
+    let mut csv = Vec::new();
+    observer.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    // Fetches in the routine, operands of the STA, then the variable it writes
+    assert_eq!(lines[4], "0,fetch,0000,31,start");
+    assert_eq!(
+        &lines[lines.len() - 4..],
+        [
+            "17,fetch,0005,32,start+5",
+            "21,read,0006,00,",
+            "24,read,0007,20,",
+            "27,write,2000,42,counter"
+        ]
+    );
+}
+
+#[test]
+fn test_vcd() {
+    let mut observer = machine();
+    while !observer.cpu.is_halted() {
+        observer.step();
+    }
+    let mut vcd = Vec::new();
+    observer.write_vcd(&mut vcd, 2_000_000).unwrap();
+    let vcd = String::from_utf8(vcd).unwrap();
+    assert!(vcd.contains("$var wire 16 A addr $end"));
+    assert!(vcd.contains("$var wire 1 l1 inte $end"));
+    assert!(vcd.contains("$enddefinitions $end"));
+    // STA at cycle 27: 13500 ns at 2 MHz
+    let sta = vcd.find("#13500\n").expect("no change at 13500 ns") + 7;
+    let changes: Vec<&str> = vcd[sta..]
+        .lines()
+        .take_while(|l| !l.starts_with('#'))
+        .collect();
+    // The read before it releases MEMR, the write raises MEMW
+    assert_eq!(
+        changes,
+        ["0s1", "b0010000000000000 A", "b01000010 D", "1s2"]
+    );
+    // Times go up
+    let times: Vec<u64> = vcd
+        .lines()
+        .filter_map(|l| l.strip_prefix('#'))
+        .map(|t| t.parse().unwrap())
+        .collect();
+    assert!(times.windows(2).all(|w| w[0] < w[1]));
+    // HLT raised HLTA at the end
+    assert!(vcd.trim_end().ends_with("1l2"));
+}
@@ -0,0 +1,244 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::NoIO;
+use space_invaders_8080::cpu::disasm::disassemble;
+use space_invaders_8080::cpu::profiler::{Profiler, Routine};
+use space_invaders_8080::cpu::HALT_CYCLES;
+use space_invaders_8080::game::invaders::driver::INVADERS;
+use space_invaders_8080::game::invaders::siaddressing::ROM_SIZE;
+use space_invaders_8080::game::invaders::{Board, InvadersAddressBus};
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::Cpu;
+
+fn machine() -> Cpu<FlatRam, NoIO> {
+    common::machine(&[
+        // LXI SP,0100h; CALL 0010h; CALL 0010h; EI; HLT
+        (
+            0x0000,
+            &[
+                0x31, 0x00, 0x01, 0xcd, 0x10, 0x00, 0xcd, 0x10, 0x00, 0xfb, 0x76,
+            ],
+        ),
+        // CALL 0020h; RET
+        (0x0010, &[0xcd, 0x20, 0x00, 0xc9]),
+        // NOP; RET
+        (0x0020, &[0x00, 0xc9]),
+        // RST 7 handler, RET
+        (0x0038, &[0xc9]),
+    ])
+}
+
+fn run_to_halt(profiler: &mut Profiler, cpu: &mut Cpu<FlatRam, NoIO>) {
+    while !cpu.is_halted() {
+        profiler.step(cpu, &mut NoIO);
+    }
+}
+
+#[test]
+fn test_routines() {
+    let mut cpu = machine();
+    let mut profiler = Profiler::new();
+    run_to_halt(&mut profiler, &mut cpu);
+
+    // NOP 4 + RET 10
+    let leaf = Routine {
+        calls: 2,
+        self_cycles: 28,
+        inclusive_cycles: 28,
+    };
+    assert_eq!(profiler.routine(0x0020), Some(leaf));
+    // CALL 17 + RET 10, plus the leaf
+    let middle = Routine {
+        calls: 2,
+        self_cycles: 54,
+        inclusive_cycles: 82,
+    };
+    assert_eq!(profiler.routine(0x0010), Some(middle));
+    // LXI 10, two CALLs 17, EI 4, HLT 7
+    let root = profiler.routine(0x0000).unwrap();
+    assert_eq!(root.calls, 0);
+    assert_eq!(root.self_cycles, 55);
+    assert_eq!(profiler.total_cycles(), 55 + 54 + 28);
+    assert_eq!(profiler.instructions(), 13);
+}
+
+#[test]
+fn test_stack_wrapping_at_zero() {
+    let mut cpu = machine();
+    // LXI SP,0: the first push goes to fffe, RET brings SP back to 0000
+    cpu.addring.set_word(0x0001, 0x0000);
+    let mut profiler = Profiler::new();
+    run_to_halt(&mut profiler, &mut cpu);
+    let middle = Routine {
+        calls: 2,
+        self_cycles: 54,
+        inclusive_cycles: 82,
+    };
+    assert_eq!(profiler.routine(0x0010), Some(middle));
+    assert_eq!(profiler.routine(0x0020).unwrap().inclusive_cycles, 28);
+    assert_eq!(profiler.routine(0x0000).unwrap().self_cycles, 55);
+}
+
+#[test]
+fn test_hits_and_halt() {
+    let mut cpu = machine();
+    let mut profiler = Profiler::new();
+    run_to_halt(&mut profiler, &mut cpu);
+    assert_eq!(profiler.hits(0x0010), 2);
+    assert_eq!(profiler.cycles(0x0010), 34);
+    assert_eq!(profiler.hits(0x0021), 2);
+    assert_eq!(profiler.hits(0x0038), 0);
+
+    // Halted steps are no hits, their cycles go to the HLT
+    for _ in 0..3 {
+        profiler.step(&mut cpu, &mut NoIO);
+    }
+    assert_eq!(profiler.hits(0x000a), 1);
+    assert_eq!(profiler.cycles(0x000a), 7 + 3 * u64::from(HALT_CYCLES));
+    assert_eq!(profiler.hits(0x000b), 0);
+
+    let executed: Vec<u16> = profiler.executed().collect();
+    assert_eq!(
+        executed,
+        [0x0000, 0x0003, 0x0006, 0x0009, 0x000a, 0x0010, 0x0013, 0x0020, 0x0021]
+    );
+    // LXI, CALL, CALL, EI, HLT: 11 bytes
+    assert_eq!(profiler.coverage(&cpu.addring, 0x0000, 0x0010), 11);
+}
+
+#[test]
+fn test_interrupt_between_steps() {
+    let mut cpu = machine();
+    let mut profiler = Profiler::new();
+    run_to_halt(&mut profiler, &mut cpu);
+    profiler.step(&mut cpu, &mut NoIO);
+    assert!(cpu.interrupt(0xff));
+    profiler.step(&mut cpu, &mut NoIO);
+    assert_eq!(cpu.register.pc, 0x000b);
+
+    let handler = profiler.routine(0x0038).unwrap();
+    assert_eq!(handler.calls, 1);
+    assert_eq!(handler.self_cycles, 10);
+    assert_eq!(handler.inclusive_cycles, 10);
+    assert_eq!(profiler.hits(0x0038), 1);
+}
+
+#[test]
+fn test_interrupts_on_board() {
+    // 0000: LXI SP,2400h; EI; JMP 0004
+    // 0008: INR B; EI; RET (RST 1)
+    // 0010: INR C; EI; RET (RST 2)
+    let mut rom = Box::new([0u8; ROM_SIZE]);
+    rom[..7].copy_from_slice(&[0x31, 0x00, 0x24, 0xfb, 0xc3, 0x04, 0x00]);
+    rom[0x08..0x0b].copy_from_slice(&[0x04, 0xfb, 0xc9]);
+    rom[0x10..0x13].copy_from_slice(&[0x0c, 0xfb, 0xc9]);
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let bus = InvadersAddressBus::new(vec![(0x0000, rom)], video).unwrap();
+    let mut board = Board::new(&INVADERS, bus);
+
+    let mut profiler = Profiler::new();
+    let mut cycles = 0;
+    for _ in 0..3 {
+        cycles += board.run_frame_with(|cpu, io| profiler.step(cpu, io));
+    }
+    assert_eq!(profiler.total_cycles(), cycles);
+    let rst1 = profiler.routine(0x0008).unwrap();
+    assert_eq!(rst1.calls, 3);
+    // INR 5, EI 4, RET 10
+    assert_eq!(rst1.inclusive_cycles, 3 * 19);
+    // The vblank interrupt ending the last frame is noticed at the next step
+    assert_eq!(profiler.routine(0x0010).unwrap().calls, 2);
+    board.run_frame_with(|cpu, io| profiler.step(cpu, io));
+    assert_eq!(profiler.routine(0x0010).unwrap().calls, 3);
+}
+
+#[test]
+fn test_annotate() {
+    let mut cpu = machine();
+    let mut profiler = Profiler::new();
+    run_to_halt(&mut profiler, &mut cpu);
+    let listing = profiler.annotate(&cpu.addring, 0x0010, 0x0014);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "          2           34  0010  cd 20 00  CALL $0020",
+            "          2           20  0013  c9        RET",
+        ]
+    );
+
+    // A jump into the middle of an instruction shows the bytes before it as data
+    let mut cpu = machine();
+    cpu.addring.set_mem(0x0040, 0x3e); // MVI A,...
+    cpu.addring.set_mem(0x0041, 0x00); // NOP, jumped to
+    cpu.register.pc = 0x0041;
+    profiler.step(&mut cpu, &mut NoIO);
+    let listing = profiler.annotate(&cpu.addring, 0x0040, 0x0042);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "                          0040  3e        DB $3e",
+            "          1            4  0041  00        NOP",
+        ]
+    );
+}
+
+#[test]
+fn test_disassemble() {
+    let program = [
+        0x3a, 0x67, 0x20, // LDA
+        0x21, 0x34, 0x12, // LXI H
+        0x7e, // MOV A,M
+        0xe6, 0x0f, // ANI
+        0xda, 0x00, 0x18, // JC
+        0xd7, // RST 2
+        0xd3, 0x06, // OUT
+        0xdd, 0x00, 0x00, // undocumented CALL
+        0x08, // undocumented NOP
+        0xf5, // PUSH PSW
+    ];
+    let memory = common::ram(&[(0x1a5c, &program)]);
+    let mut addr = 0x1a5c;
+    let mut lines = Vec::new();
+    let mut targets = Vec::new();
+    for _ in 0..10 {
+        let instruction = disassemble(&memory, addr);
+        addr += instruction.size();
+        lines.push(instruction.to_string());
+        targets.push(instruction.target);
+    }
+    assert_eq!(
+        lines,
+        [
+            "1a5c  3a 67 20  LDA $2067",
+            "1a5f  21 34 12  LXI H,$1234",
+            "1a62  7e        MOV A,M",
+            "1a63  e6 0f     ANI $0f",
+            "1a65  da 00 18  JC $1800",
+            "1a68  d7        RST 2",
+            "1a69  d3 06     OUT $06",
+            "1a6b  dd 00 00  *CALL $0000",
+            "1a6e  08        *NOP",
+            "1a6f  f5        PUSH PSW",
+        ]
+    );
+    assert_eq!(
+        targets,
+        [
+            Some(0x2067),
+            Some(0x1234),
+            None,
+            None,
+            Some(0x1800),
+            Some(0x0010),
+            None,
+            Some(0x0000),
+            None,
+            None
+        ]
+    );
+}
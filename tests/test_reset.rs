@@ -0,0 +1,89 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::cpu::IO;
+use space_invaders_8080::game::invaders::driver::INVADERS;
+use space_invaders_8080::game::invaders::siaddressing::ROM_SIZE;
+use space_invaders_8080::game::invaders::{Board, InvadersAddressBus};
+use space_invaders_8080::machine::{Altair, BufferTerminal};
+use space_invaders_8080::memory::AddressBus;
+use space_invaders_8080::{DynCpu, Register, TestAddressing};
+
+struct TestIO;
+
+impl IO for TestIO {
+    fn input(&mut self, _cpu: &mut Register, _byte: u8) {}
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+#[test]
+fn test_cpu_reset() {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    // EI; MVI A,42H
+    mem.borrow_mut()[..3].copy_from_slice(&[0xfb, 0x3e, 0x42]);
+    let io = Rc::new(RefCell::new(TestIO));
+    let mut cpu = DynCpu::new(Box::new(TestAddressing::new(mem.clone())), 0, io);
+    cpu.next();
+    cpu.next();
+    assert!(cpu.interrupts_enabled());
+
+    cpu.reset();
+    assert_eq!(cpu.register.pc, 0);
+    assert!(!cpu.interrupts_enabled());
+    assert!(!cpu.interrupt(0xcf));
+    assert_eq!(cpu.register.a, 0x42);
+
+    cpu.power_on();
+    assert_eq!(cpu.register.a, 0);
+    cpu.addring.fill_ram(&[0xaa, 0x55]);
+    assert_eq!(&mem.borrow()[0xfffe..], &[0xaa, 0x55]);
+}
+
+/// Altair running `program` from a freshly powered machine
+fn altair(program: &[u8]) -> Altair<BufferTerminal> {
+    let mut altair = Altair::new(BufferTerminal::new(b""));
+    altair.power_cycle(&[0x00]);
+    altair.load_binary(program, 0x0000);
+    altair
+}
+
+#[test]
+fn test_altair_reset_and_power_cycle() {
+    // MVI A,'A'; OUT 11H; STA 0100H; JMP 0007
+    let mut altair = altair(&[0x3e, 0x41, 0xd3, 0x11, 0x32, 0x00, 0x01, 0xc3, 0x07, 0x00]);
+    altair.run(100);
+    assert_eq!(altair.sio().terminal().output, b"A");
+
+    // Soft reset runs the program again from RAM
+    altair.reset();
+    altair.run(100);
+    assert_eq!(altair.sio().terminal().output, b"AA");
+
+    altair.power_cycle(&[0xff]);
+    assert_eq!(altair.cpu.register.pc, 0);
+    assert_eq!(altair.cpu.register.a, 0);
+    assert_eq!(altair.cpu.addring.get_mem(0x0100), 0xff);
+    assert_eq!(altair.cpu.addring.get_mem(0x0000), 0xff);
+}
+
+#[test]
+fn test_board_power_cycle() {
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let rom = Box::new([0x76u8; ROM_SIZE]);
+    let bus = InvadersAddressBus::new(vec![(0x0000, rom)], video.clone()).unwrap();
+    let mut board = Board::new(&INVADERS, bus);
+    board.cpu.addring.set_mem(0x2000, 0x12);
+    board.cpu.register.pc = 0x1234;
+
+    board.reset();
+    assert_eq!(board.cpu.register.pc, 0);
+    assert_eq!(board.cpu.addring.get_mem(0x2000), 0x12);
+
+    board.power_cycle(&[0x00, 0xff]);
+    assert_eq!(board.cpu.addring.get_mem(0x2000), 0x00);
+    assert_eq!(board.cpu.addring.get_mem(0x2001), 0xff);
+    assert_eq!(video.borrow()[1], 0xff);
+    // ROM is untouched
+    assert_eq!(board.cpu.addring.get_mem(0x0000), 0x76);
+}
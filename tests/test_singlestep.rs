@@ -0,0 +1,273 @@
+//! Single-step test vectors in the community JSON format: per test an initial state, a
+//! final state, the RAM contents around them and the bus cycles of the instruction. Each
+//! test sets up `Register` and `TestAddressing`, runs one step under a `BusObserver` and
+//! compares registers, flags, INTE, memory, cycles and port accesses. When the vector
+//! lists its T-states, the memory and IO accesses they show are compared in order with
+//! the ones the CPU made, addresses and data included.
+//!
+//! `tests/singlestep/` holds a hand-checked set of vectors per instruction group, among
+//! them the worked examples of the 8080 manual that `test_instr.rs` used to run as
+//! programs. CI runs them with the other tests. The full suite (one `xx.json` per
+//! opcode) is not vendored; it has to be downloaded, then:
+//! ```text
+//! SINGLESTEP=path/to/8080/v1 cargo test --release --test test_singlestep -- --ignored
+//! ```
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use serde::Deserialize;
+
+use space_invaders_8080::cpu::observer::{Access, BusObserver};
+use space_invaders_8080::cpu::IO;
+use space_invaders_8080::{Cpu, Register, TestAddressing};
+
+#[derive(Deserialize)]
+struct Test {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Cycles,
+    #[serde(default)]
+    ports: Vec<(u8, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    #[serde(default, alias = "iff1", alias = "ie")]
+    inte: Option<u8>,
+    ram: Vec<(u16, u8)>,
+}
+
+/// One entry per T-state `[addr, data, pins]`, or just their number. The pins are
+/// `r`, `w`, `m` (memory) and `i` (IO), `-` when inactive, e.g. `r-m-`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Cycles {
+    Count(u32),
+    Bus(Vec<(u16, Option<u8>, String)>),
+}
+
+/// What a bus cycle does, whatever the CPU calls it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    MemRead,
+    MemWrite,
+    In,
+    Out,
+}
+
+impl Cycles {
+    fn count(&self) -> u32 {
+        match self {
+            Cycles::Count(n) => *n,
+            Cycles::Bus(cycles) => cycles.len() as u32,
+        }
+    }
+
+    /// The transfers of the T-states, one per run of states with the same pins and
+    /// address. Data missing from the vector is not compared.
+    fn transfers(&self) -> Option<Vec<(Transfer, u16, Option<u8>)>> {
+        let Cycles::Bus(cycles) = self else {
+            return None;
+        };
+        let mut transfers: Vec<(Transfer, u16, Option<u8>)> = Vec::new();
+        let mut last = None;
+        for (addr, data, pins) in cycles {
+            let transfer = match (pins.contains('r'), pins.contains('w'), pins.contains('i')) {
+                (true, _, false) => Some(Transfer::MemRead),
+                (_, true, false) => Some(Transfer::MemWrite),
+                (true, _, true) => Some(Transfer::In),
+                (_, true, true) => Some(Transfer::Out),
+                _ => None,
+            };
+            match (transfer, transfers.last_mut()) {
+                (Some(t), Some(prev)) if last == Some((t, *addr)) => {
+                    prev.2 = data.or(prev.2);
+                }
+                (Some(t), _) => transfers.push((t, *addr, *data)),
+                _ => {}
+            }
+            last = transfer.map(|t| (t, *addr));
+        }
+        Some(transfers)
+    }
+}
+
+/// Answers IN from the test's `r` entries, records OUT
+#[derive(Default)]
+struct Ports {
+    reads: Vec<(u8, u8)>,
+    writes: Vec<(u8, u8)>,
+}
+
+impl IO for Ports {
+    fn input(&mut self, cpu: &mut Register, byte: u8) {
+        let i = self.reads.iter().position(|(port, _)| *port == byte);
+        cpu.a = i.map_or(0xff, |i| self.reads.remove(i).1);
+    }
+
+    fn output(&mut self, cpu: &mut Register, byte: u8) {
+        self.writes.push((byte, cpu.a));
+    }
+}
+
+/// Run one test, returns what differs
+fn run(test: &Test) -> Vec<String> {
+    let mem = Rc::new(RefCell::new(vec![0u8; 65536]));
+    for &(addr, val) in &test.initial.ram {
+        mem.borrow_mut()[addr as usize] = val;
+    }
+    let mut ports = Ports::default();
+    for (port, val, dir) in &test.ports {
+        if dir == "r" {
+            ports.reads.push((*port, *val));
+        }
+    }
+    let init = &test.initial;
+    let mut cpu = Cpu::new(TestAddressing::new(mem.clone()), init.pc);
+    let r = &mut cpu.register;
+    (r.sp, r.a, r.b, r.c, r.d) = (init.sp, init.a, init.b, init.c, init.d);
+    (r.e, r.h, r.l) = (init.e, init.h, init.l);
+    r.set_flags(init.f);
+    if let Some(inte) = init.inte {
+        cpu.set_interrupts_enabled(inte != 0);
+    }
+
+    let mut observer = BusObserver::new(cpu, ports);
+    let cycles = u32::from(observer.step());
+    let bus = observer.bus_cycles();
+    let (cpu, ports) = observer.into_inner();
+
+    let (r, want) = (&cpu.register, &test.expected);
+    let mut diffs = Vec::new();
+    let bytes = [
+        ("a", r.a, want.a),
+        ("b", r.b, want.b),
+        ("c", r.c, want.c),
+        ("d", r.d, want.d),
+        ("e", r.e, want.e),
+        ("h", r.h, want.h),
+        ("l", r.l, want.l),
+        ("f", r.get_flags(), want.f),
+    ];
+    for (name, got, want) in bytes {
+        if got != want {
+            diffs.push(format!("{}: {:02x}, want {:02x}", name, got, want));
+        }
+    }
+    for (name, got, want) in [("pc", r.pc, want.pc), ("sp", r.sp, want.sp)] {
+        if got != want {
+            diffs.push(format!("{}: {:04x}, want {:04x}", name, got, want));
+        }
+    }
+    if let Some(inte) = want.inte {
+        if cpu.interrupts_enabled() != (inte != 0) {
+            diffs.push(format!("inte: {}, want {}", cpu.interrupts_enabled(), inte));
+        }
+    }
+    for &(addr, val) in &want.ram {
+        let got = mem.borrow()[addr as usize];
+        if got != val {
+            diffs.push(format!("[{:04x}]: {:02x}, want {:02x}", addr, got, val));
+        }
+    }
+    if cycles != test.cycles.count() {
+        diffs.push(format!("cycles: {}, want {}", cycles, test.cycles.count()));
+    }
+    let writes: Vec<(u8, u8)> = test
+        .ports
+        .iter()
+        .filter(|(_, _, dir)| dir == "w")
+        .map(|(port, val, _)| (*port, *val))
+        .collect();
+    if ports.writes != writes {
+        diffs.push(format!("out: {:02x?}, want {:02x?}", ports.writes, writes));
+    }
+    if let Some(want) = test.cycles.transfers() {
+        let got: Vec<(Transfer, u16, u8)> = bus
+            .iter()
+            .filter_map(|c| {
+                let transfer = match c.access {
+                    Access::Fetch | Access::Read | Access::StackRead => Transfer::MemRead,
+                    Access::Write | Access::StackWrite => Transfer::MemWrite,
+                    Access::In => Transfer::In,
+                    Access::Out => Transfer::Out,
+                    Access::Inta => return None,
+                };
+                Some((transfer, c.addr, c.data))
+            })
+            .collect();
+        let same = got.len() == want.len()
+            && got.iter().zip(&want).all(|(got, want)| {
+                got.0 == want.0 && got.1 == want.1 && want.2.is_none_or(|data| data == got.2)
+            });
+        if !same {
+            diffs.push(format!("bus: {:04x?}, want {:04x?}", got, want));
+        }
+    }
+    diffs
+}
+
+/// Run every test of a file, returns (tests, failure reports)
+fn run_file(path: &Path) -> (usize, Vec<String>) {
+    let text = fs::read_to_string(path).unwrap();
+    let tests: Vec<Test> =
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    let failures = tests
+        .iter()
+        .filter_map(|test| {
+            let diffs = run(test);
+            (!diffs.is_empty()).then(|| format!("{}: {}", test.name, diffs.join(", ")))
+        })
+        .collect();
+    (tests.len(), failures)
+}
+
+#[test]
+fn test_vectors() {
+    let path = Path::new("tests/singlestep/8080.json");
+    let (count, failures) = run_file(path);
+    assert!(count > 0);
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore]
+fn test_singlestep_suite() {
+    let dir = std::env::var("SINGLESTEP").expect("SINGLESTEP not set");
+    let (mut total, mut failed) = (0, Vec::new());
+    for op in 0..=255u8 {
+        let path = Path::new(&dir).join(format!("{:02x}.json", op));
+        if !path.exists() {
+            continue;
+        }
+        let (count, failures) = run_file(&path);
+        total += count;
+        if !failures.is_empty() {
+            // The first failure shows what is wrong, the count how often
+            failed.push(format!(
+                "{:02x}: {} of {}, {}",
+                op,
+                failures.len(),
+                count,
+                failures[0]
+            ));
+        }
+    }
+    assert!(total > 0, "no xx.json in {}", dir);
+    assert!(failed.is_empty(), "\n{}", failed.join("\n"));
+}
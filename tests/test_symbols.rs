@@ -0,0 +1,236 @@
+mod common;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use common::NoIO;
+use space_invaders_8080::cpu::disasm::{disassemble, listing};
+use space_invaders_8080::cpu::profiler::Profiler;
+use space_invaders_8080::cpu::symbols::{Symbol, SymbolKind, SymbolTable};
+use space_invaders_8080::game::invaders::driver::{Input, INVADERS};
+use space_invaders_8080::game::invaders::{builtin_symbols, load_roms, Board};
+use space_invaders_8080::memory::{AddressBus, FlatRam};
+use space_invaders_8080::Cpu;
+
+const SYMBOLS: &str = "\
+; Test program
+0000 code start
+0010 code Delay        ; Count A down
+0020 data table        ; Three bytes
+0023 code after
+2000 data counter
+";
+
+fn program() -> FlatRam {
+    common::ram(&[
+        // LXI SP,0100h; MVI A,3; CALL Delay; STA counter; HLT
+        (
+            0x0000,
+            &[
+                0x31, 0x00, 0x01, 0x3e, 0x03, 0xcd, 0x10, 0x00, 0x32, 0x00, 0x20, 0x76,
+            ],
+        ),
+        // DCR A; JNZ 0010h; RET
+        (0x0010, &[0x3d, 0xc2, 0x10, 0x00, 0xc9]),
+        // table, then NOP
+        (0x0020, &[0xc3, 0x01, 0x02, 0x00]),
+    ])
+}
+
+#[test]
+fn test_parse() {
+    let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+    assert_eq!(symbols.len(), 5);
+    assert_eq!(
+        symbols.get(0x0010),
+        Some(&Symbol {
+            addr: 0x0010,
+            kind: SymbolKind::Code,
+            name: "Delay".to_string(),
+            comment: Some("Count A down".to_string()),
+        })
+    );
+    assert_eq!(symbols.get(0x2000).unwrap().kind, SymbolKind::Data);
+    assert_eq!(symbols.get(0x2000).unwrap().comment, None);
+    assert_eq!(symbols.name(0x0023), Some("after"));
+    assert_eq!(symbols.find("table").map(|s| s.addr), Some(0x0020));
+    assert_eq!(symbols.next_after(0x0010), Some(0x0020));
+    assert_eq!(symbols.next_after(0x2000), None);
+
+    // The closest code symbol below, data symbols are skipped
+    assert_eq!(symbols.locate(0x0010).as_deref(), Some("Delay"));
+    assert_eq!(symbols.locate(0x0014).as_deref(), Some("Delay+4"));
+    assert_eq!(symbols.locate(0x0021).as_deref(), Some("Delay+17"));
+    assert_eq!(symbols.locate(0x2001).as_deref(), Some("after+8158"));
+
+    // A later symbol at the same address wins, also when merging
+    let mut symbols = SymbolTable::parse("0010 code Wait\n0010 code Delay").unwrap();
+    assert_eq!(symbols.name(0x0010), Some("Delay"));
+    symbols.merge(SymbolTable::parse("0010 data Pause ; renamed").unwrap());
+    assert_eq!(symbols.name(0x0010), Some("Pause"));
+    assert_eq!(symbols.len(), 1);
+}
+
+#[test]
+fn test_parse_errors() {
+    for (text, line) in [
+        ("0000 code start\nzz code bad", 2),
+        ("0000 function start", 1),
+        ("; fine\n\n0000 code", 3),
+        ("0000 code two names", 1),
+        ("10000 code big", 1),
+    ] {
+        let err = SymbolTable::parse(text).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(
+            err.to_string().contains(&format!("line {}", line)),
+            "{}: {}",
+            text,
+            err
+        );
+    }
+}
+
+#[test]
+fn test_load() {
+    let path = std::env::temp_dir().join(format!("si8080-symbols-{}.sym", std::process::id()));
+    std::fs::write(&path, SYMBOLS).unwrap();
+    let symbols = SymbolTable::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(symbols, SymbolTable::parse(SYMBOLS).unwrap());
+}
+
+#[test]
+fn test_listing() {
+    let memory = program();
+    let symbols = SymbolTable::parse(SYMBOLS).unwrap();
+    let instruction = disassemble(&memory, 0x0008);
+    assert_eq!(instruction.labelled(&symbols), "STA counter");
+    assert_eq!(instruction.labelled(&SymbolTable::new()), "STA $2000");
+
+    let text = listing(&memory, 0x0010, 0x0024, &symbols);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Delay:  ; Count A down",
+            "0010  3d        DCR A",
+            "0011  c2 10 00  JNZ Delay",
+            "0014  c9        RET",
+            "0015  00        NOP",
+            "0016  00        NOP",
+            "0017  00        NOP",
+            "0018  00        NOP",
+            "0019  00        NOP",
+            "001a  00        NOP",
+            "001b  00        NOP",
+            "001c  00        NOP",
+            "001d  00        NOP",
+            "001e  00        NOP",
+            "001f  00        NOP",
+            "table:  ; Three bytes",
+            "0020  c3 01 02  DB $c3,$01,$02",
+            "after:",
+            "0023  00        NOP",
+        ]
+    );
+
+    // An instruction running into a symbol stops there
+    let text = listing(&memory, 0x0005, 0x000a, &symbols);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        ["0005  cd 10 00  CALL Delay", "0008  32 00 20  STA counter"]
+    );
+    let mut symbols = symbols;
+    symbols.insert(Symbol {
+        addr: 0x0009,
+        kind: SymbolKind::Data,
+        name: "operand".to_string(),
+        comment: None,
+    });
+    let text = listing(&memory, 0x0008, 0x000b, &symbols);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "0008  32        DB $32",
+            "operand:",
+            "0009  00 20     DB $00,$20"
+        ]
+    );
+}
+
+#[test]
+fn test_profiler_names() {
+    let mut cpu = Cpu::new(program(), 0);
+    let mut profiler = Profiler::new();
+    profiler.set_symbols(SymbolTable::parse(SYMBOLS).unwrap());
+    while !cpu.is_halted() {
+        profiler.step(&mut cpu, &mut NoIO);
+    }
+    let report = profiler.report(3);
+    // DCR A and JNZ run 3 times each
+    assert!(
+        report.contains(" 0011           3           30  "),
+        "{}",
+        report
+    );
+    assert!(report.contains("Delay+1\n"), "{}", report);
+    assert!(report.contains("  Delay\n"), "{}", report);
+
+    let text = profiler.annotate(&cpu.addring, 0x0010, 0x0015);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "Delay:  ; Count A down",
+            "          3           15  0010  3d        DCR A",
+            "          3           30  0011  c2 10 00  JNZ Delay",
+            "          1           10  0014  c9        RET",
+        ]
+    );
+}
+
+#[test]
+fn test_builtin_invaders() {
+    let symbols = builtin_symbols("invaders").unwrap();
+    assert!(builtin_symbols("lrescue").is_none());
+    let names: HashSet<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names.len(), symbols.len());
+    assert_eq!(symbols.name(0x0010), Some("ScanLine224"));
+    assert_eq!(symbols.find("DrawChar").unwrap().addr, 0x08ff);
+    // Code symbols are in ROM, data symbols in ROM tables or RAM
+    for symbol in symbols.iter() {
+        if symbol.kind == SymbolKind::Code {
+            assert!(symbol.addr < 0x2000, "{:?}", symbol);
+        }
+    }
+
+    // The RAM variables hold what their names say
+    let (bus, _) = load_roms(&INVADERS, Rc::new(RefCell::new(vec![0u8; 7168]))).unwrap();
+    let mut board = Board::new(&INVADERS, bus);
+    let peek =
+        |board: &Board, name: &str| board.cpu.addring.get_mem(symbols.find(name).unwrap().addr);
+    let run = |board: &mut Board, inputs: Vec<Input>, frames: usize| {
+        board.set_inputs(inputs);
+        for _ in 0..frames {
+            board.run_frame();
+        }
+    };
+    run(&mut board, vec![], 200);
+    assert_eq!(peek(&board, "gameMode"), 0);
+    run(&mut board, vec![Input::Coin], 10);
+    run(&mut board, vec![], 30);
+    assert_eq!(peek(&board, "numCoins"), 1);
+    run(&mut board, vec![Input::Start1], 10);
+    run(&mut board, vec![], 300);
+    assert_eq!(peek(&board, "numCoins"), 0);
+    assert_eq!(peek(&board, "gameMode"), 1);
+    assert_eq!(peek(&board, "numAliens"), 55);
+    assert_eq!(peek(&board, "playerDataMSB"), 0x21);
+    let x = peek(&board, "playerXr");
+    run(&mut board, vec![Input::Right1], 30);
+    assert!(peek(&board, "playerXr") > x);
+}
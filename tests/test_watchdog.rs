@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use space_invaders_8080::device::{Latch, Watchdog, WATCHDOG_FRAMES};
+use space_invaders_8080::game::invaders::driver::INVADERS;
+use space_invaders_8080::game::invaders::siaddressing::ROM_SIZE;
+use space_invaders_8080::game::invaders::{Board, InvadersAddressBus};
+
+fn board(program: &[u8]) -> Board {
+    let mut rom = Box::new([0u8; ROM_SIZE]);
+    rom[..program.len()].copy_from_slice(program);
+    let video = Rc::new(RefCell::new(vec![0u8; 7168]));
+    let bus = InvadersAddressBus::new(vec![(0x0000, rom)], video).unwrap();
+    Board::new(&INVADERS, bus)
+}
+
+/// Run a few instructions, then end the frame
+fn frame(board: &mut Board) -> bool {
+    for _ in 0..10 {
+        board.step();
+    }
+    board.end_frame()
+}
+
+#[test]
+fn test_watchdog_device() {
+    let mut watchdog = Watchdog::new(3);
+    assert!(!watchdog.tick());
+    watchdog.kick();
+    assert!(!watchdog.tick());
+    assert!(!watchdog.tick());
+    assert!(watchdog.tick());
+    // Starts over after firing
+    assert!(!watchdog.tick());
+    watchdog.set_enabled(false);
+    assert!((0..10).all(|_| !watchdog.tick()));
+}
+
+#[test]
+fn test_board_reset_on_timeout() {
+    // 0000: JMP 0010; 0010: INR B; JMP 0010
+    let mut board = board(&[
+        0xc3, 0x10, 0x00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x04, 0xc3, 0x10, 0x00,
+    ]);
+    for _ in 1..WATCHDOG_FRAMES {
+        assert!(!frame(&mut board));
+    }
+    assert!(frame(&mut board));
+    assert_eq!(board.cpu.register.pc, 0x0000);
+    // RAM and registers survive a reset
+    assert_ne!(board.cpu.register.b, 0);
+}
+
+#[test]
+fn test_board_kicked_or_disabled() {
+    // OUT 6; JMP 0000
+    let mut kicked = board(&[0xd3, 0x06, 0xc3, 0x00, 0x00]);
+    assert!((0..WATCHDOG_FRAMES * 2).all(|_| !frame(&mut kicked)));
+
+    let mut disabled = board(&[0xc3, 0x00, 0x00]);
+    disabled.set_watchdog(false);
+    assert!((0..WATCHDOG_FRAMES * 2).all(|_| !frame(&mut disabled)));
+    assert!(!disabled.io().device::<Watchdog>().unwrap().is_enabled());
+}
+
+#[test]
+fn test_board_reset_clears_ports() {
+    // MVI A,20; OUT 5; JMP 0004
+    let mut board = board(&[0x3e, 0x20, 0xd3, 0x05, 0xc3, 0x04, 0x00]);
+    for _ in 1..WATCHDOG_FRAMES {
+        assert!(!frame(&mut board));
+        assert_eq!(board.io().device::<Latch>().unwrap().get(5), 0x20);
+    }
+    assert!(frame(&mut board));
+    // The sound latch is cleared along with the CPU, the watchdog stays armed
+    assert_eq!(board.io().device::<Latch>().unwrap().get(5), 0x00);
+    assert!(board.io().device::<Watchdog>().unwrap().is_enabled());
+}
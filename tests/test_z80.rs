@@ -0,0 +1,286 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::rc::Rc;
+
+use space_invaders_8080::cpu::{Variant, IO};
+use space_invaders_8080::machine::cpm::BIOS_BASE_64K;
+use space_invaders_8080::machine::{BufferTerminal, Cpm};
+use space_invaders_8080::{DynCpu, Register, TestAddressing};
+
+/// IN returns the port number, OUT is ignored
+struct TestIO;
+
+impl IO for TestIO {
+    fn input(&mut self, cpu: &mut Register, byte: u8) {
+        cpu.a = byte;
+    }
+
+    fn output(&mut self, _cpu: &mut Register, _byte: u8) {}
+}
+
+fn test_cpu(program: &[u8]) -> DynCpu {
+    let mem = Rc::new(RefCell::new([0u8; 65536].to_vec()));
+    mem.borrow_mut()[..program.len()].copy_from_slice(program);
+    let mut cpu = DynCpu::with_variant(
+        Box::new(TestAddressing::new(mem)),
+        0,
+        Rc::new(RefCell::new(TestIO)),
+        Variant::Z80,
+    );
+    cpu.register.sp = 0x8000;
+    cpu
+}
+
+#[test]
+fn test_index_registers() {
+    // LD IX,1234h; LD A,(IX+5); LD (IY-2),A with IY=2002h; LD IXH,A
+    let mut cpu = test_cpu(&[
+        0xdd, 0x21, 0x34, 0x12, 0xdd, 0x7e, 0x05, 0xfd, 0x77, 0xfe, 0xdd, 0x67,
+    ]);
+    cpu.addring.set_mem(0x1239, 0x42);
+    cpu.register.iy = 0x2002;
+    assert_eq!(cpu.next(), 14);
+    assert_eq!(cpu.register.ix, 0x1234);
+    assert_eq!(cpu.next(), 19);
+    assert_eq!(cpu.register.a, 0x42);
+    assert_eq!(cpu.next(), 19);
+    assert_eq!(cpu.addring.get_mem(0x2000), 0x42);
+    assert_eq!(cpu.next(), 8);
+    assert_eq!(cpu.register.ix, 0x4234);
+    assert_eq!(cpu.register.get_hl(), 0);
+}
+
+#[test]
+fn test_alu_flags() {
+    // LD A,7fh; ADD A,1; SUB 81h; NEG
+    let mut cpu = test_cpu(&[0x3e, 0x7f, 0xc6, 0x01, 0xd6, 0x81, 0xed, 0x44]);
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.register.a, 0x80);
+    // S H V
+    assert_eq!(cpu.z80_flags(), 0x94);
+    cpu.next();
+    // 80h - 81h: S Y H X N C
+    assert_eq!(cpu.register.a, 0xff);
+    assert_eq!(cpu.z80_flags(), 0xbb);
+    assert_eq!(cpu.next(), 8);
+    assert_eq!(cpu.register.a, 0x01);
+    assert_eq!(cpu.z80_flags(), 0x13);
+}
+
+#[test]
+fn test_daa() {
+    // LD A,15h; ADD A,27h; DAA; SUB 13h; DAA
+    let mut cpu = test_cpu(&[0x3e, 0x15, 0xc6, 0x27, 0x27, 0xd6, 0x13, 0x27]);
+    cpu.next();
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.register.a, 0x42);
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.register.a, 0x29);
+    assert!(cpu.register.flag_n);
+}
+
+#[test]
+fn test_16bit_arithmetic() {
+    // SBC HL,DE; ADC HL,DE
+    let mut cpu = test_cpu(&[0xed, 0x52, 0xed, 0x5a]);
+    cpu.register.set_hl(0x1000);
+    cpu.register.set_de(0x0001);
+    assert_eq!(cpu.next(), 15);
+    assert_eq!(cpu.register.get_hl(), 0x0fff);
+    // H X N
+    assert_eq!(cpu.z80_flags(), 0x1a);
+    cpu.register.set_de(0xf001);
+    cpu.next();
+    assert_eq!(cpu.register.get_hl(), 0x0000);
+    assert!(cpu.register.flag_z && cpu.register.flag_cy);
+}
+
+#[test]
+fn test_exchange() {
+    // EX AF,AF'; EXX; EX DE,HL
+    let mut cpu = test_cpu(&[0x08, 0xd9, 0xeb]);
+    cpu.register.a = 0x12;
+    cpu.register.af_alt = 0x34ff;
+    cpu.register.set_bc(0x1111);
+    cpu.register.hl_alt = 0x2222;
+    cpu.register.set_de(0x3333);
+    cpu.next();
+    assert_eq!(cpu.register.a, 0x34);
+    assert_eq!(cpu.z80_flags(), 0xff);
+    assert_eq!(cpu.register.af_alt >> 8, 0x12);
+    cpu.next();
+    assert_eq!(cpu.register.bc_alt, 0x1111);
+    assert_eq!(cpu.register.de_alt, 0x3333);
+    assert_eq!(cpu.register.get_hl(), 0x2222);
+    cpu.next();
+    assert_eq!(cpu.register.get_de(), 0x2222);
+}
+
+#[test]
+fn test_relative_jumps() {
+    // LD B,3; DJNZ -2; JR +2; HALT; HALT; JR NZ,-3
+    let mut cpu = test_cpu(&[0x06, 0x03, 0x10, 0xfe, 0x18, 0x02, 0x76, 0x76, 0x20, 0xfd]);
+    cpu.next();
+    assert_eq!(cpu.next(), 13);
+    assert_eq!(cpu.next(), 13);
+    assert_eq!(cpu.next(), 8);
+    assert_eq!(cpu.register.pc, 0x0004);
+    assert_eq!(cpu.next(), 12);
+    assert_eq!(cpu.register.pc, 0x0008);
+    cpu.register.flag_z = false;
+    assert_eq!(cpu.next(), 12);
+    assert_eq!(cpu.register.pc, 0x0007);
+}
+
+#[test]
+fn test_block_instructions() {
+    // LDIR; CPIR
+    let mut cpu = test_cpu(&[0xed, 0xb0, 0xed, 0xb1]);
+    for (i, byte) in [0x11, 0x22, 0x33].iter().enumerate() {
+        cpu.addring.set_mem(0x4000 + i as u16, *byte);
+    }
+    cpu.register.set_hl(0x4000);
+    cpu.register.set_de(0x5000);
+    cpu.register.set_bc(3);
+    assert_eq!(cpu.next(), 21);
+    assert_eq!(cpu.next(), 21);
+    assert_eq!(cpu.next(), 16);
+    assert_eq!(cpu.addring.get_mem(0x5002), 0x33);
+    assert_eq!(cpu.register.get_bc(), 0);
+    assert!(!cpu.register.flag_p);
+
+    cpu.register.set_hl(0x4000);
+    cpu.register.set_bc(3);
+    cpu.register.a = 0x22;
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0004);
+    assert_eq!(cpu.register.get_hl(), 0x4002);
+    assert!(cpu.register.flag_z);
+    assert!(cpu.register.flag_p);
+}
+
+#[test]
+fn test_bit_instructions() {
+    // BIT 7,(IX+1); SET 0,(IX+1),B; RLC C; RLD
+    let mut cpu = test_cpu(&[
+        0xdd, 0xcb, 0x01, 0x7e, 0xdd, 0xcb, 0x01, 0xc0, 0xcb, 0x01, 0xed, 0x6f,
+    ]);
+    cpu.register.ix = 0x3000;
+    cpu.addring.set_mem(0x3001, 0x80);
+    assert_eq!(cpu.next(), 20);
+    assert!(!cpu.register.flag_z && cpu.register.flag_s);
+    assert_eq!(cpu.next(), 23);
+    assert_eq!(cpu.addring.get_mem(0x3001), 0x81);
+    assert_eq!(cpu.register.b, 0x81);
+    cpu.register.c = 0x80;
+    assert_eq!(cpu.next(), 8);
+    assert_eq!(cpu.register.c, 0x01);
+    assert!(cpu.register.flag_cy);
+
+    cpu.register.a = 0x12;
+    cpu.register.set_hl(0x3001);
+    cpu.addring.set_mem(0x3001, 0x34);
+    assert_eq!(cpu.next(), 18);
+    assert_eq!(cpu.register.a, 0x13);
+    assert_eq!(cpu.addring.get_mem(0x3001), 0x42);
+}
+
+#[test]
+fn test_port_io() {
+    // LD C,80h; IN B,(C); IN A,(7)
+    let mut cpu = test_cpu(&[0x0e, 0x80, 0xed, 0x40, 0xdb, 0x07]);
+    cpu.next();
+    assert_eq!(cpu.next(), 12);
+    assert_eq!(cpu.register.b, 0x80);
+    assert!(cpu.register.flag_s);
+    assert_eq!(cpu.next(), 11);
+    assert_eq!(cpu.register.a, 0x07);
+}
+
+#[test]
+fn test_interrupt_modes() {
+    // IM 1; EI; NOP; NOP
+    let mut cpu = test_cpu(&[0xed, 0x56, 0xfb, 0x00, 0x00]);
+    cpu.next();
+    assert_eq!(cpu.interrupt_mode(), 1);
+    cpu.request_interrupt(&[0xff]);
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.next(), 13);
+    assert_eq!(cpu.register.pc, 0x0038);
+
+    // IM 2; EI; NOP; NOP, the device supplies the low byte of the vector
+    let mut cpu = test_cpu(&[0xed, 0x5e, 0xfb, 0x00, 0x00]);
+    cpu.register.i = 0x80;
+    cpu.addring.set_word(0x8010, 0x1234);
+    cpu.request_interrupt(&[0x10]);
+    cpu.next();
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.next(), 19);
+    assert_eq!(cpu.register.pc, 0x1234);
+    assert_eq!(cpu.addring.get_word(cpu.register.sp), 0x0004);
+
+    // IM 0 runs the instruction on the data bus like the 8080
+    let mut cpu = test_cpu(&[0xfb, 0x00, 0x00]);
+    cpu.request_interrupt(&[0xd7]);
+    cpu.next();
+    cpu.next();
+    assert_eq!(cpu.next(), 13);
+    assert_eq!(cpu.register.pc, 0x0010);
+}
+
+#[test]
+fn test_nmi() {
+    // EI; HALT; at 66h: LD A,I; RETN
+    let mut cpu = test_cpu(&[0xfb, 0x76]);
+    cpu.addring.set_mem(0x66, 0xed);
+    cpu.addring.set_mem(0x67, 0x57);
+    cpu.addring.set_mem(0x68, 0xed);
+    cpu.addring.set_mem(0x69, 0x45);
+    cpu.next();
+    cpu.next();
+    assert!(cpu.is_halted());
+    cpu.nmi();
+    assert_eq!(cpu.next(), 11);
+    assert_eq!(cpu.register.pc, 0x0066);
+    assert!(!cpu.interrupts_enabled());
+    cpu.next();
+    // P/V holds IFF2
+    assert!(cpu.register.flag_p);
+    cpu.next();
+    assert_eq!(cpu.register.pc, 0x0002);
+    assert!(cpu.interrupts_enabled());
+}
+
+/// Runs one of the exercisers given by path, e.g.
+/// `ZEXDOC=zexdoc.com cargo test --release --test test_z80 -- --ignored`
+fn run_exerciser(path: &str) {
+    let program = fs::read(path).unwrap();
+    let mut cpm = Cpm::with_variant(BufferTerminal::new(b""), BIOS_BASE_64K, Variant::Z80);
+    cpm.load_com(&program);
+    while !cpm.is_exited() {
+        cpm.run(100_000_000);
+    }
+    let output = String::from_utf8_lossy(&cpm.bios().terminal().output).into_owned();
+    println!("{}", output);
+    assert!(output.contains("Tests complete"));
+    assert!(!output.contains("ERROR"));
+}
+
+#[test]
+#[ignore]
+fn test_zexdoc() {
+    run_exerciser(&env::var("ZEXDOC").expect("ZEXDOC not set"));
+}
+
+#[test]
+#[ignore]
+fn test_zexall() {
+    run_exerciser(&env::var("ZEXALL").expect("ZEXALL not set"));
+}